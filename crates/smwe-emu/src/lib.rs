@@ -2,5 +2,6 @@
 
 pub mod emu;
 pub mod rom;
+pub mod symbols;
 
 pub type Cpu = wdc65816::Cpu<emu::CheckedMem>;
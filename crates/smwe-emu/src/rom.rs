@@ -2,7 +2,7 @@
 
 //! Storage for ROM files, mapper support, etc.
 
-use std::collections::HashMap;
+use crate::symbols::SymbolTable;
 
 #[derive(Debug, Copy, Clone)]
 pub enum Mapper {
@@ -56,33 +56,24 @@ impl Mapper {
 pub struct Rom {
     buf:     Vec<u8>,
     mapper:  Mapper,
-    symbols: HashMap<String, u32>,
+    symbols: SymbolTable,
 }
 
 impl Rom {
     pub fn new(buf: Vec<u8>) -> Self {
-        Self { buf, mapper: Mapper::LoRom, symbols: HashMap::new() }
+        Self { buf, mapper: Mapper::LoRom, symbols: SymbolTable::new() }
     }
 
     pub fn load_symbols(&mut self, data: &str) {
-        for i in data.lines() {
-            let i = if let Some(comment) = i.find(';') { &i[..comment] } else { i }.trim();
-            if i.is_empty() {
-                continue;
-            }
-            if let Some(v) = i.find(' ') {
-                match u32::from_str_radix(&i[..v], 16) {
-                    Ok(addr) => {
-                        self.symbols.insert(i[v + 1..].to_string(), addr);
-                    }
-                    Err(_e) => {}
-                }
-            }
-        }
+        self.symbols.load(data);
+    }
+
+    pub fn symbols(&self) -> &SymbolTable {
+        &self.symbols
     }
 
     pub fn resolve(&self, symbol: &str) -> Option<u32> {
-        self.symbols.get(symbol).copied()
+        self.symbols.resolve(symbol)
     }
 
     pub fn read(&self, addr: u32) -> Option<u8> {
@@ -109,8 +100,9 @@ impl Rom {
         &self.buf
     }
 
-    pub fn checksum(&self) -> u16 {
-        // TODO: npo2 roms
-        self.buf.iter().map(|c| *c as u16).sum()
+    /// Bytes of the internal header at $00:FFC0, e.g. to update the ROM size and checksum with `smwe-rom`.
+    pub fn internal_header_mut(&mut self) -> Option<&mut [u8]> {
+        let begin = self.mapper.map_to_file(0x00FFC0)?;
+        self.buf.get_mut(begin..begin + 0x40)
     }
 }
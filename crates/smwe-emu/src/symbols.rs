@@ -0,0 +1,154 @@
+//! Symbol tables loaded from files generated by assemblers, for naming addresses in the ROM.
+
+use std::collections::{BTreeMap, HashMap};
+
+/// Name → address and address → name mapping.
+///
+/// Supported formats are no$sns (`0000BA4D :label`), WLA DX (`00:BA4D label`) and bsnes (`00ba4d label ANY 1`), all
+/// of which may contain `;` or `#` comments and `[section]` headers. Only the label sections are read.
+///
+/// A label may be defined at multiple addresses, in which case the first definition is used for resolving the name.
+/// Labels that asar prefixes with a colon (macro and `+`/`-` labels) are stored without it, but when looking up names
+/// by address, other labels at the same address take precedence over them.
+#[derive(Clone, Debug, Default)]
+pub struct SymbolTable {
+    addresses: HashMap<String, Vec<u32>>,
+    names:     BTreeMap<u32, Vec<String>>,
+}
+
+impl SymbolTable {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn parse(data: &str) -> Self {
+        let mut table = Self::new();
+        table.load(data);
+        table
+    }
+
+    /// Adds symbols from a symbol file to the table. Lines that cannot be parsed are skipped.
+    pub fn load(&mut self, data: &str) {
+        let mut in_labels_section = true;
+        let mut anonymous_labels = Vec::new();
+        for line in data.lines() {
+            let line = if let Some(comment) = line.find([';', '#']) { &line[..comment] } else { line }.trim();
+            if line.is_empty() {
+                continue;
+            }
+            if let Some(section) = line.strip_prefix('[').and_then(|s| s.strip_suffix(']')) {
+                in_labels_section = matches!(section.to_ascii_lowercase().as_str(), "labels" | "symbol" | "symbols");
+                continue;
+            }
+            if !in_labels_section {
+                continue;
+            }
+
+            let mut tokens = line.split_whitespace();
+            let (Some(addr), Some(name)) = (tokens.next().and_then(Self::parse_address), tokens.next()) else {
+                continue;
+            };
+            match name.strip_prefix(':') {
+                Some(name) => anonymous_labels.push((name, addr)),
+                None => self.insert(name, addr),
+            }
+        }
+        for (name, addr) in anonymous_labels {
+            self.insert(name, addr);
+        }
+    }
+
+    pub fn insert(&mut self, name: impl Into<String>, addr: u32) {
+        let name = name.into();
+        let names = self.names.entry(addr).or_default();
+        if !names.contains(&name) {
+            names.push(name.clone());
+        }
+        let addresses = self.addresses.entry(name).or_default();
+        if !addresses.contains(&addr) {
+            addresses.push(addr);
+        }
+    }
+
+    /// Returns the address of the first definition of the label.
+    pub fn resolve(&self, name: &str) -> Option<u32> {
+        self.resolve_all(name).first().copied()
+    }
+
+    /// Returns addresses of all definitions of the label, in the order they were loaded.
+    pub fn resolve_all(&self, name: &str) -> &[u32] {
+        self.addresses.get(name).map(Vec::as_slice).unwrap_or_default()
+    }
+
+    /// Returns all labels defined at the address, the preferred one first.
+    pub fn names_at(&self, addr: u32) -> &[String] {
+        self.names.get(&addr).map(Vec::as_slice).unwrap_or_default()
+    }
+
+    /// Returns the closest label at or before the address in the same bank, along with the offset from it.
+    pub fn nearest(&self, addr: u32) -> Option<(&str, u32)> {
+        let bank_start = addr & 0xFF0000;
+        let (&label_addr, names) = self.names.range(bank_start..=addr).next_back()?;
+        Some((names.first()?.as_str(), addr - label_addr))
+    }
+
+    /// Formats the address as `label` or `label+offset`, or `None` if there is no label before it in its bank.
+    pub fn describe(&self, addr: u32) -> Option<String> {
+        self.nearest(addr).map(|(name, offset)| match offset {
+            0 => name.to_string(),
+            _ => format!("{name}+{offset:X}"),
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.addresses.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.addresses.is_empty()
+    }
+
+    /// Parses `BB:AAAA` or a plain hexadecimal address.
+    fn parse_address(token: &str) -> Option<u32> {
+        match token.split_once(':') {
+            Some((bank, addr)) => {
+                let bank = u32::from_str_radix(bank, 16).ok()?;
+                let addr = u32::from_str_radix(addr, 16).ok()?;
+                (bank <= 0xFF && addr <= 0xFFFF).then_some((bank << 16) | addr)
+            }
+            None => u32::from_str_radix(token, 16).ok().filter(|&addr| addr <= 0xFFFFFF),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_nosns_format() {
+        let table = SymbolTable::parse(
+            ";no$sns symbolic information file\n;generated by asar\n\n0000BA4D :macro_116_start\n0000BA4D \
+             GameMode00\n00058955 CODE_058955\n",
+        );
+        assert_eq!(table.resolve("macro_116_start"), Some(0x00BA4D));
+        assert_eq!(table.resolve(":macro_116_start"), None);
+        assert_eq!(table.names_at(0x00BA4D), ["GameMode00", "macro_116_start"]);
+        assert_eq!(table.nearest(0x05895E), Some(("CODE_058955", 9)));
+        assert_eq!(table.nearest(0x04FFFF), None);
+    }
+
+    #[test]
+    fn test_wla_and_bsnes_formats() {
+        let table = SymbolTable::parse(
+            "; wla symbolic information file\n[labels]\n00:8000 Reset\n05:8955 CODE_058955\n[definitions]\n00000001 \
+             _sizeof_x\n",
+        );
+        assert_eq!(table.resolve("CODE_058955"), Some(0x058955));
+        assert_eq!(table.resolve("_sizeof_x"), None);
+
+        let table = SymbolTable::parse("#SNES65816\n[SYMBOL]\n008000 Reset ANY 1\n018000 Reset ANY 1\n");
+        assert_eq!(table.resolve_all("Reset"), [0x008000, 0x018000]);
+        assert_eq!(table.describe(0x018010).as_deref(), Some("Reset+10"));
+    }
+}
@@ -26,6 +26,23 @@ impl Abgr1555 {
     pub const MAGENTA:     Abgr1555 = Abgr1555(0b0_11111_00000_11111);
 }
 
+impl Abgr1555 {
+    /// Opaque color from 8-bit channels, dropping their 3 lowest bits. Channels exported either as `c << 3` or with
+    /// bits repeated by [`Abgr1555::to_rgb888`] are read back unchanged.
+    pub fn from_rgb888([r, g, b]: [u8; 3]) -> Self {
+        Abgr1555(((b as u16 >> 3) << 0xA) | ((g as u16 >> 3) << 0x5) | ((r as u16 >> 3) << 0x0))
+    }
+
+    /// 8-bit channels, with the highest bits of each channel repeated in its lowest bits, so that white is `FFFFFF`.
+    pub fn to_rgb888(self) -> [u8; 3] {
+        let channel = |shift: u16| {
+            let c = ((self.0 >> shift) & SNES_BGR_CHANNEL_MAX) as u8;
+            (c << 3) | (c >> 2)
+        };
+        [channel(0x0), channel(0x5), channel(0xA)]
+    }
+}
+
 impl Default for Abgr1555 {
     fn default() -> Self {
         Abgr1555::TRANSPARENT
@@ -0,0 +1,245 @@
+use thiserror::Error;
+
+use crate::audio::wav::Wav;
+
+// -------------------------------------------------------------------------------------------------
+
+#[derive(Debug, Error)]
+pub enum BrrError {
+    #[error("Sample is empty")]
+    Empty,
+    #[error("Loop start {0} is not a multiple of {SAMPLES_PER_BRR_BLOCK}")]
+    LoopAlignment(usize),
+    #[error("Loop start {loop_start} is past the end of the sample, which has {len} frames")]
+    LoopOutOfBounds { loop_start: usize, len: usize },
+}
+
+// -------------------------------------------------------------------------------------------------
+
+pub const BRR_BLOCK_SIZE: usize = 9;
+pub const SAMPLES_PER_BRR_BLOCK: usize = 16;
+
+/// Highest range accepted by the DSP as is, ranges above it decode every nibble to either 0 or `-0x800`.
+const MAX_RANGE: u8 = 12;
+
+// -------------------------------------------------------------------------------------------------
+
+/// A sample in the BRR format used by the S-DSP, made of 9-byte blocks, each encoding 16 frames of 16-bit mono PCM.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct BrrSample {
+    pub data:        Vec<u8>,
+    /// Offset in bytes from the start of the sample to the block the DSP jumps to after playing the last one, if the
+    /// last block has the loop flag set.
+    pub loop_offset: usize,
+}
+
+/// `RRRRFFLE`
+///
+/// | Value  | Comment                                                    |
+/// |--------|------------------------------------------------------------|
+/// | `RRRR` | Range: shift applied to the nibbles                        |
+/// | `FF`   | Filter: how much the previous frames contribute to the new |
+/// | `L`    | Loop: if set in the last block, the sample loops           |
+/// | `E`    | End: set in the last block of the sample                   |
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct BrrHeader(pub u8);
+
+// -------------------------------------------------------------------------------------------------
+
+impl BrrSample {
+    /// Reads blocks until the one with the end flag. Returns `None` if the input ends before it.
+    pub fn read_from(input: &[u8], loop_offset: usize) -> Option<Self> {
+        let block_count = input.chunks_exact(BRR_BLOCK_SIZE).position(|block| BrrHeader(block[0]).end())? + 1;
+        Some(Self { data: input[..block_count * BRR_BLOCK_SIZE].to_vec(), loop_offset })
+    }
+
+    /// Encodes 16-bit mono PCM, padding it with silence at the end to a multiple of [`SAMPLES_PER_BRR_BLOCK`] frames.
+    /// The loop start, if any, must be a multiple of [`SAMPLES_PER_BRR_BLOCK`] as well.
+    pub fn from_pcm(pcm: &[i16], loop_start: Option<usize>) -> Result<Self, BrrError> {
+        if pcm.is_empty() {
+            return Err(BrrError::Empty);
+        }
+        if let Some(loop_start) = loop_start {
+            if loop_start % SAMPLES_PER_BRR_BLOCK != 0 {
+                return Err(BrrError::LoopAlignment(loop_start));
+            }
+            if loop_start >= pcm.len() {
+                return Err(BrrError::LoopOutOfBounds { loop_start, len: pcm.len() });
+            }
+        }
+
+        let loop_block = loop_start.map(|loop_start| loop_start / SAMPLES_PER_BRR_BLOCK);
+        let block_count = (pcm.len() + SAMPLES_PER_BRR_BLOCK - 1) / SAMPLES_PER_BRR_BLOCK;
+        let mut data = Vec::with_capacity(block_count * BRR_BLOCK_SIZE);
+        let mut history = (0, 0);
+        for (index, frames) in pcm.chunks(SAMPLES_PER_BRR_BLOCK).enumerate() {
+            let mut frames = frames.to_vec();
+            frames.resize(SAMPLES_PER_BRR_BLOCK, 0);
+
+            // The first block and the loop start are reached with an unknown history, so they can't use filters.
+            let filters = if index == 0 || Some(index) == loop_block { 0..=0 } else { 0..=3 };
+            let (mut block, new_history) = filters
+                .flat_map(|filter| (0..=MAX_RANGE).map(move |range| (range, filter)))
+                .map(|(range, filter)| encode_block(&frames, range, filter, history))
+                .min_by_key(|(_, _, error)| *error)
+                .map(|(block, history, _)| (block, history))
+                .unwrap();
+
+            if index == block_count - 1 {
+                block[0] |= if loop_block.is_some() { 0b11 } else { 0b01 };
+            }
+            data.extend(block);
+            history = new_history;
+        }
+
+        let loop_offset = loop_block.map_or(0, |block| block * BRR_BLOCK_SIZE);
+        Ok(Self { data, loop_offset })
+    }
+
+    /// Encodes the frames of a WAV file, looping from its loop start if it has one. The audio is not resampled.
+    pub fn from_wav(wav: &Wav) -> Result<Self, BrrError> {
+        Self::from_pcm(&wav.frames, wav.loop_start)
+    }
+
+    pub fn block_count(&self) -> usize {
+        self.data.len() / BRR_BLOCK_SIZE
+    }
+
+    /// Number of PCM frames.
+    pub fn len(&self) -> usize {
+        self.block_count() * SAMPLES_PER_BRR_BLOCK
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.len() < BRR_BLOCK_SIZE
+    }
+
+    pub fn loops(&self) -> bool {
+        self.data.chunks_exact(BRR_BLOCK_SIZE).last().is_some_and(|block| BrrHeader(block[0]).looping())
+    }
+
+    /// Frame the sample loops back to, or `None` if it doesn't loop or the loop offset is not at a block inside it.
+    pub fn loop_start(&self) -> Option<usize> {
+        (self.loops() && self.loop_offset % BRR_BLOCK_SIZE == 0 && self.loop_offset < self.data.len())
+            .then(|| self.loop_offset / BRR_BLOCK_SIZE * SAMPLES_PER_BRR_BLOCK)
+    }
+
+    /// Decodes the sample to 16-bit mono PCM, playing it once without looping.
+    pub fn to_pcm(&self) -> Vec<i16> {
+        let mut pcm = Vec::with_capacity(self.len());
+        let mut history = (0, 0);
+        for block in self.data.chunks_exact(BRR_BLOCK_SIZE) {
+            let header = BrrHeader(block[0]);
+            for nibble in block[1..].iter().flat_map(|&byte| [(byte as i8) >> 4, ((byte << 4) as i8) >> 4]) {
+                let frame = decode_frame(nibble, header.range(), header.filter(), history);
+                history = (frame, history.0);
+                pcm.push(frame);
+            }
+        }
+        pcm
+    }
+
+    /// Decodes the sample into a WAV file with the given sample rate, keeping its loop point.
+    pub fn to_wav(&self, sample_rate: u32) -> Wav {
+        Wav { sample_rate, frames: self.to_pcm(), loop_start: self.loop_start() }
+    }
+}
+
+impl BrrHeader {
+    pub fn range(self) -> u8 {
+        self.0 >> 4
+    }
+
+    pub fn filter(self) -> u8 {
+        (self.0 >> 2) & 0b11
+    }
+
+    pub fn looping(self) -> bool {
+        self.0 & 0b10 != 0
+    }
+
+    pub fn end(self) -> bool {
+        self.0 & 0b01 != 0
+    }
+}
+
+// -------------------------------------------------------------------------------------------------
+
+/// Decodes a single nibble the way the S-DSP does, given the two previously decoded frames.
+fn decode_frame(nibble: i8, range: u8, filter: u8, (p1, p2): (i16, i16)) -> i16 {
+    let mut s = ((nibble as i32) << range) >> 1;
+    if range > MAX_RANGE {
+        s = if nibble < 0 { -0x800 } else { 0 };
+    }
+    let (p1, p2) = (p1 as i32, (p2 as i32) >> 1);
+    match filter {
+        0 => {}
+        1 => s += (p1 >> 1) + ((-p1) >> 5),
+        2 => s += p1 - p2 + (p2 >> 4) + ((p1 * -3) >> 6),
+        _ => s += p1 - p2 + ((p1 * -13) >> 7) + ((p2 * 3) >> 4),
+    }
+    (s.clamp(i16::MIN as i32, i16::MAX as i32) as i16).wrapping_mul(2)
+}
+
+/// Encodes 16 frames with the given range and filter, picking the closest nibble for each frame. Returns the block,
+/// the history after decoding it and the squared error.
+fn encode_block(
+    frames: &[i16], range: u8, filter: u8, mut history: (i16, i16),
+) -> ([u8; BRR_BLOCK_SIZE], (i16, i16), u64) {
+    let mut block = [0; BRR_BLOCK_SIZE];
+    block[0] = (range << 4) | (filter << 2);
+    let mut error = 0;
+    for (i, &frame) in frames.iter().enumerate() {
+        let (nibble, decoded) = (-8..=7)
+            .map(|nibble| (nibble, decode_frame(nibble, range, filter, history)))
+            .min_by_key(|(_, decoded)| (*decoded as i32 - frame as i32).unsigned_abs())
+            .unwrap();
+        error += ((decoded as i64 - frame as i64).pow(2)) as u64;
+        history = (decoded, history.0);
+        block[1 + i / 2] |= ((nibble as u8) & 0xF) << if i % 2 == 0 { 4 } else { 0 };
+    }
+    (block, history, error)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode() {
+        // Range 12 with filter 0, then filter 1 continuing from the last frame.
+        let data = [0xC0, 0x12, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x0F, 0x17, 0, 0, 0, 0, 0, 0, 0, 0];
+        let sample = BrrSample::read_from(&[&data[..], &[0xFF; 4]].concat(), 9).unwrap();
+        assert_eq!(sample.block_count(), 2);
+        assert!(sample.loops());
+        assert_eq!(sample.loop_start(), Some(16));
+
+        let pcm = sample.to_pcm();
+        assert_eq!(&pcm[..3], &[0x1000, 0x2000, 0]);
+        assert_eq!(pcm[15], -0x1000);
+        assert_eq!(pcm[16], -0xF00);
+        assert_eq!(BrrSample::read_from(&data[..9], 0), None);
+    }
+
+    #[test]
+    fn test_encode() {
+        let pcm = (0..100).map(|i| ((i as f64 / 8.0).sin() * 12000.0) as i16).collect::<Vec<_>>();
+        let sample = BrrSample::from_pcm(&pcm, Some(32)).unwrap();
+        assert_eq!(sample.block_count(), 7);
+        assert_eq!(sample.loop_start(), Some(32));
+        assert_eq!(BrrHeader(sample.data[0]).filter(), 0);
+        assert_eq!(BrrHeader(sample.data[2 * BRR_BLOCK_SIZE]).filter(), 0);
+
+        let decoded = sample.to_pcm();
+        assert_eq!(decoded.len(), 112);
+        let errors = pcm.iter().zip(&decoded).map(|(&a, &b)| (a as i32 - b as i32).abs()).collect::<Vec<_>>();
+        // Blocks without a filter can only get as close as the step of their range allows.
+        assert!(errors.iter().all(|&error| error <= 1024));
+        assert!(errors[16..32].iter().chain(&errors[48..]).all(|&error| error < 150));
+
+        assert!(matches!(BrrSample::from_pcm(&pcm, Some(20)), Err(BrrError::LoopAlignment(20))));
+        assert!(matches!(BrrSample::from_pcm(&pcm, Some(112)), Err(BrrError::LoopOutOfBounds { .. })));
+        assert!(matches!(BrrSample::from_pcm(&[], None), Err(BrrError::Empty)));
+        assert!(!BrrSample::from_pcm(&pcm, None).unwrap().loops());
+    }
+}
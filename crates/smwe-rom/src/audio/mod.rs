@@ -0,0 +1,148 @@
+//! Data uploaded to the SPC700: the sound engine, the BRR samples played by the DSP and the N-SPC songs.
+//!
+//! Each upload is a chain of blocks copied to ARAM by the upload routine. The samples upload holds the sample
+//! directory together with the samples, and each music bank is uploaded over the same area of ARAM, starting with the
+//! table of its songs.
+
+use thiserror::Error;
+
+pub use self::{brr::BrrSample, music::Song, samples::SampleBank, spc::SpcUpload, wav::Wav};
+use crate::{
+    audio::{brr::BrrError, wav::WavError},
+    disassembler::{binary_block::DataKind, RomDisassembly},
+    snes_utils::{addr::AddrSnes, rom::RomError},
+};
+
+pub mod brr;
+pub mod music;
+pub mod samples;
+pub mod spc;
+pub mod wav;
+
+// -------------------------------------------------------------------------------------------------
+
+#[derive(Debug, Error)]
+pub enum AudioParseError {
+    #[error("Reading SPC upload at {0}:\n- {1}")]
+    UploadRead(AddrSnes, RomError),
+    #[error("SPC upload at {0} is not terminated")]
+    UploadTerminator(AddrSnes),
+    #[error("No block of the samples upload is copied to the sample directory at ARAM ${0:04X}")]
+    SampleDirectory(u16),
+}
+
+#[derive(Debug, Error)]
+pub enum AudioError {
+    #[error("SPC upload takes {needed} bytes, but only {capacity} are available in place")]
+    Overflow { needed: usize, capacity: usize },
+    #[error("SPC block of {0:#X} bytes is too large")]
+    BlockSize(usize),
+    #[error("{1:#X} bytes copied to ARAM ${0:04X} don't fit in ARAM")]
+    AramOverflow(u16, usize),
+    #[error("Sample directory takes {needed} bytes, but only {capacity} are reserved for it")]
+    DirectoryFull { needed: usize, capacity: usize },
+    #[error("Encoding sample:\n- {0}")]
+    Brr(BrrError),
+    #[error("Reading WAV file:\n- {0}")]
+    Wav(WavError),
+    #[error("Writing SPC upload:\n- {0}")]
+    Write(RomError),
+}
+
+// -------------------------------------------------------------------------------------------------
+
+/// Sound engine, along with sound effects and the music played on the title screen and during cutscenes.
+pub const SPC_ENGINE_UPLOAD: AddrSnes = AddrSnes(0x0E8000);
+/// Sample directory and BRR samples.
+pub const SAMPLES_UPLOAD: AddrSnes = AddrSnes(0x0F8000);
+pub const OVERWORLD_MUSIC_UPLOAD: AddrSnes = AddrSnes(0x0E98B1);
+pub const LEVEL_MUSIC_UPLOAD: AddrSnes = AddrSnes(0x0EAED6);
+pub const CREDITS_MUSIC_UPLOAD: AddrSnes = AddrSnes(0x03E400);
+
+/// ARAM address of the sample directory, as set in the DSP's `DIR` register by the sound engine.
+pub const SAMPLE_DIRECTORY_ARAM: u16 = 0x8000;
+/// Rate at which the DSP plays samples at their original pitch.
+pub const DSP_SAMPLE_RATE: u32 = 32000;
+
+// -------------------------------------------------------------------------------------------------
+
+#[derive(Debug, Clone)]
+pub struct Audio {
+    pub engine:         SpcUpload,
+    pub samples_upload: SpcUpload,
+    /// Samples parsed from the samples upload. Changes to them are applied to the upload when writing.
+    pub samples:        SampleBank,
+    pub music_banks:    Vec<MusicBank>,
+}
+
+#[derive(Debug, Clone)]
+pub struct MusicBank {
+    pub kind:   MusicBankKind,
+    pub upload: SpcUpload,
+    pub songs:  Vec<Song>,
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum MusicBankKind {
+    Overworld,
+    Level,
+    Credits,
+}
+
+// -------------------------------------------------------------------------------------------------
+
+impl Audio {
+    pub fn parse(disasm: &mut RomDisassembly) -> Result<Self, AudioParseError> {
+        let engine = SpcUpload::parse(disasm, SPC_ENGINE_UPLOAD, DataKind::Music)?;
+        let samples_upload = SpcUpload::parse(disasm, SAMPLES_UPLOAD, DataKind::SoundSample)?;
+        let (_, samples) = SampleBank::read_from_upload(&samples_upload, SAMPLE_DIRECTORY_ARAM)
+            .ok_or(AudioParseError::SampleDirectory(SAMPLE_DIRECTORY_ARAM))?;
+        let music_banks = MusicBankKind::ALL
+            .into_iter()
+            .map(|kind| {
+                let upload = SpcUpload::parse(disasm, kind.upload_addr(), DataKind::Music)?;
+                let songs = Song::split_upload(&upload);
+                Ok(MusicBank { kind, upload, songs })
+            })
+            .collect::<Result<_, AudioParseError>>()?;
+        Ok(Self { engine, samples_upload, samples, music_banks })
+    }
+
+    /// Replaces a sample with the audio from a WAV file, returning the previous one.
+    pub fn import_sample(&mut self, index: usize, wav: &[u8]) -> Result<Option<BrrSample>, AudioError> {
+        let wav = Wav::from_bytes(wav).map_err(AudioError::Wav)?;
+        let sample = BrrSample::from_wav(&wav).map_err(AudioError::Brr)?;
+        Ok(self.samples.samples.get_mut(index).map(|old| std::mem::replace(old, sample)))
+    }
+
+    /// Returns the samples upload with the block holding the sample directory replaced with the current samples, or
+    /// with a block of them added if the directory has been moved outside the original blocks.
+    pub fn updated_samples_upload(&self) -> Result<SpcUpload, AudioError> {
+        let mut upload = self.samples_upload.clone();
+        let block = self.samples.to_block()?;
+        match upload.block_at(self.samples.aram_addr) {
+            Some((block_index, _)) => upload.blocks[block_index] = block,
+            None => upload.blocks.push(block),
+        }
+        Ok(upload)
+    }
+
+    /// Writes the samples back in place of the original samples upload. Nothing is written if they don't fit.
+    pub fn write_to_rom(&self, disasm: &mut RomDisassembly) -> Result<(), AudioError> {
+        let upload = self.updated_samples_upload()?;
+        let bytes = upload.to_bytes()?;
+        disasm.rom.write_lorom(upload.addr, &bytes).map_err(AudioError::Write)
+    }
+}
+
+impl MusicBankKind {
+    pub const ALL: [Self; 3] = [Self::Overworld, Self::Level, Self::Credits];
+
+    pub fn upload_addr(self) -> AddrSnes {
+        match self {
+            Self::Overworld => OVERWORLD_MUSIC_UPLOAD,
+            Self::Level => LEVEL_MUSIC_UPLOAD,
+            Self::Credits => CREDITS_MUSIC_UPLOAD,
+        }
+    }
+}
@@ -0,0 +1,106 @@
+use crate::audio::spc::SpcUpload;
+
+// -------------------------------------------------------------------------------------------------
+
+/// An N-SPC song sequence, as it's laid out in ARAM.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Song {
+    /// Index of the song in the song table of its bank, starting from 1 like the values written to the music port.
+    pub number:    usize,
+    pub aram_addr: u16,
+    /// Phrase list, patterns and tracks of the song, up to the start of the next song or the end of the block.
+    pub data:      Vec<u8>,
+}
+
+// -------------------------------------------------------------------------------------------------
+
+impl Song {
+    /// Splits the first block of the upload into songs. The block starts with a table of song pointers, which ends
+    /// where the first song begins. Empty entries and entries pointing outside the block are skipped.
+    pub fn split_upload(upload: &SpcUpload) -> Vec<Self> {
+        let Some(block) = upload.blocks.first() else {
+            return Vec::new();
+        };
+        let block_end = block.aram_addr as usize + block.data.len();
+
+        let mut pointers = Vec::new();
+        let mut table_end = block_end;
+        for (index, entry) in block.data.chunks_exact(2).enumerate() {
+            let table_size = (index + 1) * 2;
+            if block.aram_addr as usize + table_size > table_end {
+                break;
+            }
+            let aram_addr = u16::from_le_bytes([entry[0], entry[1]]);
+            if (block.aram_addr as usize + table_size..block_end).contains(&(aram_addr as usize)) {
+                table_end = table_end.min(aram_addr as usize);
+                pointers.push((index + 1, aram_addr));
+            }
+        }
+
+        let mut starts: Vec<_> = pointers.iter().map(|&(_, aram_addr)| aram_addr as usize).collect();
+        starts.sort_unstable();
+        starts.dedup();
+        pointers
+            .into_iter()
+            .map(|(number, aram_addr)| {
+                let start = aram_addr as usize;
+                let end = starts.iter().copied().find(|&next| next > start).unwrap_or(block_end);
+                let offset = start - block.aram_addr as usize;
+                let data = block.data[offset..offset + end - start].to_vec();
+                Self { number, aram_addr, data }
+            })
+            .collect()
+    }
+
+    /// Pointers to the patterns played in order, stopping at the end of the phrase list. Loop and jump commands,
+    /// which are followed by the address to jump to, are skipped.
+    pub fn pattern_pointers(&self) -> Vec<u16> {
+        let mut pointers = Vec::new();
+        let mut words = self.data.chunks_exact(2).map(|w| u16::from_le_bytes([w[0], w[1]]));
+        while let Some(word) = words.next() {
+            match word {
+                0 => break,
+                1..=0xFF => {
+                    words.next();
+                }
+                _ => pointers.push(word),
+            }
+        }
+        pointers
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{audio::spc::SpcBlock, snes_utils::addr::AddrSnes};
+
+    #[test]
+    fn test_split_upload() {
+        #[rustfmt::skip]
+        let data = vec![
+            // Song table: two songs, an empty entry and a pointer past the block.
+            0x6A, 0x13, 0x00, 0x00, 0x68, 0x13, 0x00, 0x20,
+            // Song 3: a phrase list cut short by the start of song 1.
+            0x70, 0x13,
+            // Song 1: a pattern, a jump back to the start and the terminator.
+            0x72, 0x13, 0xFF, 0x00, 0x6A, 0x13, 0x00, 0x00,
+        ];
+        let upload = SpcUpload {
+            blocks:   vec![SpcBlock { aram_addr: 0x1360, data }],
+            entry:    0x0500,
+            addr:     AddrSnes(0x0EAED6),
+            capacity: 0,
+        };
+        let songs = Song::split_upload(&upload);
+        assert_eq!(songs.len(), 2);
+        assert_eq!(songs[0], Song {
+            number:    1,
+            aram_addr: 0x136A,
+            data:      vec![0x72, 0x13, 0xFF, 0x00, 0x6A, 0x13, 0, 0],
+        });
+        assert_eq!(songs[1], Song { number: 3, aram_addr: 0x1368, data: vec![0x70, 0x13] });
+        assert_eq!(songs[0].pattern_pointers(), [0x1372]);
+        assert_eq!(songs[1].pattern_pointers(), [0x1370]);
+    }
+}
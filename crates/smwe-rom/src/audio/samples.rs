@@ -0,0 +1,139 @@
+use crate::audio::{
+    brr::BrrSample,
+    spc::{SpcBlock, SpcUpload},
+    AudioError,
+};
+
+// -------------------------------------------------------------------------------------------------
+
+pub const SAMPLE_DIRECTORY_ENTRY_SIZE: usize = 4;
+
+// -------------------------------------------------------------------------------------------------
+
+/// The sample directory read by the DSP, along with the samples it points to, all stored in a single upload block.
+///
+/// Each directory entry is `SSSSSSSS SSSSSSSS LLLLLLLL LLLLLLLL`: ARAM addresses of the start of the sample and of
+/// the block it loops back to, both in LE.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct SampleBank {
+    pub aram_addr: u16,
+    /// Original bytes of the directory, after which sample data begins. Entries of the samples are rewritten when
+    /// laying out the block, the rest of them are kept as they were.
+    pub directory: Vec<u8>,
+    pub samples:   Vec<BrrSample>,
+}
+
+// -------------------------------------------------------------------------------------------------
+
+impl SampleBank {
+    /// Reads the directory from the block of the upload containing it, returning the index of that block. The
+    /// directory ends at the first entry pointing outside the block or at data that isn't a complete sample, or where
+    /// the first sample begins.
+    pub fn read_from_upload(upload: &SpcUpload, aram_addr: u16) -> Option<(usize, Self)> {
+        let (block_index, directory_offset) = upload.block_at(aram_addr)?;
+        let data = &upload.blocks[block_index].data;
+        let block_addr = upload.blocks[block_index].aram_addr as usize;
+
+        let mut samples = Vec::new();
+        let mut data_start = data.len();
+        let mut offset = directory_offset;
+        while offset + SAMPLE_DIRECTORY_ENTRY_SIZE <= data_start {
+            let entry = &data[offset..offset + SAMPLE_DIRECTORY_ENTRY_SIZE];
+            let start = u16::from_le_bytes([entry[0], entry[1]]);
+            let loop_addr = u16::from_le_bytes([entry[2], entry[3]]);
+            offset += SAMPLE_DIRECTORY_ENTRY_SIZE;
+
+            let Some(start_offset) = (start as usize).checked_sub(block_addr).filter(|&start| start >= offset) else {
+                break;
+            };
+            let Some(sample) = data
+                .get(start_offset..)
+                .and_then(|sample| BrrSample::read_from(sample, loop_addr.wrapping_sub(start) as usize))
+            else {
+                break;
+            };
+            data_start = data_start.min(start_offset);
+            samples.push(sample);
+        }
+
+        let directory = data[directory_offset..data_start].to_vec();
+        Some((block_index, Self { aram_addr, directory, samples }))
+    }
+
+    /// Lays out the directory followed by the samples in a single block. Samples which are equal to the end of an
+    /// already placed one share its data, as they originally might have.
+    pub fn to_block(&self) -> Result<SpcBlock, AudioError> {
+        let directory_needed = self.samples.len() * SAMPLE_DIRECTORY_ENTRY_SIZE;
+        if directory_needed > self.directory.len() {
+            return Err(AudioError::DirectoryFull { needed: directory_needed, capacity: self.directory.len() });
+        }
+
+        let mut directory = Vec::with_capacity(self.directory.len());
+        let mut sample_data: Vec<u8> = Vec::new();
+        let mut placed: Vec<(usize, &[u8])> = Vec::new();
+        for sample in self.samples.iter() {
+            let offset = match placed.iter().find(|(_, data)| data.ends_with(&sample.data)) {
+                Some((offset, data)) => offset + data.len() - sample.data.len(),
+                None => {
+                    let offset = sample_data.len();
+                    sample_data.extend_from_slice(&sample.data);
+                    placed.push((offset, sample.data.as_slice()));
+                    offset
+                }
+            };
+            let start = (self.aram_addr as usize + self.directory.len() + offset) as u16;
+            directory.extend(start.to_le_bytes());
+            directory.extend(start.wrapping_add(sample.loop_offset as u16).to_le_bytes());
+        }
+        directory.extend_from_slice(&self.directory[directory_needed..]);
+
+        let data = [directory, sample_data].concat();
+        if self.aram_addr as usize + data.len() > 0x10000 {
+            return Err(AudioError::AramOverflow(self.aram_addr, data.len()));
+        }
+        Ok(SpcBlock { aram_addr: self.aram_addr, data })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::snes_utils::addr::AddrSnes;
+
+    #[test]
+    fn test_sample_bank_round_trip() {
+        let first = [[0xB0, 1, 2, 3, 4, 5, 6, 7, 8], [0xB3, 8, 7, 6, 5, 4, 3, 2, 1]].concat();
+        let second = [0xC1, 0, 0, 0, 0, 0, 0, 0, 0];
+        #[rustfmt::skip]
+        let directory = [
+            0x10, 0x80, 0x19, 0x80,
+            0x22, 0x80, 0x22, 0x80,
+            0x19, 0x80, 0x19, 0x80,
+            // Garbage after the last valid entry, points into the directory.
+            0x04, 0x80, 0x00, 0x00,
+        ];
+        let data = [&directory[..], &first, &second].concat();
+        let upload = SpcUpload {
+            blocks:   vec![SpcBlock { aram_addr: 0x0500, data: vec![0; 4] }, SpcBlock { aram_addr: 0x8000, data }],
+            entry:    0x0500,
+            addr:     AddrSnes(0x0F8000),
+            capacity: 0,
+        };
+
+        let (block_index, mut bank) = SampleBank::read_from_upload(&upload, 0x8000).unwrap();
+        assert_eq!(block_index, 1);
+        assert_eq!(bank.directory.len(), 0x10);
+        assert_eq!(bank.samples.len(), 3);
+        assert_eq!(bank.samples[0].loop_start(), Some(16));
+        assert_eq!(bank.samples[2].data, &first[9..]);
+        assert_eq!(bank.to_block().unwrap(), upload.blocks[1]);
+
+        bank.samples[1] = BrrSample { data: first.clone(), loop_offset: 0 };
+        let block = bank.to_block().unwrap();
+        assert_eq!(block.data.len(), 0x10 + 18);
+        assert_eq!(&block.data[4..8], &[0x10, 0x80, 0x10, 0x80]);
+
+        bank.samples.extend([bank.samples[2].clone(), bank.samples[2].clone()]);
+        assert!(matches!(bank.to_block(), Err(AudioError::DirectoryFull { needed: 20, capacity: 16 })));
+    }
+}
@@ -0,0 +1,133 @@
+use crate::{
+    audio::{AudioError, AudioParseError},
+    disassembler::{binary_block::DataKind, RomDisassembly},
+    snes_utils::{addr::AddrSnes, rom_slice::SnesSlice},
+};
+
+// -------------------------------------------------------------------------------------------------
+
+pub const SPC_BLOCK_HEADER_SIZE: usize = 4;
+
+// -------------------------------------------------------------------------------------------------
+
+/// Data sent to the SPC700 by the upload routine: a chain of blocks, each one copied to its own address in ARAM,
+/// terminated by a header with size 0, which is followed by the address at which the SPC700 continues execution.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct SpcUpload {
+    pub blocks:   Vec<SpcBlock>,
+    pub entry:    u16,
+    pub addr:     AddrSnes,
+    /// Size of the original upload in bytes, including its terminator.
+    pub capacity: usize,
+}
+
+/// `SSSSSSSS SSSSSSSS AAAAAAAA AAAAAAAA`
+///
+/// | Value               | Comment                         |
+/// |---------------------|---------------------------------|
+/// | `SSSSSSSS SSSSSSSS` | Size of data in bytes, in LE    |
+/// | `AAAAAAAA AAAAAAAA` | Destination in ARAM, in LE      |
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct SpcBlock {
+    pub aram_addr: u16,
+    pub data:      Vec<u8>,
+}
+
+// -------------------------------------------------------------------------------------------------
+
+impl SpcUpload {
+    pub(crate) fn parse(disasm: &mut RomDisassembly, addr: AddrSnes, kind: DataKind) -> Result<Self, AudioParseError> {
+        disasm.parse_and_mark_data(
+            addr,
+            kind,
+            |e| AudioParseError::UploadRead(addr, e),
+            |rom_view| {
+                let bytes = rom_view.as_bytes()?;
+                let upload = Self::read_from(bytes, addr).ok_or(AudioParseError::UploadTerminator(addr))?;
+                let size = upload.capacity;
+                Ok((upload, size))
+            },
+        )
+    }
+
+    /// Returns `None` if the input ends before the terminator.
+    pub fn read_from(input: &[u8], addr: AddrSnes) -> Option<Self> {
+        let mut blocks = Vec::new();
+        let mut in_it = input;
+        loop {
+            let header = in_it.get(..SPC_BLOCK_HEADER_SIZE)?;
+            let size = u16::from_le_bytes([header[0], header[1]]) as usize;
+            let aram_addr = u16::from_le_bytes([header[2], header[3]]);
+            in_it = &in_it[SPC_BLOCK_HEADER_SIZE..];
+            if size == 0 {
+                let capacity = input.len() - in_it.len();
+                return Some(Self { blocks, entry: aram_addr, addr, capacity });
+            }
+            let data = in_it.get(..size)?.to_vec();
+            in_it = &in_it[size..];
+            blocks.push(SpcBlock { aram_addr, data });
+        }
+    }
+
+    /// Encodes the blocks followed by the terminator, failing if the upload is larger than the original one.
+    pub fn to_bytes(&self) -> Result<Vec<u8>, AudioError> {
+        let mut bytes = Vec::with_capacity(self.capacity);
+        for block in self.blocks.iter() {
+            let size = u16::try_from(block.data.len()).map_err(|_| AudioError::BlockSize(block.data.len()))?;
+            bytes.extend(size.to_le_bytes());
+            bytes.extend(block.aram_addr.to_le_bytes());
+            bytes.extend_from_slice(&block.data);
+        }
+        bytes.extend([0, 0]);
+        bytes.extend(self.entry.to_le_bytes());
+        if bytes.len() > self.capacity {
+            return Err(AudioError::Overflow { needed: bytes.len(), capacity: self.capacity });
+        }
+        Ok(bytes)
+    }
+
+    /// Returns the block that gets copied over the given ARAM address, along with the offset of that address in it.
+    pub fn block_at(&self, aram_addr: u16) -> Option<(usize, usize)> {
+        self.blocks.iter().enumerate().find_map(|(index, block)| {
+            let offset = aram_addr.checked_sub(block.aram_addr)? as usize;
+            (offset < block.data.len()).then_some((index, offset))
+        })
+    }
+
+    pub fn slice(&self) -> SnesSlice {
+        SnesSlice::new(self.addr, self.capacity)
+    }
+}
+
+impl SpcBlock {
+    /// Returns the data from the given ARAM address to the end of the block.
+    pub fn data_from(&self, aram_addr: u16) -> Option<&[u8]> {
+        let offset = aram_addr.checked_sub(self.aram_addr)? as usize;
+        self.data.get(offset..).filter(|data| !data.is_empty())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_upload_round_trip() {
+        let first = [0x03, 0x00, 0x00, 0x05, 0xAA, 0xBB, 0xCC];
+        let second = [0x01, 0x00, 0x60, 0x13, 0xDD];
+        let bytes = [&first[..], &second, &[0x00, 0x00, 0x00, 0x05]].concat();
+        let upload = SpcUpload::read_from(&[&bytes[..], &[0xFF; 3]].concat(), AddrSnes(0x0E8000)).unwrap();
+        assert_eq!(upload.capacity, bytes.len());
+        assert_eq!(upload.entry, 0x0500);
+        assert_eq!(upload.blocks[1], SpcBlock { aram_addr: 0x1360, data: vec![0xDD] });
+        assert_eq!(upload.block_at(0x0502), Some((0, 2)));
+        assert_eq!(upload.block_at(0x0503), None);
+        assert_eq!(upload.blocks[0].data_from(0x0501), Some(&[0xBB, 0xCC][..]));
+        assert_eq!(upload.to_bytes().unwrap(), bytes);
+
+        let mut larger = upload.clone();
+        larger.blocks[1].data.push(0xEE);
+        assert!(matches!(larger.to_bytes(), Err(AudioError::Overflow { needed: 17, capacity: 16 })));
+        assert_eq!(SpcUpload::read_from(&bytes[..bytes.len() - 4], AddrSnes(0x0E8000)), None);
+    }
+}
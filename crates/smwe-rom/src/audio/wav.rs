@@ -0,0 +1,161 @@
+//! Minimal reader and writer of WAV files, covering uncompressed PCM and the loop points of the `smpl` chunk.
+
+use thiserror::Error;
+
+// -------------------------------------------------------------------------------------------------
+
+#[derive(Debug, Error)]
+pub enum WavError {
+    #[error("Not a RIFF WAVE file")]
+    NotWave,
+    #[error("Chunk '{0}' is truncated")]
+    TruncatedChunk(String),
+    #[error("Missing '{0}' chunk")]
+    MissingChunk(&'static str),
+    #[error("Unsupported format: only uncompressed 8-bit and 16-bit PCM is supported")]
+    UnsupportedFormat,
+}
+
+// -------------------------------------------------------------------------------------------------
+
+const FORMAT_PCM: u16 = 1;
+
+// -------------------------------------------------------------------------------------------------
+
+/// 16-bit PCM audio, mixed down to mono.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Wav {
+    pub sample_rate: u32,
+    pub frames:      Vec<i16>,
+    /// Frame at which the first loop from the `smpl` chunk starts. The loop is assumed to last until the end.
+    pub loop_start:  Option<usize>,
+}
+
+// -------------------------------------------------------------------------------------------------
+
+impl Wav {
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, WavError> {
+        if bytes.len() < 12 || &bytes[0..4] != b"RIFF" || &bytes[8..12] != b"WAVE" {
+            return Err(WavError::NotWave);
+        }
+
+        let mut format = None;
+        let mut data = None;
+        let mut loop_start = None;
+        let mut in_it = &bytes[12..];
+        while in_it.len() >= 8 {
+            let id = &in_it[0..4];
+            let size = u32::from_le_bytes([in_it[4], in_it[5], in_it[6], in_it[7]]) as usize;
+            let chunk = in_it
+                .get(8..8 + size)
+                .ok_or_else(|| WavError::TruncatedChunk(String::from_utf8_lossy(id).into_owned()))?;
+            match id {
+                b"fmt " if size >= 16 => format = Some(chunk),
+                b"data" => data = Some(chunk),
+                // The loop count is at 28 and the start of the first loop at 36 + 8.
+                b"smpl" if size >= 36 + 24 && read_u32(chunk, 28) > 0 => {
+                    loop_start = Some(read_u32(chunk, 44) as usize)
+                }
+                _ => {}
+            }
+            // Chunks are padded to an even size.
+            in_it = in_it.get(8 + size + size % 2..).unwrap_or_default();
+        }
+
+        let format = format.ok_or(WavError::MissingChunk("fmt "))?;
+        let data = data.ok_or(WavError::MissingChunk("data"))?;
+        let audio_format = u16::from_le_bytes([format[0], format[1]]);
+        let channels = u16::from_le_bytes([format[2], format[3]]) as usize;
+        let sample_rate = read_u32(format, 4);
+        let bits_per_sample = u16::from_le_bytes([format[14], format[15]]);
+        if audio_format != FORMAT_PCM || channels == 0 {
+            return Err(WavError::UnsupportedFormat);
+        }
+
+        let samples: Vec<i32> = match bits_per_sample {
+            8 => data.iter().map(|&s| ((s as i32) - 0x80) << 8).collect(),
+            16 => data.chunks_exact(2).map(|s| i16::from_le_bytes([s[0], s[1]]) as i32).collect(),
+            _ => return Err(WavError::UnsupportedFormat),
+        };
+        let frames = samples.chunks_exact(channels).map(|frame| (frame.iter().sum::<i32>() / channels as i32) as i16);
+
+        Ok(Self { sample_rate, frames: frames.collect(), loop_start })
+    }
+
+    /// Encodes the audio as a mono 16-bit PCM WAV file, with a `smpl` chunk if it loops.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let data_size = self.frames.len() * 2;
+        let mut chunks = Vec::with_capacity(4 + 24 + 8 + data_size + 68);
+        chunks.extend(b"WAVE");
+
+        chunks.extend(b"fmt ");
+        chunks.extend(16u32.to_le_bytes());
+        chunks.extend(FORMAT_PCM.to_le_bytes());
+        chunks.extend(1u16.to_le_bytes());
+        chunks.extend(self.sample_rate.to_le_bytes());
+        chunks.extend((self.sample_rate * 2).to_le_bytes());
+        chunks.extend(2u16.to_le_bytes());
+        chunks.extend(16u16.to_le_bytes());
+
+        chunks.extend(b"data");
+        chunks.extend((data_size as u32).to_le_bytes());
+        chunks.extend(self.frames.iter().flat_map(|frame| frame.to_le_bytes()));
+
+        if let Some(loop_start) = self.loop_start {
+            let sample_period = 1_000_000_000 / self.sample_rate.max(1);
+            let loop_end = self.frames.len().saturating_sub(1) as u32;
+            chunks.extend(b"smpl");
+            chunks.extend(60u32.to_le_bytes());
+            // Manufacturer, product, sample period, MIDI unity note and pitch fraction, SMPTE format and offset.
+            for field in [0, 0, sample_period, 60, 0, 0, 0] {
+                chunks.extend(field.to_le_bytes());
+            }
+            // Loop count, sampler data, then the loop: ID, type (forward), start, end, fraction and play count.
+            for field in [1, 0, 0, 0, loop_start as u32, loop_end, 0, 0] {
+                chunks.extend(field.to_le_bytes());
+            }
+        }
+
+        let mut bytes = Vec::with_capacity(8 + chunks.len());
+        bytes.extend(b"RIFF");
+        bytes.extend((chunks.len() as u32).to_le_bytes());
+        bytes.extend(chunks);
+        bytes
+    }
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([bytes[offset], bytes[offset + 1], bytes[offset + 2], bytes[offset + 3]])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wav_round_trip() {
+        let wav = Wav { sample_rate: 32000, frames: vec![0, 100, -100, i16::MAX, i16::MIN], loop_start: Some(2) };
+        let bytes = wav.to_bytes();
+        assert_eq!(bytes.len(), 12 + 24 + 8 + 10 + 68);
+        assert_eq!(u32::from_le_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]) as usize, bytes.len() - 8);
+        assert_eq!(Wav::from_bytes(&bytes).unwrap(), wav);
+
+        let no_loop = Wav { loop_start: None, ..wav };
+        assert_eq!(Wav::from_bytes(&no_loop.to_bytes()).unwrap(), no_loop);
+        assert!(matches!(Wav::from_bytes(b"RIFF\0\0\0\0WAVE"), Err(WavError::MissingChunk("fmt "))));
+        assert!(matches!(Wav::from_bytes(&bytes[..50]), Err(WavError::TruncatedChunk(_))));
+    }
+
+    #[test]
+    fn test_stereo_8_bit() {
+        let mut bytes = Wav { sample_rate: 8000, frames: vec![0; 2], loop_start: None }.to_bytes();
+        // Switch to stereo 8-bit, making the 4 data bytes two frames.
+        bytes[22] = 2;
+        bytes[34] = 8;
+        bytes[44..48].copy_from_slice(&[0x80, 0x80, 0xC0, 0x40]);
+        let wav = Wav::from_bytes(&bytes).unwrap();
+        assert_eq!(wav.frames, [0, 0]);
+        bytes[47] = 0xC0;
+        assert_eq!(Wav::from_bytes(&bytes).unwrap().frames, [0, 0x4000]);
+    }
+}
@@ -1,4 +1,8 @@
-use std::ops::Range;
+use std::{
+    cmp::Reverse,
+    collections::{BinaryHeap, HashMap, VecDeque},
+    ops::Range,
+};
 
 use thiserror::Error;
 
@@ -8,10 +12,6 @@ use crate::compression::DecompressionError;
 
 #[derive(Debug, Error)]
 pub enum LcLz2Error {
-    #[error("Wrong command: {0:03b}")]
-    Command(u8),
-    #[error("Long Length - Wrong command: {0:03b}")]
-    LongLengthCommand(u8),
     #[error("Long Length - Cannot read second byte of header")]
     LongLength,
     #[error("Direct Copy - Cannot read {0} bytes")]
@@ -48,6 +48,10 @@ const INCREASING_FILL: u8 = 0b011;
 
 /// Followed by two bytes (ABCD byte order) containing address (in the
 /// output buffer) to copy (L+1) bytes from
+///
+/// The game only checks the highest bit of the command, so commands 0b101
+/// and 0b110, as well as 0b111 as the real command of Long Length, are
+/// repeats too. The compressor only emits 0b100.
 const REPEAT: u8 = 0b100;
 
 /// This command has got a two-byte header:
@@ -61,32 +65,37 @@ const LONG_LENGTH: u8 = 0b111;
 // -------------------------------------------------------------------------------------------------
 
 pub fn decompress(input: &[u8], little_endian_in_repeat: bool) -> Result<Vec<u8>, DecompressionError> {
+    decompress_with_size(input, little_endian_in_repeat).map(|(output, _)| output)
+}
+
+/// Returns decompressed data and the size of compressed data, including the terminator.
+///
+/// Commands are decoded like the game does, so there is no error for an unknown command: commands 0b101 and 0b110 are
+/// decoded as repeats, and so is 0b111 as the real command of Long Length.
+pub fn decompress_with_size(
+    input: &[u8], little_endian_in_repeat: bool,
+) -> Result<(Vec<u8>, usize), DecompressionError> {
     assert!(!input.is_empty());
 
     let mut output = Vec::with_capacity(input.len() * 2);
     let mut in_it = input;
     while let Some(chunk_header) = in_it.first().copied() {
+        in_it = &in_it[1..];
         if chunk_header == 0xFF {
             break;
         }
-        in_it = &in_it[1..];
 
         let mut command = chunk_header >> 5;
         let length = match command {
             LONG_LENGTH => {
                 command = (chunk_header >> 2) & 0b111;
 
-                if !matches!(command, DIRECT_COPY..=REPEAT | LONG_LENGTH) {
-                    return Err(LcLz2Error::LongLengthCommand(command).into());
-                }
-
                 let next_byte = *in_it.first().ok_or(LcLz2Error::LongLength)?;
                 in_it = &in_it[1..];
 
                 u16::from_le_bytes([next_byte, chunk_header & 3])
             }
-            DIRECT_COPY..=REPEAT => u16::from(chunk_header & 0x1F),
-            _ => return Err(LcLz2Error::Command(command).into()),
+            _ => u16::from(chunk_header & 0x1F),
         };
 
         let length = usize::from(length) + 1;
@@ -127,7 +136,7 @@ pub fn decompress(input: &[u8], little_endian_in_repeat: bool) -> Result<Vec<u8>
                 );
                 in_it = &in_it[1..];
             }
-            REPEAT => {
+            REPEAT..=LONG_LENGTH => {
                 if in_it.len() >= 2 {
                     let (bytes, rest) = in_it.split_at(2);
                     let from_bytes = if little_endian_in_repeat { u16::from_le_bytes } else { u16::from_be_bytes };
@@ -151,7 +160,298 @@ pub fn decompress(input: &[u8], little_endian_in_repeat: bool) -> Result<Vec<u8>
     }
 
     output.shrink_to_fit();
-    Ok(output)
+    let bytes_consumed = input.len() - in_it.len();
+    Ok((output, bytes_consumed))
+}
+
+/// Compresses `input` using as few bytes as possible, including the terminating `0xFF` byte.
+///
+/// The choice of commands is optimal, except that only a limited number of earlier positions
+/// is checked when looking for the longest repeat.
+pub fn compress(input: &[u8], little_endian_in_repeat: bool) -> Vec<u8> {
+    let byte_runs = byte_runs(input);
+    let word_runs = word_runs(input);
+    let increasing_runs = increasing_runs(input);
+    let mut repeat_finder = RepeatFinder::new(input, |input, source, offset| input.get(source + offset).copied());
+
+    let chunks = find_optimal_chunks(input.len(), |pos, candidates| {
+        candidates.push(Candidate {
+            command:      BYTE_FILL,
+            max_length:   byte_runs[pos],
+            payload_size: 1,
+            source:       0,
+        });
+        if word_runs[pos] >= 3 {
+            candidates.push(Candidate {
+                command:      WORD_FILL,
+                max_length:   word_runs[pos],
+                payload_size: 2,
+                source:       0,
+            });
+        }
+        if increasing_runs[pos] >= 2 {
+            let max_length = increasing_runs[pos];
+            candidates.push(Candidate { command: INCREASING_FILL, max_length, payload_size: 1, source: 0 });
+        }
+        // Repeat addresses are 16-bit.
+        if let Some((source, max_length)) = repeat_finder.longest_match(pos, |source| source <= u16::MAX as usize) {
+            candidates.push(Candidate { command: REPEAT, max_length, payload_size: 2, source });
+        }
+        repeat_finder.insert(pos);
+    });
+
+    let mut output = Vec::with_capacity(input.len() + 1);
+    for chunk in chunks {
+        write_chunk_header(&mut output, chunk);
+        match chunk.command {
+            DIRECT_COPY => output.extend_from_slice(&input[chunk.begin..chunk.begin + chunk.length]),
+            BYTE_FILL | INCREASING_FILL => output.push(input[chunk.begin]),
+            WORD_FILL => output.extend_from_slice(&input[chunk.begin..chunk.begin + 2]),
+            REPEAT => {
+                let source = chunk.source as u16;
+                output.extend(if little_endian_in_repeat { source.to_le_bytes() } else { source.to_be_bytes() });
+            }
+            _ => unreachable!(),
+        }
+    }
+    output.push(0xFF);
+    output
+}
+
+// -------------------------------------------------------------------------------------------------
+// Building blocks of compressors for LC-LZ2 and formats derived from it.
+
+/// Maximum length of data output by a single command.
+pub(super) const MAX_LENGTH: usize = 0x400;
+
+/// Maximum length of data output by a command with a one-byte header.
+pub(super) const MAX_SHORT_LENGTH: usize = 0x20;
+
+/// Maximum number of earlier positions checked when looking for the longest repeat.
+const MAX_REPEAT_CANDIDATES: usize = 0x200;
+
+/// A single command chosen by the compressor.
+#[derive(Copy, Clone, Debug)]
+pub(super) struct Chunk {
+    pub command: u8,
+    pub begin:   usize,
+    pub length:  usize,
+    /// Address to copy from, used only by repeat commands.
+    pub source:  usize,
+}
+
+/// A command which can be used at some position to output up to `max_length` bytes.
+#[derive(Copy, Clone, Debug)]
+pub(super) struct Candidate {
+    pub command:      u8,
+    pub max_length:   usize,
+    /// Number of bytes following the command's header.
+    pub payload_size: usize,
+    /// Address to copy from, used only by repeat commands.
+    pub source:       usize,
+}
+
+/// A command starting at `begin` which can end anywhere up to `end`, at the cost of `cost` bytes in total.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Ord, PartialOrd)]
+struct Offer {
+    cost:    usize,
+    end:     usize,
+    begin:   usize,
+    command: u8,
+    source:  usize,
+}
+
+/// Finds the longest repeats of earlier data using chains of positions starting with the same three bytes.
+pub(super) struct RepeatFinder<'a> {
+    input:       &'a [u8],
+    /// Returns the byte which a repeat copying from `source` outputs at `offset`.
+    source_byte: fn(&[u8], usize, usize) -> Option<u8>,
+    heads:       HashMap<[u8; 3], usize>,
+    previous:    Vec<Option<usize>>,
+}
+
+fn header_size(length: usize) -> usize {
+    if length <= MAX_SHORT_LENGTH {
+        1
+    } else {
+        2
+    }
+}
+
+pub(super) fn write_chunk_header(output: &mut Vec<u8>, chunk: Chunk) {
+    let length_bits = chunk.length - 1;
+    if chunk.length <= MAX_SHORT_LENGTH {
+        output.push((chunk.command << 5) | length_bits as u8);
+    } else {
+        output.push((LONG_LENGTH << 5) | (chunk.command << 2) | (length_bits >> 8) as u8);
+        output.push(length_bits as u8);
+    }
+}
+
+/// Number of bytes starting at each position which are all equal.
+pub(super) fn byte_runs(input: &[u8]) -> Vec<usize> {
+    let mut runs = vec![1; input.len()];
+    for i in (0..input.len().saturating_sub(1)).rev() {
+        if input[i + 1] == input[i] {
+            runs[i] += runs[i + 1];
+        }
+    }
+    runs
+}
+
+/// Number of bytes starting at each position which alternate between two values.
+pub(super) fn word_runs(input: &[u8]) -> Vec<usize> {
+    let len = input.len();
+    // Number of consecutive bytes starting at i which are equal to the byte two positions earlier.
+    let mut alternating_runs = vec![0; len + 2];
+    for i in (2..len).rev() {
+        if input[i] == input[i - 2] {
+            alternating_runs[i] = alternating_runs[i + 1] + 1;
+        }
+    }
+    (0..len).map(|i| (len - i).min(2 + alternating_runs[i + 2])).collect()
+}
+
+/// Number of bytes starting at each position which increase by one.
+fn increasing_runs(input: &[u8]) -> Vec<usize> {
+    let mut runs = vec![1; input.len()];
+    for i in (0..input.len().saturating_sub(1)).rev() {
+        if input[i + 1] == input[i].wrapping_add(1) {
+            runs[i] += runs[i + 1];
+        }
+    }
+    runs
+}
+
+/// Finds the sequence of commands that encodes `len` bytes of input in the smallest number of bytes.
+///
+/// `candidates_at` is called for consecutive positions and lists commands other than Direct Copy which can be used
+/// there. The cost of a command depends only on its length and not on its data, so for each position it's enough
+/// to know how far each command can go and the cheapest way to get there.
+pub(super) fn find_optimal_chunks(len: usize, mut candidates_at: impl FnMut(usize, &mut Vec<Candidate>)) -> Vec<Chunk> {
+    // Size of the shortest encoding of input[..i] along with its last chunk.
+    let mut best: Vec<(usize, Option<Chunk>)> = vec![(usize::MAX, None); len + 1];
+    best[0].0 = 0;
+
+    let mut offers_by_start: Vec<Vec<Offer>> = vec![Vec::new(); len + 1];
+    let mut active_offers = BinaryHeap::new();
+    let mut direct_copy_windows = [(MAX_SHORT_LENGTH, VecDeque::new()), (MAX_LENGTH, VecDeque::new())];
+    let mut candidates = Vec::new();
+
+    for pos in 0..=len {
+        if pos > 0 {
+            // Commands from candidates ending here
+            active_offers.extend(offers_by_start[pos].drain(..).map(Reverse));
+            while active_offers.peek().is_some_and(|Reverse(offer): &Reverse<Offer>| offer.end < pos) {
+                active_offers.pop();
+            }
+            if let Some(Reverse(offer)) = active_offers.peek() {
+                let chunk = Chunk {
+                    command: offer.command,
+                    begin:   offer.begin,
+                    length:  pos - offer.begin,
+                    source:  offer.source,
+                };
+                best[pos] = (offer.cost, Some(chunk));
+            }
+
+            // Direct copies ending here, starting where the encoding is cheapest relative to the position
+            let prev = pos - 1;
+            let key = best[prev].0 as isize - prev as isize;
+            for (max_length, window) in direct_copy_windows.iter_mut() {
+                while window.back().is_some_and(|&(_, k)| k >= key) {
+                    window.pop_back();
+                }
+                window.push_back((prev, key));
+                while window.front().is_some_and(|&(begin, _)| begin + *max_length < pos) {
+                    window.pop_front();
+                }
+                let &(begin, _) = window.front().unwrap();
+                let length = pos - begin;
+                let cost = best[begin].0 + header_size(length) + length;
+                if cost < best[pos].0 {
+                    best[pos] = (cost, Some(Chunk { command: DIRECT_COPY, begin, length, source: 0 }));
+                }
+            }
+        }
+
+        if pos == len {
+            break;
+        }
+
+        candidates.clear();
+        candidates_at(pos, &mut candidates);
+        for candidate in candidates.iter() {
+            let max_length = candidate.max_length.min(MAX_LENGTH).min(len - pos);
+            for (min_length, length_limit) in [(1, MAX_SHORT_LENGTH), (MAX_SHORT_LENGTH + 1, MAX_LENGTH)] {
+                if min_length <= max_length {
+                    offers_by_start[pos + min_length].push(Offer {
+                        cost:    best[pos].0 + header_size(min_length) + candidate.payload_size,
+                        end:     pos + length_limit.min(max_length),
+                        begin:   pos,
+                        command: candidate.command,
+                        source:  candidate.source,
+                    });
+                }
+            }
+        }
+    }
+
+    let mut chunks = Vec::new();
+    let mut pos = len;
+    while pos > 0 {
+        let chunk = best[pos].1.expect("every position should be reachable by a direct copy");
+        chunks.push(chunk);
+        pos = chunk.begin;
+    }
+    chunks.reverse();
+    chunks
+}
+
+impl<'a> RepeatFinder<'a> {
+    pub(super) fn new(input: &'a [u8], source_byte: fn(&[u8], usize, usize) -> Option<u8>) -> Self {
+        Self { input, source_byte, heads: HashMap::new(), previous: vec![None; input.len()] }
+    }
+
+    /// Returns the source address and length of the longest repeat which outputs the data at `pos`, considering
+    /// only sources inserted before and accepted by `accept_source`.
+    pub(super) fn longest_match(&self, pos: usize, accept_source: impl Fn(usize) -> bool) -> Option<(usize, usize)> {
+        let key: [u8; 3] = self.input.get(pos..pos + 3)?.try_into().unwrap();
+        let max_length = (self.input.len() - pos).min(MAX_LENGTH);
+
+        let mut longest: Option<(usize, usize)> = None;
+        let mut candidate = self.heads.get(&key).copied();
+        for _ in 0..MAX_REPEAT_CANDIDATES {
+            let Some(source) = candidate else { break };
+            candidate = self.previous[source];
+            if !accept_source(source) {
+                continue;
+            }
+            // A source can only give a longer repeat if it matches the byte right after the longest one so far.
+            let mismatch_at = |length| (self.source_byte)(self.input, source, length) != Some(self.input[pos + length]);
+            if longest.is_some_and(|(_, longest_length)| mismatch_at(longest_length)) {
+                continue;
+            }
+            let length = (0..max_length)
+                .take_while(|&offset| (self.source_byte)(self.input, source, offset) == Some(self.input[pos + offset]))
+                .count();
+            if longest.map_or(true, |(_, longest_length)| length > longest_length) {
+                longest = Some((source, length));
+                if length == max_length {
+                    break;
+                }
+            }
+        }
+        longest
+    }
+
+    /// Makes `source` available to repeats of data at later positions.
+    pub(super) fn insert(&mut self, source: usize) {
+        let key = [0, 1, 2].map(|offset| (self.source_byte)(self.input, source, offset));
+        if let [Some(b0), Some(b1), Some(b2)] = key {
+            self.previous[source] = self.heads.insert([b0, b1, b2], source);
+        }
+    }
 }
 
 // -------------------------------------------------------------------------------------------------
@@ -166,6 +466,43 @@ mod tests {
         }
     }
 
+    fn assert_round_trip(data: &[u8]) {
+        for little_endian_in_repeat in [false, true] {
+            let compressed = super::compress(data, little_endian_in_repeat);
+            let res = super::decompress(&compressed, little_endian_in_repeat);
+            let res = res.unwrap_or_else(|err| panic!("decompression of compressed data failed ({err})"));
+            assert!(res == data, "compression round trip gave wrong results (got: {res:?}, expected: {data:?})");
+        }
+    }
+
+    #[test]
+    fn test_compression_round_trip() {
+        let mut seed = 0x1234_5678u32;
+        let noise: Vec<u8> = std::iter::repeat_with(|| {
+            seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+            (seed >> 16) as u8
+        })
+        .take(0x1800)
+        .collect();
+
+        assert_round_trip(&[]);
+        assert_round_trip(&[0x42]);
+        assert_round_trip(&noise);
+        assert_round_trip(&[0; 0x1234]);
+        assert_round_trip(&[1, 2].repeat(0x500));
+        assert_round_trip(&(0..=0xFF).cycle().take(0x900).collect::<Vec<u8>>());
+        assert_round_trip(&[&noise[..0x40], &[7; 0x30], &noise[..0x40], &noise[0x20..0x500], &[3, 9, 3]].concat());
+        assert_round_trip(&noise[..0x80].repeat(0x40));
+    }
+
+    #[test]
+    fn test_compression_size() {
+        // Two Byte Fills with Long Length
+        assert_eq!(super::compress(&[0; 2000], false).len(), 7);
+        // Increasing Fill followed by a Repeat
+        assert_eq!(super::compress(&[1, 2, 3, 4, 2, 3, 4, 2, 3, 4, 2], false), [0x63, 1, 0x86, 0, 1, 0xFF]);
+    }
+
     #[test]
     fn test_slice_repeat() {
         let compressed = [
@@ -0,0 +1,361 @@
+use std::ops::Range;
+
+use thiserror::Error;
+
+use crate::compression::{
+    lc_lz2::{byte_runs, find_optimal_chunks, word_runs, write_chunk_header, Candidate, RepeatFinder},
+    DecompressionError,
+};
+
+// -------------------------------------------------------------------------------------------------
+
+#[derive(Debug, Error)]
+pub enum LcLz3Error {
+    #[error("No compressed data")]
+    Empty,
+    #[error("Long Length - Wrong command: {0:03b}")]
+    LongLengthCommand(u8),
+    #[error("Long Length - Cannot read second byte of header")]
+    LongLength,
+    #[error("Direct Copy - Cannot read {0} bytes")]
+    DirectCopy(usize),
+    #[error("Byte Fill - Cannot read byte")]
+    ByteFill,
+    #[error("Word Fill - Cannot read word")]
+    WordFill,
+    #[error("Repeat - Cannot read offset")]
+    RepeatIncomplete,
+    #[error("Repeat - Relative offset -{0} out of bounds (out buffer size: {1})")]
+    RepeatRelativeOffsetOutOfBounds(usize, usize),
+    #[error("Repeat - Range ({}..{}) out of bounds (out buffer size: {1})", .0.start, .0.end)]
+    RepeatRangeOutOfBounds(Range<usize>, usize),
+    #[error("Backwards Repeat - Cannot copy {1} bytes down from address {0}")]
+    BackwardsRepeatOutOfBounds(usize, usize),
+}
+
+// -------------------------------------------------------------------------------------------------
+
+/// Followed by (L+1) bytes of data
+const DIRECT_COPY: u8 = 0b000;
+
+/// Followed by one byte to be repeated (L+1) times
+const BYTE_FILL: u8 = 0b001;
+
+/// Followed by two bytes. Output first byte, then second, then first,
+/// then second, etc. until (L+1) bytes has been outputted
+const WORD_FILL: u8 = 0b010;
+
+/// Output (L+1) zero bytes
+const ZERO_FILL: u8 = 0b011;
+
+/// Followed by an offset (see below) of the address in the output buffer
+/// to copy (L+1) bytes from
+const REPEAT: u8 = 0b100;
+
+/// Same as Repeat, but the order of bits in each copied byte is reversed
+const BIT_REVERSED_REPEAT: u8 = 0b101;
+
+/// Same as Repeat, but the bytes are copied backwards, starting at the
+/// address and going down
+const BACKWARDS_REPEAT: u8 = 0b110;
+
+/// This command has got a two-byte header:
+/// ```text
+/// 111CCCLL LLLLLLLL
+/// CCC:        Real command
+/// LLLLLLLLLL: Length
+/// ```
+const LONG_LENGTH: u8 = 0b111;
+
+// Offsets of repeat commands take one of two forms:
+// ```text
+// 1ooooooo          - address is the current position in the output buffer minus (ooooooo+1)
+// 0AAAAAAA aaaaaaaa - address is AAAAAAAaaaaaaaa
+// ```
+
+/// Maximum distance between the current position and the address of a repeat with a one-byte offset.
+const MAX_RELATIVE_DISTANCE: usize = 0x80;
+
+/// Maximum address of a repeat with a two-byte offset.
+const MAX_ABSOLUTE_ADDRESS: usize = 0x7FFF;
+
+// -------------------------------------------------------------------------------------------------
+
+pub fn decompress(input: &[u8]) -> Result<Vec<u8>, DecompressionError> {
+    decompress_with_size(input).map(|(output, _)| output)
+}
+
+/// Returns decompressed data and the size of compressed data, including the terminator.
+pub fn decompress_with_size(input: &[u8]) -> Result<(Vec<u8>, usize), DecompressionError> {
+    if input.is_empty() {
+        return Err(LcLz3Error::Empty.into());
+    }
+
+    let mut output = Vec::with_capacity(input.len() * 2);
+    let mut in_it = input;
+    while let Some(chunk_header) = in_it.first().copied() {
+        in_it = &in_it[1..];
+        if chunk_header == 0xFF {
+            break;
+        }
+
+        let mut command = chunk_header >> 5;
+        let length = match command {
+            LONG_LENGTH => {
+                command = (chunk_header >> 2) & 0b111;
+
+                if command == LONG_LENGTH {
+                    return Err(LcLz3Error::LongLengthCommand(command).into());
+                }
+
+                let next_byte = *in_it.first().ok_or(LcLz3Error::LongLength)?;
+                in_it = &in_it[1..];
+
+                u16::from_le_bytes([next_byte, chunk_header & 3])
+            }
+            _ => u16::from(chunk_header & 0x1F),
+        };
+
+        let length = usize::from(length) + 1;
+
+        match command {
+            DIRECT_COPY => {
+                if length <= in_it.len() {
+                    let (bytes, rest) = in_it.split_at(length);
+                    output.extend_from_slice(bytes);
+                    in_it = rest;
+                } else {
+                    return Err(LcLz3Error::DirectCopy(length).into());
+                }
+            }
+            BYTE_FILL => {
+                let byte = *in_it.first().ok_or(LcLz3Error::ByteFill)?;
+                output.resize(output.len() + length, byte);
+                in_it = &in_it[1..];
+            }
+            WORD_FILL => {
+                if in_it.len() >= 2 {
+                    let (bytes, rest) = in_it.split_at(2);
+                    output.extend(bytes.iter().cycle().take(length));
+                    in_it = rest;
+                } else {
+                    return Err(LcLz3Error::WordFill.into());
+                }
+            }
+            ZERO_FILL => {
+                output.resize(output.len() + length, 0);
+            }
+            _ => {
+                let offset = *in_it.first().ok_or(LcLz3Error::RepeatIncomplete)?;
+                let read_start = if offset & 0x80 != 0 {
+                    let distance = usize::from(offset & 0x7F) + 1;
+                    in_it = &in_it[1..];
+                    output
+                        .len()
+                        .checked_sub(distance)
+                        .ok_or(LcLz3Error::RepeatRelativeOffsetOutOfBounds(distance, output.len()))?
+                } else if in_it.len() >= 2 {
+                    let (bytes, rest) = in_it.split_at(2);
+                    in_it = rest;
+                    usize::from(u16::from_be_bytes([bytes[0], bytes[1]]))
+                } else {
+                    return Err(LcLz3Error::RepeatIncomplete.into());
+                };
+
+                if read_start >= output.len() {
+                    let read_range = read_start..read_start + length;
+                    return Err(LcLz3Error::RepeatRangeOutOfBounds(read_range, output.len()).into());
+                }
+
+                output.reserve(length);
+                match command {
+                    REPEAT => {
+                        for i in read_start..read_start + length {
+                            output.push(output[i]);
+                        }
+                    }
+                    BIT_REVERSED_REPEAT => {
+                        for i in read_start..read_start + length {
+                            output.push(output[i].reverse_bits());
+                        }
+                    }
+                    _ => {
+                        if length > read_start + 1 {
+                            return Err(LcLz3Error::BackwardsRepeatOutOfBounds(read_start, length).into());
+                        }
+                        for i in (read_start + 1 - length..=read_start).rev() {
+                            output.push(output[i]);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    output.shrink_to_fit();
+    let bytes_consumed = input.len() - in_it.len();
+    Ok((output, bytes_consumed))
+}
+
+/// Compresses `input` using as few bytes as possible, including the terminating `0xFF` byte.
+///
+/// The choice of commands is optimal, except that only a limited number of earlier positions
+/// is checked when looking for the longest repeats.
+pub fn compress(input: &[u8]) -> Vec<u8> {
+    let byte_runs = byte_runs(input);
+    let word_runs = word_runs(input);
+    let mut repeat_finders = [
+        (REPEAT, RepeatFinder::new(input, |input, source, offset| input.get(source + offset).copied())),
+        (
+            BIT_REVERSED_REPEAT,
+            RepeatFinder::new(input, |input, source, offset| input.get(source + offset).map(|b| b.reverse_bits())),
+        ),
+        (
+            BACKWARDS_REPEAT,
+            RepeatFinder::new(input, |input, source, offset| source.checked_sub(offset).map(|i| input[i])),
+        ),
+    ];
+
+    let chunks = find_optimal_chunks(input.len(), |pos, candidates| {
+        if input[pos] == 0 {
+            candidates.push(Candidate {
+                command:      ZERO_FILL,
+                max_length:   byte_runs[pos],
+                payload_size: 0,
+                source:       0,
+            });
+        } else {
+            candidates.push(Candidate {
+                command:      BYTE_FILL,
+                max_length:   byte_runs[pos],
+                payload_size: 1,
+                source:       0,
+            });
+        }
+        if word_runs[pos] >= 3 {
+            candidates.push(Candidate {
+                command:      WORD_FILL,
+                max_length:   word_runs[pos],
+                payload_size: 2,
+                source:       0,
+            });
+        }
+        for (command, repeat_finder) in repeat_finders.iter_mut() {
+            let command = *command;
+            let near = repeat_finder.longest_match(pos, |source| pos - source <= MAX_RELATIVE_DISTANCE);
+            let far = repeat_finder.longest_match(pos, |source| source <= MAX_ABSOLUTE_ADDRESS);
+            let near_length = near.map_or(0, |(_, length)| length);
+            if let Some((source, max_length)) = near {
+                candidates.push(Candidate { command, max_length, payload_size: 1, source });
+            }
+            if let Some((source, max_length)) = far.filter(|&(_, length)| length > near_length) {
+                candidates.push(Candidate { command, max_length, payload_size: 2, source });
+            }
+            repeat_finder.insert(pos);
+        }
+    });
+
+    let mut output = Vec::with_capacity(input.len() + 1);
+    for chunk in chunks {
+        write_chunk_header(&mut output, chunk);
+        match chunk.command {
+            DIRECT_COPY => output.extend_from_slice(&input[chunk.begin..chunk.begin + chunk.length]),
+            BYTE_FILL => output.push(input[chunk.begin]),
+            WORD_FILL => output.extend_from_slice(&input[chunk.begin..chunk.begin + 2]),
+            ZERO_FILL => {}
+            REPEAT | BIT_REVERSED_REPEAT | BACKWARDS_REPEAT => {
+                let distance = chunk.begin - chunk.source;
+                if distance <= MAX_RELATIVE_DISTANCE {
+                    output.push(0x80 | (distance - 1) as u8);
+                } else {
+                    output.extend((chunk.source as u16).to_be_bytes());
+                }
+            }
+            _ => unreachable!(),
+        }
+    }
+    output.push(0xFF);
+    output
+}
+
+// -------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    fn assert_decompression(compressed: &[u8], decompressed: &[u8]) {
+        let res = super::decompress(compressed);
+        let res = res.unwrap_or_else(|err| panic!("decompression failed unexpectedly ({err})"));
+        if res.as_slice() != decompressed {
+            panic!("decompression gave wrong results (got: {res:?}, expected: {decompressed:?})")
+        }
+    }
+
+    fn assert_round_trip(data: &[u8]) {
+        let compressed = super::compress(data);
+        let (res, bytes_consumed) = super::decompress_with_size(&compressed)
+            .unwrap_or_else(|err| panic!("decompression of compressed data failed ({err})"));
+        assert!(res == data, "compression round trip gave wrong results (got: {res:?}, expected: {data:?})");
+        assert_eq!(bytes_consumed, compressed.len(), "compressed data not fully consumed");
+    }
+
+    #[test]
+    fn test_empty_input() {
+        assert!(super::decompress(&[]).is_err());
+    }
+
+    #[test]
+    fn test_repeat_commands() {
+        let compressed = [
+            // Insert [1, 2, 3, 4]
+            (0b000 << 5) | (4 - 1),
+            1,
+            2,
+            3,
+            4,
+            // Repeat 3 bytes from 3 bytes back
+            (0b100 << 5) | (3 - 1),
+            0x80 | (3 - 1),
+            // Repeat 2 bit-reversed bytes from address 0
+            (0b101 << 5) | (2 - 1),
+            0,
+            0,
+            // Repeat 4 bytes backwards from address 3
+            (0b110 << 5) | (4 - 1),
+            0,
+            3,
+            0xFF,
+        ];
+        assert_decompression(&compressed, &[1, 2, 3, 4, 2, 3, 4, 0x80, 0x40, 4, 3, 2, 1]);
+    }
+
+    #[test]
+    fn test_compression_round_trip() {
+        let mut seed = 0x8765_4321u32;
+        let noise: Vec<u8> = std::iter::repeat_with(|| {
+            seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+            (seed >> 16) as u8
+        })
+        .take(0x1800)
+        .collect();
+        let reversed: Vec<u8> = noise[..0x300].iter().rev().copied().collect();
+        let flipped: Vec<u8> = noise[..0x300].iter().map(|b| b.reverse_bits()).collect();
+
+        assert_round_trip(&[]);
+        assert_round_trip(&[0x42]);
+        assert_round_trip(&noise);
+        assert_round_trip(&[0; 0x1234]);
+        assert_round_trip(&[9, 0].repeat(0x500));
+        assert_round_trip(&[&noise[..0x300], &reversed, &flipped, &[0; 0x21], &noise[0x10..0x90]].concat());
+        assert_round_trip(&[&noise[..], &noise[0x40..0x80], &[1; 0x40], &reversed[0x8..0x10]].concat());
+    }
+
+    #[test]
+    fn test_compression_size() {
+        // Direct Copy, Zero Fill and Backwards Repeat
+        assert_eq!(super::compress(&[1, 2, 3, 0, 0, 0, 0, 3, 2, 1]).len(), 8);
+        // Direct Copy and Backwards Repeat
+        assert_eq!(super::compress(&[1, 2, 3, 4, 5, 6, 7, 8, 8, 7, 6, 5, 4, 3, 2, 1]).len(), 12);
+        // Direct Copy and Bit-Reversed Repeat
+        assert_eq!(super::compress(&[1, 2, 3, 4, 5, 6, 0x80, 0x40, 0xC0, 0x20, 0xA0, 0x60]).len(), 10);
+    }
+}
@@ -19,9 +19,12 @@ pub enum LcRle1Error {
 const COMMAND_DIRECT_COPY: u8 = 0;
 const COMMAND_BYTE_FILL: u8 = 1;
 
+/// Maximum length of data output by a single command.
+const MAX_LENGTH: usize = 0x80;
+
 // -------------------------------------------------------------------------------------------------
 
-/// Returns decompressed data and the size of compressed data.
+/// Returns decompressed data and the size of compressed data, including the terminator.
 pub fn decompress(input: &[u8]) -> Result<(Vec<u8>, usize), DecompressionError> {
     assert!(!input.is_empty());
     assert!(!input.len() >= 2);
@@ -29,6 +32,7 @@ pub fn decompress(input: &[u8]) -> Result<(Vec<u8>, usize), DecompressionError>
     let mut in_it = input;
     while let Some(chunk_header) = in_it.first().copied() {
         if chunk_header == 0xFF && (in_it.len() == 1 || in_it[1] == 0xFF) {
+            in_it = &in_it[in_it.len().min(2)..];
             break;
         }
         in_it = &in_it[1..];
@@ -58,3 +62,86 @@ pub fn decompress(input: &[u8]) -> Result<(Vec<u8>, usize), DecompressionError>
     let bytes_consumed = input.len() - in_it.len();
     Ok((output, bytes_consumed))
 }
+
+/// Compresses `input` using as few bytes as possible, including the terminating `0xFF 0xFF` bytes.
+pub fn compress(input: &[u8]) -> Vec<u8> {
+    let len = input.len();
+
+    let mut byte_runs = vec![1; len];
+    for i in (0..len.saturating_sub(1)).rev() {
+        if input[i + 1] == input[i] {
+            byte_runs[i] += byte_runs[i + 1];
+        }
+    }
+
+    // Size of the shortest encoding of input[..i], along with the command and the beginning of its last chunk.
+    let mut best = vec![(usize::MAX, COMMAND_DIRECT_COPY, 0); len + 1];
+    best[0].0 = 0;
+    for begin in 0..len {
+        let cost = best[begin].0;
+        for length in 1..=MAX_LENGTH.min(len - begin) {
+            let end = begin + length;
+            if cost + 1 + length < best[end].0 {
+                best[end] = (cost + 1 + length, COMMAND_DIRECT_COPY, begin);
+            }
+        }
+        // Byte Fill of 0x80 0xFF bytes would be encoded as the terminator.
+        let max_fill_length = if input[begin] == 0xFF { MAX_LENGTH - 1 } else { MAX_LENGTH };
+        for length in 1..=max_fill_length.min(byte_runs[begin]) {
+            let end = begin + length;
+            if cost + 2 < best[end].0 {
+                best[end] = (cost + 2, COMMAND_BYTE_FILL, begin);
+            }
+        }
+    }
+
+    let mut chunks = Vec::new();
+    let mut end = len;
+    while end > 0 {
+        let (_, command, begin) = best[end];
+        chunks.push((command, begin, end - begin));
+        end = begin;
+    }
+
+    let mut output = Vec::with_capacity(best[len].0 + 2);
+    for (command, begin, length) in chunks.into_iter().rev() {
+        output.push((command << 7) | (length - 1) as u8);
+        match command {
+            COMMAND_DIRECT_COPY => output.extend_from_slice(&input[begin..begin + length]),
+            COMMAND_BYTE_FILL => output.push(input[begin]),
+            _ => unreachable!(),
+        }
+    }
+    output.extend([0xFF, 0xFF]);
+    output
+}
+
+// -------------------------------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    fn assert_round_trip(data: &[u8]) {
+        let compressed = super::compress(data);
+        let (res, bytes_consumed) =
+            super::decompress(&compressed).unwrap_or_else(|err| panic!("decompression failed unexpectedly ({err})"));
+        assert!(res == data, "compression round trip gave wrong results (got: {res:?}, expected: {data:?})");
+        assert_eq!(bytes_consumed, compressed.len(), "compressed data not fully consumed");
+    }
+
+    #[test]
+    fn test_compression_round_trip() {
+        assert_round_trip(&[]);
+        assert_round_trip(&[0xFF]);
+        assert_round_trip(&[0xFF; 0x200]);
+        assert_round_trip(&[0x25; 0x181]);
+        assert_round_trip(&(0..=0xFF).cycle().take(0x333).collect::<Vec<u8>>());
+        assert_round_trip(&[&[1, 2, 3][..], &[4; 3], &[5, 6], &[7; 0x90], &[0xFF, 0xFF, 0xFF, 8]].concat());
+    }
+
+    #[test]
+    fn test_compression_size() {
+        // Direct Copy wins over Byte Fill for runs of two bytes surrounded by other data.
+        assert_eq!(super::compress(&[1, 2, 2, 3]), [0x03, 1, 2, 2, 3, 0xFF, 0xFF]);
+        assert_eq!(super::compress(&[1, 2, 2, 2, 2, 3]), [0x00, 1, 0x83, 2, 0x00, 3, 0xFF, 0xFF]);
+    }
+}
@@ -1,11 +1,12 @@
 pub mod lc_lz2;
+pub mod lc_lz3;
 pub mod lc_rle1;
 
 use duplicate::duplicate_item;
 use paste::paste;
 use thiserror::Error;
 
-pub use self::{lc_lz2::LcLz2Error, lc_rle1::LcRle1Error};
+pub use self::{lc_lz2::LcLz2Error, lc_lz3::LcLz3Error, lc_rle1::LcRle1Error};
 
 // -------------------------------------------------------------------------------------------------
 
@@ -13,15 +14,47 @@ pub use self::{lc_lz2::LcLz2Error, lc_rle1::LcRle1Error};
 pub enum DecompressionError {
     #[error("Decompression with LC-LZ2:\n- {0}")]
     LcLz2(LcLz2Error),
+    #[error("Decompression with LC-LZ3:\n- {0}")]
+    LcLz3(LcLz3Error),
     #[error("Decompression with LC-RLE1:\n- {0}")]
     LcRle1(LcRle1Error),
 }
 
+/// Compression format of data which Lunar Magic can store in a different format than the original game.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Compression {
+    LcLz2 { little_endian_in_repeat: bool },
+    LcLz3,
+    LcRle1,
+}
+
 // -------------------------------------------------------------------------------------------------
 
-#[duplicate_item(algorithm; [LcLz2]; [LcRle1];)]
+#[duplicate_item(algorithm; [LcLz2]; [LcLz3]; [LcRle1];)]
 impl From<paste! { [<algorithm Error>] }> for DecompressionError {
     fn from(e: paste! { [<algorithm Error>] }) -> Self {
         DecompressionError::algorithm(e)
     }
 }
+
+impl Compression {
+    /// Returns decompressed data and the size of compressed data, including the terminator.
+    pub fn decompress(self, input: &[u8]) -> Result<(Vec<u8>, usize), DecompressionError> {
+        match self {
+            Compression::LcLz2 { little_endian_in_repeat } => {
+                lc_lz2::decompress_with_size(input, little_endian_in_repeat)
+            }
+            Compression::LcLz3 => lc_lz3::decompress_with_size(input),
+            Compression::LcRle1 => lc_rle1::decompress(input),
+        }
+    }
+
+    /// Compresses `input`, including the terminator.
+    pub fn compress(self, input: &[u8]) -> Vec<u8> {
+        match self {
+            Compression::LcLz2 { little_endian_in_repeat } => lc_lz2::compress(input, little_endian_in_repeat),
+            Compression::LcLz3 => lc_lz3::compress(input),
+            Compression::LcRle1 => lc_rle1::compress(input),
+        }
+    }
+}
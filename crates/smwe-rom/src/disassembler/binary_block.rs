@@ -47,6 +47,8 @@ pub enum DataKind {
     JumpTableLong,
 
     // Level
+    LevelBanksLayer2Background,
+    LevelBanksSprite,
     LevelPointersLayer1,
     LevelPointersLayer2,
     LevelPointersSprite,
@@ -60,6 +62,9 @@ pub enum DataKind {
     SecondaryEntranceTable,
 
     // Overworld
+    OverworldEventLayer2,
+    OverworldEventLayer2Tilemap,
+    OverworldHardCodedPaths,
     OverworldLayer1,
     OverworldLayer2,
     OverworldSpriteLayer,
@@ -73,6 +78,7 @@ pub enum DataKind {
     GfxFile,
     InternalRomHeader,
     Music,
+    RatsTag,
     SoundSample,
     Text,
     Tileset,
@@ -0,0 +1,197 @@
+use std::ops::{Range, RangeInclusive};
+
+use itertools::Itertools;
+
+use crate::{
+    disassembler::{
+        binary_block::{BinaryBlock, DataBlock, DataKind},
+        RomDisassembly,
+    },
+    snes_utils::{
+        addr::{AddrPc, AddrSnes},
+        rom_slice::{PcSlice, SnesSlice},
+    },
+    RomError,
+};
+
+// -------------------------------------------------------------------------------------------------
+
+pub const BANK_SIZE: usize = 0x8000;
+
+/// End of the original 512 KB ROM. Only the area added by expanding the ROM is searched for free space, since unknown
+/// chunks in the original ROM may still hold code or data which hasn't been traced.
+pub const EXPANDED_AREA_START: AddrPc = AddrPc(0x80000);
+
+/// Banks in which data can be placed in free space. In a 4 MB LoROM ROM, banks `$7E` and `$7F` would map to WRAM, so
+/// pointers into them would never reach the data.
+pub const FREE_SPACE_BANKS: RangeInclusive<u8> = 0x10..=0x7D;
+
+pub const RATS_TAG_SIZE: usize = 8;
+pub const RATS_TAG_ID: [u8; 4] = *b"STAR";
+
+/// Maximum size of data protected by a single RATS tag, limited by the LoROM bank size.
+pub const MAX_PROTECTED_DATA_SIZE: usize = BANK_SIZE - RATS_TAG_SIZE;
+
+/// `STAR ssssssss SSSSSSSS cccccccc CCCCCCCC`
+///
+/// | Value               | Comment                                |
+/// |---------------------|----------------------------------------|
+/// | `STAR`              | Tag identifier                         |
+/// | `SSSSSSSS ssssssss` | Size of protected data minus one       |
+/// | `CCCCCCCC cccccccc` | Bitwise complement of the stored size  |
+///
+/// The tag protects data placed right after it in free space from being overwritten by other tools.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct RatsTag {
+    pub data_size: usize,
+}
+
+// -------------------------------------------------------------------------------------------------
+
+impl RatsTag {
+    pub fn new(data_size: usize) -> Self {
+        debug_assert!((1..=0x10000).contains(&data_size), "invalid size of RATS-protected data: {data_size}");
+        Self { data_size }
+    }
+
+    /// Returns `None` if `bytes` don't start with a valid RATS tag.
+    pub fn parse(bytes: &[u8]) -> Option<Self> {
+        let tag = bytes.get(..RATS_TAG_SIZE)?;
+        let size = u16::from_le_bytes([tag[4], tag[5]]);
+        let complement = u16::from_le_bytes([tag[6], tag[7]]);
+        (tag[..4] == RATS_TAG_ID && size ^ complement == 0xFFFF).then(|| Self::new(size as usize + 1))
+    }
+
+    pub fn to_bytes(self) -> [u8; RATS_TAG_SIZE] {
+        let size = (self.data_size - 1) as u16;
+        let [s_lo, s_hi] = size.to_le_bytes();
+        let [c_lo, c_hi] = (!size).to_le_bytes();
+        let [i0, i1, i2, i3] = RATS_TAG_ID;
+        [i0, i1, i2, i3, s_lo, s_hi, c_lo, c_hi]
+    }
+}
+
+impl RomDisassembly {
+    /// Finds `size` bytes of free space in one of the `banks`, not crossing a bank boundary. Free space is a run of
+    /// zero bytes in the expanded area of the ROM, see [`EXPANDED_AREA_START`], inside a chunk that hasn't been
+    /// identified as code or data. Areas protected by RATS tags are never considered free, even if they are filled
+    /// with zeros.
+    ///
+    /// A ROM that hasn't been expanded has no free space.
+    pub fn find_free_space(&self, size: usize, banks: RangeInclusive<u8>) -> Option<AddrSnes> {
+        if size == 0 || size > BANK_SIZE {
+            return None;
+        }
+
+        let bytes = self.rom_bytes();
+        for ((begin, block), (next_begin, _)) in self.chunks.iter().tuple_windows::<(_, _)>() {
+            if !matches!(block, BinaryBlock::Unknown) {
+                continue;
+            }
+
+            let (mut pc, end) = (begin.as_index().max(EXPANDED_AREA_START.as_index()), next_begin.as_index());
+            let (mut run_start, mut run_len) = (pc, 0);
+            while pc < end {
+                if let Some(tag) = RatsTag::parse(&bytes[pc..end]) {
+                    pc += RATS_TAG_SIZE + tag.data_size;
+                    run_len = 0;
+                    continue;
+                }
+                if pc % BANK_SIZE == 0 || bytes[pc] != 0 {
+                    run_len = 0;
+                }
+                if bytes[pc] == 0 {
+                    if run_len == 0 {
+                        run_start = pc;
+                    }
+                    run_len += 1;
+                    if run_len == size {
+                        let addr = AddrSnes::try_from_lorom(AddrPc(run_start as u32)).ok()?;
+                        if banks.contains(&addr.bank()) {
+                            return Some(addr);
+                        }
+                        run_len = 0;
+                    }
+                }
+                pc += 1;
+            }
+        }
+
+        None
+    }
+
+    /// Reserves `size` bytes of free space in one of the `banks` and protects them with a RATS tag, which gets written
+    /// into the ROM and marked as data. Returns the address of the reserved space, right after the tag.
+    ///
+    /// The caller is expected to write the data and mark it with its kind.
+    pub fn allocate(&mut self, size: usize, banks: RangeInclusive<u8>) -> Result<AddrSnes, RomError> {
+        if size == 0 || size > MAX_PROTECTED_DATA_SIZE {
+            return Err(RomError::NoFreeSpace(size));
+        }
+
+        let tag_addr = self.find_free_space(RATS_TAG_SIZE + size, banks).ok_or(RomError::NoFreeSpace(size))?;
+        self.rom.write_lorom(tag_addr, &RatsTag::new(size).to_bytes())?;
+        self.mark_data_block(DataBlock { slice: SnesSlice::new(tag_addr, RATS_TAG_SIZE), kind: DataKind::RatsTag })?;
+        Ok(tag_addr + RATS_TAG_SIZE)
+    }
+
+    /// Releases data protected by a RATS tag right before `addr`, filling the tag and the data with zeros and turning
+    /// data blocks marked there back into unknown chunks, so that the space can be reused.
+    ///
+    /// Returns `false` without modifying anything if there is no valid RATS tag before `addr`, since the extent of
+    /// unprotected data cannot be known and it may be used by the original game.
+    pub fn free(&mut self, addr: AddrSnes) -> Result<bool, RomError> {
+        let data_pc = AddrPc::try_from_lorom(addr).map_err(|_| RomError::SliceSnes(SnesSlice::new(addr, 0)))?;
+        if data_pc.as_index() % BANK_SIZE < RATS_TAG_SIZE {
+            return Ok(false);
+        }
+
+        let tag_pc = data_pc - RATS_TAG_SIZE;
+        let Some(tag) = RatsTag::parse(&self.rom_bytes()[tag_pc.as_index()..]) else {
+            return Ok(false);
+        };
+
+        let end_pc = data_pc + tag.data_size;
+        self.mark_unknown(tag_pc..end_pc)?;
+        self.rom.write_pc(tag_pc, &vec![0; RATS_TAG_SIZE + tag.data_size])?;
+        Ok(true)
+    }
+
+    /// Replaces chunks inside `range` with a single unknown chunk, merging it with adjacent unknown chunks.
+    ///
+    /// Fails if the range overlaps with code, or with data extending beyond it.
+    fn mark_unknown(&mut self, range: Range<AddrPc>) -> Result<(), RomError> {
+        let error =
+            || RomError::FreeOverlapping(PcSlice::new(range.start, range.end.as_index() - range.start.as_index()));
+
+        let overlapping = self
+            .chunks
+            .iter()
+            .tuple_windows::<(_, _)>()
+            .positions(|((begin, _), (next_begin, _))| *begin < range.end && *next_begin > range.start)
+            .collect_vec();
+        let (Some(&first), Some(&last)) = (overlapping.first(), overlapping.last()) else {
+            return Err(error());
+        };
+
+        for ((begin, block), (next_begin, _)) in self.chunks[first..=last + 1].iter().tuple_windows::<(_, _)>() {
+            match block {
+                BinaryBlock::Unknown => {}
+                BinaryBlock::Data(_) if *begin >= range.start && *next_begin <= range.end => {}
+                _ => return Err(error()),
+            }
+        }
+
+        let removed = self.chunks.splice(first..=last, [(self.chunks[first].0, BinaryBlock::Unknown)]).collect_vec();
+        for (_, block) in removed {
+            if let BinaryBlock::Data(data_block) = block {
+                self.cached_data_blocks.remove(&data_block);
+            }
+        }
+        self.chunks.dedup_by(|(_, next), (_, prev)| {
+            matches!(prev, BinaryBlock::Unknown) && matches!(next, BinaryBlock::Unknown)
+        });
+
+        Ok(())
+    }
+}
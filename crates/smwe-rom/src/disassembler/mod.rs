@@ -2,6 +2,7 @@
 // https://github.com/Dotsarecool/DiztinGUIsh
 
 pub mod binary_block;
+pub mod free_space;
 pub mod instruction;
 pub mod jump_tables;
 pub mod opcodes;
@@ -37,7 +38,7 @@ use crate::{
     },
     snes_utils::{
         addr::{Addr, AddrPc, AddrSnes},
-        rom::{RomViewWithErrorMapper, SnesSliced},
+        rom::{noop_error_mapper, RomViewWithErrorMapper, SnesSliced},
         rom_slice::SnesSlice,
     },
     Rom,
@@ -144,10 +145,36 @@ impl RomDisassembly {
         }
     }
 
+    /// Wraps the ROM without analysing its code, so that all of it is unknown.
+    #[cfg(test)]
+    pub(crate) fn unanalysed(rom: Rom) -> Self {
+        let end_of_rom = AddrPc(rom.0.len() as u32);
+        Self {
+            rom,
+            chunks: vec![(AddrPc(0), BinaryBlock::Unknown), (end_of_rom, BinaryBlock::EndOfRom)],
+            cached_data_blocks: HashSet::new(),
+            code_lines: Vec::new(),
+        }
+    }
+
     pub fn rom_bytes(&self) -> &[u8] {
         &self.rom.0
     }
 
+    /// Expands the ROM and updates its internal header, see [`expand_lorom`]. The added area becomes an unknown chunk,
+    /// so that it can be used as free space.
+    ///
+    /// [`expand_lorom`]: crate::snes_utils::rom::expand_lorom
+    pub fn expand(&mut self, new_size: usize) -> std::result::Result<(), RomError> {
+        self.rom.expand(new_size)?;
+        let (end_of_rom, _) = self.chunks.pop().expect("Disassembly has no chunks");
+        if !matches!(self.chunks.last(), Some((_, BinaryBlock::Unknown))) {
+            self.chunks.push((end_of_rom, BinaryBlock::Unknown));
+        }
+        self.chunks.push((AddrPc(new_size as u32), BinaryBlock::EndOfRom));
+        Ok(())
+    }
+
     /// Parses a data block and marks it with given kind and size determined by the `parse` function. `parse` returns
     /// the parsed data and number of ROM bytes consumed by the parser.
     pub fn parse_and_mark_data<EM, ET, RT, PF>(
@@ -235,6 +262,11 @@ impl RomDisassembly {
         self.rom.with_error_mapper(error_mapper).slice_lorom(data_block.slice)
     }
 
+    /// Marks an area of the ROM as data of given kind without reading it, e.g. after writing new data into free space.
+    pub fn mark_data_block(&mut self, data_block: DataBlock) -> std::result::Result<(), RomError> {
+        self.rom_slice_at_block(data_block, noop_error_mapper).map(drop)
+    }
+
     fn split_unknown_block_with<EM, ET>(
         &mut self, data_block: DataBlock, error_mapper: &EM,
     ) -> std::result::Result<(), ET>
@@ -0,0 +1,274 @@
+//! ExGFX files, which Lunar Magic inserts into free space and loads in place of the original GFX files when their
+//! numbers, `80`–`FFF`, are used in GFX lists. Lunar Magic projects keep them in a folder as `ExGFX<number>.bin`
+//! files, holding uncompressed tiles.
+
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use thiserror::Error;
+
+use crate::{
+    compression::Compression,
+    disassembler::{
+        binary_block::{DataBlock, DataKind},
+        free_space::FREE_SPACE_BANKS,
+        RomDisassembly,
+    },
+    graphics::gfx_file::{GfxFile, GfxFileParseError, TileFormat},
+    lunar_magic::{LunarMagic, FIRST_EXGFX_NUM},
+    snes_utils::{addr::AddrSnes, rom::RomError, rom_slice::SnesSlice},
+};
+
+// -------------------------------------------------------------------------------------------------
+
+#[derive(Debug, Error)]
+pub enum ExGfxError {
+    #[error("ExGFX{0:X} is outside ExGFX80–ExGFXFFF")]
+    Number(usize),
+    #[error("File '{}' is not named like an ExGFX file, e.g. 'ExGFX80.bin'", .0.display())]
+    FileName(PathBuf),
+    #[error("Reading '{}':\n- {1}", .0.display())]
+    Io(PathBuf, std::io::Error),
+    #[error("Parsing ExGFX{0:X}:\n- {1}")]
+    Parse(usize, GfxFileParseError),
+    #[error("Lunar Magic's pointer table for ExGFX{0:X} is not installed")]
+    NoPointerTable(usize),
+    #[error("Not enough free space for ExGFX{0:X} ({1} bytes)")]
+    NoFreeSpace(usize, usize),
+    #[error("Writing ExGFX{0:X}:\n- {1}")]
+    Write(usize, RomError),
+}
+
+// -------------------------------------------------------------------------------------------------
+
+pub const LAST_EXGFX_NUM: usize = 0xFFF;
+pub const EXGFX_FILE_PREFIX: &str = "ExGFX";
+pub const EXGFX_FILE_EXTENSION: &str = "bin";
+
+/// Number of tiles in a GFX file filling a whole slot of a GFX list.
+const TILES_PER_GFX_SLOT: usize = 0x80;
+
+// -------------------------------------------------------------------------------------------------
+
+#[derive(Debug, Clone)]
+pub struct ExGfxFile {
+    pub file: GfxFile,
+    /// Location of the file in the ROM, `None` if it hasn't been inserted yet.
+    pub addr: Option<AddrSnes>,
+}
+
+/// ExGFX files by their numbers.
+#[derive(Debug, Clone)]
+pub struct ExGfx {
+    pub files:   BTreeMap<usize, ExGfxFile>,
+    /// Compression of inserted files.
+    compression: Compression,
+}
+
+// -------------------------------------------------------------------------------------------------
+
+impl ExGfx {
+    /// Reads all files listed in Lunar Magic's ExGFX pointer tables. Files which cannot be read are skipped with a
+    /// warning, since the tables may point at leftovers of files that have been removed.
+    pub fn parse(disasm: &mut RomDisassembly, lunar_magic: Option<&LunarMagic>, compression: Compression) -> Self {
+        let mut exgfx = Self { files: BTreeMap::new(), compression };
+        let Some(lm) = lunar_magic else {
+            return exgfx;
+        };
+
+        for file_num in FIRST_EXGFX_NUM..=LAST_EXGFX_NUM {
+            let Some(addr) = lm.exgfx_addr(file_num) else {
+                continue;
+            };
+            let parsed =
+                disasm.parse_and_mark_data(addr, DataKind::GfxFile, GfxFileParseError::IsolatingData, |view| {
+                    let (data, compressed_size) =
+                        compression.decompress(view.as_bytes()?).map_err(GfxFileParseError::DecompressingData)?;
+                    let file = GfxFile::from_uncompressed_bytes(&data, guess_tile_format(data.len()), compression)?;
+                    Ok((file, compressed_size))
+                });
+            match parsed {
+                Ok(file) => {
+                    exgfx.files.insert(file_num, ExGfxFile { file, addr: Some(addr) });
+                }
+                Err(e) => log::warn!("Cannot read ExGFX{file_num:X} at {addr}:\n- {e}"),
+            }
+        }
+        exgfx
+    }
+
+    pub fn get(&self, file_num: usize) -> Option<&GfxFile> {
+        self.files.get(&file_num).map(|exgfx| &exgfx.file)
+    }
+
+    /// Adds or replaces a file, returning the previous one. A replaced file's space in the ROM is reused or released
+    /// when writing.
+    pub fn insert(&mut self, file_num: usize, file: GfxFile) -> Result<Option<GfxFile>, ExGfxError> {
+        if !(FIRST_EXGFX_NUM..=LAST_EXGFX_NUM).contains(&file_num) {
+            return Err(ExGfxError::Number(file_num));
+        }
+        let addr = self.files.get(&file_num).and_then(|exgfx| exgfx.addr);
+        Ok(self.files.insert(file_num, ExGfxFile { file, addr }).map(|exgfx| exgfx.file))
+    }
+
+    /// Reads an uncompressed `ExGFX<number>.bin` file, returning its number. The format of tiles is guessed from the
+    /// size of the file and can be changed afterwards, it doesn't affect how the file is stored.
+    pub fn load_file(&mut self, path: impl AsRef<Path>) -> Result<usize, ExGfxError> {
+        let path = path.as_ref();
+        let file_num = file_num_from_path(path).ok_or_else(|| ExGfxError::FileName(path.to_path_buf()))?;
+        let data = fs::read(path).map_err(|e| ExGfxError::Io(path.to_path_buf(), e))?;
+        let file = GfxFile::from_uncompressed_bytes(&data, guess_tile_format(data.len()), self.compression)
+            .map_err(|e| ExGfxError::Parse(file_num, e))?;
+        self.insert(file_num, file)?;
+        Ok(file_num)
+    }
+
+    /// Reads all ExGFX files in a folder, e.g. `ExGraphics` of a Lunar Magic project, returning their numbers.
+    /// Files not named like ExGFX files are ignored.
+    pub fn load_dir(&mut self, dir: impl AsRef<Path>) -> Result<Vec<usize>, ExGfxError> {
+        let dir = dir.as_ref();
+        let mut paths = fs::read_dir(dir)
+            .and_then(|entries| entries.map(|entry| entry.map(|entry| entry.path())).collect::<Result<Vec<_>, _>>())
+            .map_err(|e| ExGfxError::Io(dir.to_path_buf(), e))?;
+        paths.retain(|path| path.is_file() && file_num_from_path(path).is_some());
+        paths.sort_by_key(|path| file_num_from_path(path));
+        paths.into_iter().map(|path| self.load_file(path)).collect()
+    }
+
+    /// Compresses files and inserts them into free space, updating Lunar Magic's pointer tables. Files whose contents
+    /// haven't changed are left alone, and files that still fit in place are overwritten.
+    pub fn write_to_rom(
+        &mut self, disasm: &mut RomDisassembly, mut lunar_magic: Option<&mut LunarMagic>,
+    ) -> Result<(), ExGfxError> {
+        let addrs: Vec<AddrSnes> = self.files.values().filter_map(|exgfx| exgfx.addr).collect();
+        for (&file_num, exgfx) in self.files.iter_mut() {
+            let lm = match lunar_magic.as_deref_mut() {
+                Some(lm) if lm.has_exgfx_pointer(file_num) => lm,
+                _ => return Err(ExGfxError::NoPointerTable(file_num)),
+            };
+            let shared = exgfx.addr.is_some_and(|addr| {
+                addrs.iter().filter(|&&other| other == addr).count() > 1 || lm.gfx_file_addrs.contains(&addr)
+            });
+
+            let new_data = exgfx.file.to_uncompressed_bytes();
+            let compressed = exgfx.file.to_bytes();
+            if let Some(old_addr) = exgfx.addr {
+                let old = disasm
+                    .rom
+                    .view()
+                    .slice_lorom(SnesSlice::new(old_addr, usize::MAX))
+                    .and_then(|view| view.as_bytes())
+                    .ok()
+                    .and_then(|bytes| exgfx.file.compression.decompress(bytes).ok());
+                if let Some((old_data, old_size)) = old {
+                    if old_data == new_data {
+                        continue;
+                    }
+                    if !shared && compressed.len() <= old_size {
+                        disasm.rom.write_lorom(old_addr, &compressed).map_err(|e| ExGfxError::Write(file_num, e))?;
+                        continue;
+                    }
+                }
+            }
+
+            let new_addr = disasm.allocate(compressed.len(), FREE_SPACE_BANKS).map_err(|e| match e {
+                RomError::NoFreeSpace(size) => ExGfxError::NoFreeSpace(file_num, size),
+                e => ExGfxError::Write(file_num, e),
+            })?;
+            disasm.rom.write_lorom(new_addr, &compressed).map_err(|e| ExGfxError::Write(file_num, e))?;
+            disasm
+                .mark_data_block(DataBlock {
+                    slice: SnesSlice::new(new_addr, compressed.len()),
+                    kind:  DataKind::GfxFile,
+                })
+                .map_err(|e| ExGfxError::Write(file_num, e))?;
+            lm.set_exgfx_addr(disasm, file_num, new_addr).map_err(|e| ExGfxError::Write(file_num, e))?;
+
+            // Like with levels, only files previously inserted into free space are released.
+            if let Some(old_addr) = exgfx.addr.filter(|_| !shared) {
+                disasm.free(old_addr).map_err(|e| ExGfxError::Write(file_num, e))?;
+            }
+            exgfx.addr = Some(new_addr);
+        }
+        Ok(())
+    }
+}
+
+/// Reads the number from names like `ExGFX80.bin` or `ExGFX1A0.bin`, case-insensitively.
+pub fn file_num_from_path(path: &Path) -> Option<usize> {
+    if !path.extension()?.to_str()?.eq_ignore_ascii_case(EXGFX_FILE_EXTENSION) {
+        return None;
+    }
+    let stem = path.file_stem()?.to_str()?;
+    let prefix = stem.get(..EXGFX_FILE_PREFIX.len())?;
+    let number = &stem[EXGFX_FILE_PREFIX.len()..];
+    if !prefix.eq_ignore_ascii_case(EXGFX_FILE_PREFIX) || !number.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+    usize::from_str_radix(number, 16).ok().filter(|num| (FIRST_EXGFX_NUM..=LAST_EXGFX_NUM).contains(num))
+}
+
+pub fn file_name(file_num: usize) -> String {
+    format!("{EXGFX_FILE_PREFIX}{file_num:X}.{EXGFX_FILE_EXTENSION}")
+}
+
+/// ExGFX files don't record the format of their tiles, which depends on the layer they are loaded into. The format
+/// in which the file fills a GFX slot is preferred, then 3BPP used by most GFX files.
+fn guess_tile_format(size: usize) -> TileFormat {
+    use TileFormat::*;
+    [Tile3bpp, Tile2bpp, Tile4bpp]
+        .into_iter()
+        .find(|format| size == TILES_PER_GFX_SLOT * format.tile_size())
+        .or_else(|| [Tile3bpp, Tile4bpp].into_iter().find(|format| size % format.tile_size() == 0))
+        .unwrap_or(Tile2bpp)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_file_num_from_path() {
+        assert_eq!(file_num_from_path(Path::new("ExGraphics/ExGFX80.bin")), Some(0x80));
+        assert_eq!(file_num_from_path(Path::new("exgfx1a0.BIN")), Some(0x1A0));
+        assert_eq!(file_num_from_path(Path::new(&file_name(0xFFF))), Some(0xFFF));
+        assert_eq!(file_num_from_path(Path::new("ExGFX7F.bin")), None);
+        assert_eq!(file_num_from_path(Path::new("ExGFX1000.bin")), None);
+        assert_eq!(file_num_from_path(Path::new("ExGFX+80.bin")), None);
+        assert_eq!(file_num_from_path(Path::new("ExGFX80.png")), None);
+        assert_eq!(file_num_from_path(Path::new("GFX00.bin")), None);
+    }
+
+    #[test]
+    fn test_guess_tile_format() {
+        use TileFormat::*;
+        assert_eq!(guess_tile_format(0x800), Tile2bpp);
+        assert_eq!(guess_tile_format(0xC00), Tile3bpp);
+        assert_eq!(guess_tile_format(0x1000), Tile4bpp);
+        assert_eq!(guess_tile_format(0x2000), Tile4bpp);
+        assert_eq!(guess_tile_format(0x600), Tile3bpp);
+        assert_eq!(guess_tile_format(0x620), Tile4bpp);
+        assert_eq!(guess_tile_format(0x48), Tile3bpp);
+        assert_eq!(guess_tile_format(0x50), Tile2bpp);
+    }
+
+    #[test]
+    fn test_insert() {
+        let compression = Compression::LcLz2 { little_endian_in_repeat: false };
+        let mut exgfx = ExGfx { files: BTreeMap::new(), compression };
+        let file = GfxFile::from_uncompressed_bytes(&[0xFF; 0x30], TileFormat::Tile3bpp, compression).unwrap();
+        assert_eq!(file.tiles.len(), 2);
+        assert!(matches!(exgfx.insert(0x7F, file.clone()), Err(ExGfxError::Number(0x7F))));
+        assert!(exgfx.insert(0x100, file.clone()).unwrap().is_none());
+
+        exgfx.files.get_mut(&0x100).unwrap().addr = Some(AddrSnes(0x108000));
+        let padded = GfxFile::from_uncompressed_bytes(&[0xFF; 0x31], TileFormat::Tile3bpp, compression).unwrap();
+        assert_eq!(padded.tiles.len(), 3);
+        assert_eq!(exgfx.insert(0x100, padded).unwrap().unwrap().tiles, file.tiles);
+        assert_eq!(exgfx.files[&0x100].addr, Some(AddrSnes(0x108000)));
+        assert_eq!(exgfx.get(0x100).unwrap().tiles.len(), 3);
+    }
+}
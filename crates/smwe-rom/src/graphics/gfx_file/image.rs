@@ -0,0 +1,256 @@
+//! Conversion of GFX files to and from indexed PNG images, laid out like in Lunar Magic and YY-CHR: tiles go left to
+//! right in rows of [`TILES_PER_IMAGE_ROW`], top to bottom.
+//!
+//! Imported images don't need to be indexed. Colors of pixels are matched against the palette with the precision
+//! of SNES colors, so that the image survives being saved as RGB by an editor.
+
+use epaint::Color32;
+use png::{BitDepth, ColorType, Decoder, Encoder, Transformations};
+use smwe_render::color::Abgr1555;
+use thiserror::Error;
+
+use crate::{
+    compression::Compression,
+    graphics::gfx_file::{GfxFile, Tile, TileFormat, N_PIXELS_IN_TILE},
+};
+
+// -------------------------------------------------------------------------------------------------
+
+#[derive(Debug, Error)]
+pub enum GfxImageError {
+    #[error("Encoding PNG image:\n- {0}")]
+    Encode(png::EncodingError),
+    #[error("Decoding PNG image:\n- {0}")]
+    Decode(png::DecodingError),
+    #[error("Image size {0}x{1} is not a multiple of 8x8 tiles")]
+    Size(u32, u32),
+    #[error("Image has {found} tiles, but the GFX file needs {expected}")]
+    TileCount { expected: usize, found: usize },
+    #[error("Tile {tile:X} uses {count} colors, but only {max} are available in the {tile_format} format")]
+    TooManyColors { tile: usize, count: usize, max: usize, tile_format: TileFormat },
+    #[error("Color #{:02X}{:02X}{:02X} of pixel ({x}, {y}) is not in the palette", color[0], color[1], color[2])]
+    ColorNotInPalette { x: usize, y: usize, color: [u8; 4] },
+}
+
+// -------------------------------------------------------------------------------------------------
+
+pub const TILES_PER_IMAGE_ROW: usize = 16;
+
+// -------------------------------------------------------------------------------------------------
+
+impl TileFormat {
+    /// Number of colors a tile in this format can use.
+    pub fn color_count(self) -> usize {
+        use TileFormat::*;
+        match self {
+            Tile2bpp => 4,
+            Tile3bpp | Tile3bppMode7 => 8,
+            Tile4bpp => 16,
+            Tile8bpp => 256,
+        }
+    }
+}
+
+impl GfxFile {
+    /// Encodes the tiles as an indexed PNG image, with the first [`TileFormat::color_count`] colors of the palette,
+    /// e.g. a row returned by [`ColorPalette::get_row`], as the image's palette. The last row of tiles is padded with
+    /// tiles of color 0.
+    ///
+    /// [`ColorPalette::get_row`]: crate::graphics::palette::ColorPalette::get_row
+    pub fn to_png(&self, palette: &[Abgr1555]) -> Result<Vec<u8>, GfxImageError> {
+        let (width, height) = image_size(self.tiles.len());
+
+        let mut pixels = vec![0; width * height];
+        for (tile_num, tile) in self.tiles.iter().enumerate() {
+            for (i, &color_idx) in tile.color_indices.iter().enumerate() {
+                pixels[pixel_index(tile_num, i, width)] = color_idx;
+            }
+        }
+
+        let mut plte = Vec::with_capacity(self.tile_format.color_count() * 3);
+        let mut trns = Vec::with_capacity(self.tile_format.color_count());
+        for color_idx in 0..self.tile_format.color_count() {
+            let color = Color32::from(palette.get(color_idx).copied().unwrap_or(Abgr1555::MAGENTA));
+            plte.extend([color.r(), color.g(), color.b()]);
+            trns.push(color.a());
+        }
+
+        let mut bytes = Vec::new();
+        let mut encoder = Encoder::new(&mut bytes, width as u32, height as u32);
+        encoder.set_color(ColorType::Indexed);
+        encoder.set_depth(BitDepth::Eight);
+        encoder.set_palette(plte);
+        encoder.set_trns(trns);
+        let mut writer = encoder.write_header().map_err(GfxImageError::Encode)?;
+        writer.write_image_data(&pixels).map_err(GfxImageError::Encode)?;
+        writer.finish().map_err(GfxImageError::Encode)?;
+        Ok(bytes)
+    }
+
+    /// Reads all tiles of a PNG image, matching colors of their pixels against the first
+    /// [`TileFormat::color_count`] colors of the palette. Transparent pixels get the first transparent color.
+    pub fn from_png(
+        bytes: &[u8], palette: &[Abgr1555], tile_format: TileFormat, compression: Compression,
+    ) -> Result<Self, GfxImageError> {
+        let (pixels, width, height) = decode_rgba(bytes)?;
+        let palette = &palette[..palette.len().min(tile_format.color_count())];
+        let tile_count = (width / 8) * (height / 8);
+
+        let mut tiles = Vec::with_capacity(tile_count);
+        for tile_num in 0..tile_count {
+            let tile_pixels: Vec<[u8; 4]> =
+                (0..N_PIXELS_IN_TILE).map(|i| pixels[pixel_index(tile_num, i, width)]).collect();
+
+            let mut colors = tile_pixels.iter().map(|&color| snes_color(color)).collect::<Vec<_>>();
+            colors.sort_unstable();
+            colors.dedup();
+            if colors.len() > tile_format.color_count() {
+                return Err(GfxImageError::TooManyColors {
+                    tile: tile_num,
+                    count: colors.len(),
+                    max: tile_format.color_count(),
+                    tile_format,
+                });
+            }
+
+            let color_indices = tile_pixels
+                .iter()
+                .enumerate()
+                .map(|(i, &color)| {
+                    let color_idx = palette.iter().position(|&c| snes_color_of(c) == snes_color(color));
+                    color_idx.map(|idx| idx as u8).ok_or_else(|| {
+                        let pixel = pixel_index(tile_num, i, width);
+                        GfxImageError::ColorNotInPalette { x: pixel % width, y: pixel / width, color }
+                    })
+                })
+                .collect::<Result<_, _>>()?;
+            tiles.push(Tile { color_indices });
+        }
+
+        Ok(Self { tile_format, tiles, compression })
+    }
+
+    /// Replaces the tiles with the ones from a PNG image, in the file's format and compression. The image must have
+    /// at least as many tiles as the file, the ones past them are ignored as padding.
+    pub fn import_png(&mut self, bytes: &[u8], palette: &[Abgr1555]) -> Result<(), GfxImageError> {
+        let mut imported = Self::from_png(bytes, palette, self.tile_format, self.compression)?;
+        if imported.tiles.len() < self.tiles.len() {
+            return Err(GfxImageError::TileCount { expected: self.tiles.len(), found: imported.tiles.len() });
+        }
+        imported.tiles.truncate(self.tiles.len());
+        self.tiles = imported.tiles;
+        Ok(())
+    }
+}
+
+// -------------------------------------------------------------------------------------------------
+
+fn image_size(tile_count: usize) -> (usize, usize) {
+    let rows = (tile_count + TILES_PER_IMAGE_ROW - 1) / TILES_PER_IMAGE_ROW;
+    (TILES_PER_IMAGE_ROW * 8, rows.max(1) * 8)
+}
+
+/// Index in the image of the `i`th pixel of a tile.
+fn pixel_index(tile_num: usize, i: usize, width: usize) -> usize {
+    let tiles_per_row = width / 8;
+    let (tile_x, tile_y) = (tile_num % tiles_per_row, tile_num / tiles_per_row);
+    let (x, y) = (tile_x * 8 + i % 8, tile_y * 8 + i / 8);
+    y * width + x
+}
+
+/// Decodes an image of any color type into RGBA pixels, along with its width and height.
+fn decode_rgba(bytes: &[u8]) -> Result<(Vec<[u8; 4]>, usize, usize), GfxImageError> {
+    let mut decoder = Decoder::new(bytes);
+    decoder.set_transformations(Transformations::normalize_to_color8());
+    let mut reader = decoder.read_info().map_err(GfxImageError::Decode)?;
+    let mut buf = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut buf).map_err(GfxImageError::Decode)?;
+    if info.width == 0 || info.height == 0 || info.width % 8 != 0 || info.height % 8 != 0 {
+        return Err(GfxImageError::Size(info.width, info.height));
+    }
+
+    let (width, height) = (info.width as usize, info.height as usize);
+    let channels = info.color_type.samples();
+    let pixels = buf[..info.buffer_size()]
+        .chunks_exact(info.line_size)
+        .flat_map(|line| line[..width * channels].chunks_exact(channels))
+        .map(|pixel| match *pixel {
+            [l] => [l, l, l, 0xFF],
+            [l, a] => [l, l, l, a],
+            [r, g, b] => [r, g, b, 0xFF],
+            [r, g, b, a] => [r, g, b, a],
+            _ => unreachable!("colors are normalized to at most 4 channels"),
+        })
+        .collect();
+    Ok((pixels, width, height))
+}
+
+/// Color with the precision of SNES colors, with all transparent colors being the same.
+fn snes_color([r, g, b, a]: [u8; 4]) -> u16 {
+    if a == 0 {
+        Abgr1555::TRANSPARENT.0
+    } else {
+        ((b as u16 >> 3) << 10) | ((g as u16 >> 3) << 5) | (r as u16 >> 3)
+    }
+}
+
+fn snes_color_of(color: Abgr1555) -> u16 {
+    if color.0 & Abgr1555::TRANSPARENT.0 != 0 {
+        Abgr1555::TRANSPARENT.0
+    } else {
+        color.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn palette() -> Vec<Abgr1555> {
+        let mut palette = vec![Abgr1555::TRANSPARENT, Abgr1555::WHITE, Abgr1555::RED, Abgr1555::GREEN];
+        palette.extend((4..16).map(|i| Abgr1555(i * 0x421)));
+        palette
+    }
+
+    fn gfx_file(tile_count: usize) -> GfxFile {
+        let tiles = (0..tile_count)
+            .map(|t| Tile { color_indices: (0..N_PIXELS_IN_TILE).map(|i| ((i + t) % 8) as u8).collect() })
+            .collect();
+        GfxFile { tile_format: TileFormat::Tile3bpp, tiles, compression: Compression::LcRle1 }
+    }
+
+    #[test]
+    fn test_png_round_trip() {
+        let file = gfx_file(20);
+        let png = file.to_png(&palette()).unwrap();
+        let (pixels, width, height) = decode_rgba(&png).unwrap();
+        assert_eq!((width, height), (128, 16));
+        assert_eq!(pixels[0][3], 0);
+        assert_eq!(pixels[1], [0xFF, 0xFF, 0xFF, 0xFF]);
+        assert_eq!(pixels[8], [0xFF, 0xFF, 0xFF, 0xFF]);
+
+        let mut imported = gfx_file(20);
+        imported.tiles.iter_mut().for_each(|tile| tile.color_indices.fill(0));
+        imported.import_png(&png, &palette()).unwrap();
+        assert_eq!(imported.tiles, file.tiles);
+
+        let mut larger = gfx_file(40);
+        assert!(matches!(
+            larger.import_png(&png, &palette()),
+            Err(GfxImageError::TileCount { expected: 40, found: 32 })
+        ));
+    }
+
+    #[test]
+    fn test_png_errors() {
+        let file = gfx_file(1);
+        let mut other_palette = palette();
+        other_palette[3] = Abgr1555::BLUE;
+        let png = file.to_png(&other_palette).unwrap();
+        let error = GfxFile::from_png(&png, &palette(), TileFormat::Tile3bpp, Compression::LcRle1).unwrap_err();
+        assert!(matches!(error, GfxImageError::ColorNotInPalette { x: 3, y: 0, color: [0, 0, 0xFF, 0xFF] }));
+
+        let error = GfxFile::from_png(&png, &other_palette, TileFormat::Tile2bpp, Compression::LcRle1).unwrap_err();
+        assert!(matches!(error, GfxImageError::TooManyColors { tile: 0, count: 8, max: 4, .. }));
+    }
+}
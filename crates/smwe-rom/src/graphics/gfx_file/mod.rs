@@ -1,4 +1,5 @@
 mod data;
+pub mod image;
 
 use std::fmt::{self, Display, Formatter};
 
@@ -9,7 +10,8 @@ use smwe_render::color::Abgr1555;
 use thiserror::Error;
 
 use crate::{
-    compression::{lc_lz2, DecompressionError},
+    compression::{Compression, DecompressionError},
+    snes_utils::addr::AddrSnes,
     DataBlock,
     DataKind,
     RomDisassembly,
@@ -43,7 +45,7 @@ pub enum TileFormat {
     Tile3bppMode7,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Eq, PartialEq)]
 pub struct Tile {
     pub color_indices: Box<[u8]>,
 }
@@ -52,6 +54,8 @@ pub struct Tile {
 pub struct GfxFile {
     pub tile_format: TileFormat,
     pub tiles:       Vec<Tile>,
+    /// Compression the file was read with, and which it is compressed with when encoded.
+    pub compression: Compression,
 }
 
 // -------------------------------------------------------------------------------------------------
@@ -146,6 +150,67 @@ impl Tile {
         Ok((input, tile))
     }
 
+    pub fn to_2bpp(&self) -> Vec<u8> {
+        self.to_xbpp(2)
+    }
+
+    pub fn to_3bpp(&self) -> Vec<u8> {
+        let mut bytes = vec![0; 24];
+        for (i, &color_idx) in self.color_indices.iter().enumerate() {
+            let (row, col) = (i / 8, 7 - (i % 8));
+            bytes[2 * row] |= (color_idx & 1) << col;
+            bytes[2 * row + 1] |= ((color_idx >> 1) & 1) << col;
+            bytes[16 + row] |= ((color_idx >> 2) & 1) << col;
+        }
+        bytes
+    }
+
+    pub fn to_4bpp(&self) -> Vec<u8> {
+        self.to_xbpp(4)
+    }
+
+    pub fn to_8bpp(&self) -> Vec<u8> {
+        self.to_xbpp(8)
+    }
+
+    /// Bits of color indices that don't fit in `x` bits are dropped.
+    fn to_xbpp(&self, x: usize) -> Vec<u8> {
+        debug_assert!([2, 4, 8].contains(&x));
+        let mut bytes = vec![0; x * 8];
+        for (i, &color_idx) in self.color_indices.iter().enumerate() {
+            let (row, col) = (i / 8, 7 - (i % 8));
+            for bit_idx in 0..x {
+                let byte_idx = (2 * row) + (16 * (bit_idx / 2)) + (bit_idx % 2);
+                bytes[byte_idx] |= ((color_idx >> bit_idx) & 1) << col;
+            }
+        }
+        bytes
+    }
+
+    pub fn to_3bpp_mode7(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(24);
+        for row in self.color_indices.chunks(8) {
+            let raw_row = row
+                .iter()
+                .enumerate()
+                .fold(0u32, |raw_row, (row_pixel, &index)| raw_row | ((index as u32 & 0b111) << (3 * (7 - row_pixel))));
+            bytes.extend(&raw_row.to_be_bytes()[1..]);
+        }
+        bytes
+    }
+
+    /// Encodes the tile in the given format, dropping bits of color indices which don't fit in it.
+    pub fn to_format(&self, tile_format: TileFormat) -> Vec<u8> {
+        use TileFormat::*;
+        match tile_format {
+            Tile2bpp => self.to_2bpp(),
+            Tile3bpp => self.to_3bpp(),
+            Tile4bpp => self.to_4bpp(),
+            Tile8bpp => self.to_8bpp(),
+            Tile3bppMode7 => self.to_3bpp_mode7(),
+        }
+    }
+
     pub fn to_bgr555(&self, palette: &[Abgr1555]) -> Box<[Abgr1555]> {
         self.color_indices
             .iter()
@@ -188,36 +253,130 @@ impl Tile {
 }
 
 impl GfxFile {
-    pub fn new(disasm: &mut RomDisassembly, file_num: usize, revised_gfx: bool) -> Result<Self, GfxFileParseError> {
+    pub fn new(
+        disasm: &mut RomDisassembly, file_num: usize, compression: Compression,
+    ) -> Result<Self, GfxFileParseError> {
         debug_assert!(file_num < GFX_FILES_META.len());
 
+        let (tile_format, slice) = GFX_FILES_META[file_num];
+        let tiles = disasm
+            .rom_slice_at_block(DataBlock { slice, kind: DataKind::GfxFile }, Self::map_rom_error)?
+            .decompress(move |slice| compression.decompress(slice).map(|(data, _)| data))?
+            .view()
+            .parse(|input| Self::parse_tiles(input, tile_format))?;
+
+        Ok(Self { tile_format, tiles, compression })
+    }
+
+    /// Parses a GFX file that has been moved from its original location, e.g. by Lunar Magic. The size of compressed
+    /// data is determined by decompressing it.
+    pub fn new_at(
+        disasm: &mut RomDisassembly, file_num: usize, addr: AddrSnes, compression: Compression,
+    ) -> Result<Self, GfxFileParseError> {
+        debug_assert!(file_num < GFX_FILES_META.len());
+
+        let (tile_format, _) = GFX_FILES_META[file_num];
+        let tiles = disasm.parse_and_mark_data(addr, DataKind::GfxFile, Self::map_rom_error, |rom_view| {
+            let (data, compressed_size) =
+                compression.decompress(rom_view.as_bytes()?).map_err(GfxFileParseError::DecompressingData)?;
+            let (_, tiles) = Self::parse_tiles(&data, tile_format).map_err(|_| GfxFileParseError::ParsingTile)?;
+            Ok((tiles, compressed_size))
+        })?;
+
+        Ok(Self { tile_format, tiles, compression })
+    }
+
+    /// Parses tiles from data that isn't stored in the ROM, e.g. read from a file. Data that doesn't end on a whole
+    /// tile is padded with zeros.
+    pub fn from_uncompressed_bytes(
+        bytes: &[u8], tile_format: TileFormat, compression: Compression,
+    ) -> Result<Self, GfxFileParseError> {
+        let tile_size = tile_format.tile_size();
+        let mut data = bytes.to_vec();
+        data.resize((bytes.len() + tile_size - 1) / tile_size * tile_size, 0);
+        let (_, tiles) = Self::parse_tiles(&data, tile_format).map_err(|_| GfxFileParseError::ParsingTile)?;
+        Ok(Self { tile_format, tiles, compression })
+    }
+
+    fn parse_tiles(input: &[u8], tile_format: TileFormat) -> IResult<&[u8], Vec<Tile>> {
         use TileFormat::*;
         type ParserFn = fn(&[u8]) -> IResult<&[u8], Tile>;
 
-        let (tile_format, slice) = GFX_FILES_META[file_num];
-        let (tile_parser, tile_size_bytes): (ParserFn, usize) = match tile_format {
-            Tile2bpp => (Tile::from_2bpp, 2 * 8),
-            Tile3bpp => (Tile::from_3bpp, 3 * 8),
-            Tile4bpp => (Tile::from_4bpp, 4 * 8),
-            Tile8bpp => (Tile::from_8bpp, 8 * 8),
-            Tile3bppMode7 => (Tile::from_3bpp_mode7, 3 * 8),
+        let tile_parser: ParserFn = match tile_format {
+            Tile2bpp => Tile::from_2bpp,
+            Tile3bpp => Tile::from_3bpp,
+            Tile4bpp => Tile::from_4bpp,
+            Tile8bpp => Tile::from_8bpp,
+            Tile3bppMode7 => Tile::from_3bpp_mode7,
         };
+        many1(map_parser(take(tile_format.tile_size()), tile_parser))(input)
+    }
 
-        let tiles = disasm
-            .rom_slice_at_block(DataBlock { slice, kind: DataKind::GfxFile }, |e| match e {
-                RomError::SliceSnes(_) | RomError::SlicePc(_) => GfxFileParseError::IsolatingData(e),
-                RomError::Decompress(DecompressionError::LcLz2(l)) => GfxFileParseError::DecompressingData(l.into()),
-                RomError::Parse => GfxFileParseError::ParsingTile,
-                _ => unreachable!(),
-            })?
-            .decompress(move |slice| lc_lz2::decompress(slice, revised_gfx))?
-            .view()
-            .parse(many1(map_parser(take(tile_size_bytes), tile_parser)))?;
-
-        Ok(Self { tile_format, tiles })
+    fn map_rom_error(e: RomError) -> GfxFileParseError {
+        match e {
+            RomError::SliceSnes(_) | RomError::SlicePc(_) => GfxFileParseError::IsolatingData(e),
+            RomError::Decompress(d) => GfxFileParseError::DecompressingData(d),
+            RomError::Parse => GfxFileParseError::ParsingTile,
+            _ => unreachable!(),
+        }
     }
 
     pub fn n_pixels(&self) -> usize {
         self.tiles.len() * N_PIXELS_IN_TILE
     }
+
+    /// Encodes all tiles in the file's format, without compressing them.
+    pub fn to_uncompressed_bytes(&self) -> Vec<u8> {
+        self.tiles.iter().flat_map(|tile| tile.to_format(self.tile_format)).collect()
+    }
+
+    /// Encodes all tiles in the file's format and compresses them, as they are stored in the ROM.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.compression.compress(&self.to_uncompressed_bytes())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_tile(mask: u8) -> Tile {
+        Tile { color_indices: (0..N_PIXELS_IN_TILE).map(|i| (i * 37) as u8 & mask).collect() }
+    }
+
+    #[test]
+    fn test_tile_round_trip() {
+        use TileFormat::*;
+        type ParserFn = fn(&[u8]) -> IResult<&[u8], Tile>;
+        let formats: [(TileFormat, u8, ParserFn); 5] = [
+            (Tile2bpp, 0b11, Tile::from_2bpp),
+            (Tile3bpp, 0b111, Tile::from_3bpp),
+            (Tile4bpp, 0b1111, Tile::from_4bpp),
+            (Tile8bpp, 0xFF, Tile::from_8bpp),
+            (Tile3bppMode7, 0b111, Tile::from_3bpp_mode7),
+        ];
+        for (tile_format, mask, parser) in formats {
+            let tile = test_tile(mask);
+            let bytes = tile.to_format(tile_format);
+            assert_eq!(bytes.len(), tile_format.tile_size(), "{tile_format}");
+            let (rest, decoded) = parser(&bytes).unwrap();
+            assert!(rest.is_empty());
+            assert_eq!(decoded, tile, "{tile_format}");
+        }
+
+        let mut tile = test_tile(0b11);
+        let bytes = tile.to_2bpp();
+        tile.color_indices[0] |= 0b100;
+        assert_eq!(tile.to_2bpp(), bytes);
+    }
+
+    #[test]
+    fn test_gfx_file_to_bytes() {
+        let compression = Compression::LcLz2 { little_endian_in_repeat: false };
+        let file = GfxFile { tile_format: TileFormat::Tile3bpp, tiles: vec![test_tile(7), test_tile(3)], compression };
+        let (decompressed, _) = compression.decompress(&file.to_bytes()).unwrap();
+        assert_eq!(decompressed, file.to_uncompressed_bytes());
+        let (_, tiles) = GfxFile::parse_tiles(&decompressed, file.tile_format).unwrap();
+        assert_eq!(tiles, file.tiles);
+    }
 }
@@ -1,25 +1,31 @@
 use thiserror::Error;
 
 use crate::{
+    compression::Compression,
     disassembler::RomDisassembly,
     graphics::{
+        exgfx::{ExGfx, ExGfxError},
         gfx_file::{GfxFile, Tile, GFX_FILES_META},
-        palette::ColorPalettes,
+        palette::{ColorPaletteSaveError, ColorPalettes},
+        sprite_gfx_list::SpriteGfxList,
     },
-    level::Level,
+    lunar_magic::{LunarMagic, FIRST_EXGFX_NUM},
     objects::{
         animated_tile_data::AnimatedTileData,
         map16::Block,
         object_gfx_list::ObjectGfxList,
         tilesets::TILESETS_COUNT,
     },
-    snes_utils::addr::AddrSnes,
+    snes_utils::{addr::AddrSnes, rom::RomError},
     RegionCode,
     RomInternalHeader,
 };
 
+pub mod exgfx;
 pub mod gfx_file;
 pub mod palette;
+pub mod palette_file;
+pub mod sprite_gfx_list;
 
 // -------------------------------------------------------------------------------------------------
 
@@ -27,6 +33,38 @@ pub mod palette;
 #[error("Cannot get GFX tile at WRAM address ${0:X}")]
 pub struct TileFromWramError(AddrSnes);
 
+#[derive(Debug, Error)]
+pub enum GfxListError {
+    #[error("GFX file {0:X} doesn't exist")]
+    MissingFile(usize),
+    #[error("GFX file {0:X} cannot be referenced by a GFX list, which holds numbers up to FF")]
+    FileNumber(usize),
+    #[error("GFX list {0:X} doesn't exist")]
+    List(usize),
+    #[error("GFX list slot {0} doesn't exist, each list loads 4 files")]
+    Slot(usize),
+}
+
+#[derive(Debug, Error)]
+pub enum BlockGfxError {
+    #[error("GFX file {0:X} doesn't exist")]
+    MissingFile(usize),
+    #[error("GFX file {0:X} doesn't have tile {1:X}")]
+    MissingTile(usize, usize),
+    #[error("Animation frame:\n- {0}")]
+    AnimationFrame(TileFromWramError),
+}
+
+#[derive(Debug, Error)]
+pub enum GfxSaveError {
+    #[error("Saving ExGFX files:\n- {0}")]
+    ExGfx(ExGfxError),
+    #[error("Writing GFX lists:\n- {0}")]
+    GfxList(RomError),
+    #[error("Saving color palettes:\n- {0}")]
+    ColorPalettes(ColorPaletteSaveError),
+}
+
 // -------------------------------------------------------------------------------------------------
 
 #[derive(Debug)]
@@ -38,39 +76,93 @@ pub enum BlockGfx<'t> {
 #[derive(Debug)]
 pub struct Gfx {
     pub files:              Vec<GfxFile>,
+    /// Files inserted by Lunar Magic, numbered after [`Gfx::files`] from [`FIRST_EXGFX_NUM`].
+    pub exgfx:              ExGfx,
     pub color_palettes:     ColorPalettes,
     pub object_gfx_list:    ObjectGfxList,
+    pub sprite_gfx_list:    SpriteGfxList,
     pub animated_tile_data: AnimatedTileData,
 }
 
 // -------------------------------------------------------------------------------------------------
 
 impl Gfx {
+    /// Files moved by Lunar Magic are read from their new locations, in the format in which Lunar Magic stores them.
     pub fn parse(
-        disasm: &mut RomDisassembly, levels: &[Level], internal_header: &RomInternalHeader,
+        disasm: &mut RomDisassembly, internal_header: &RomInternalHeader, lunar_magic: Option<&LunarMagic>,
     ) -> anyhow::Result<Self> {
         let revised_gfx =
             matches!(internal_header.region_code, RegionCode::Japan) || internal_header.version_number > 0;
+        let compression = Compression::LcLz2 { little_endian_in_repeat: revised_gfx };
 
         let mut files = Vec::with_capacity(GFX_FILES_META.len());
         for file_num in 0..GFX_FILES_META.len() {
-            let file = GfxFile::new(disasm, file_num, revised_gfx)?;
+            let moved_to = lunar_magic.and_then(|lm| Some((lm.gfx_file_addrs.get(file_num).copied()?, lm)));
+            let file = match moved_to {
+                Some((addr, lm)) => {
+                    GfxFile::new_at(disasm, file_num, addr, lm.gfx_compression().unwrap_or(compression))?
+                }
+                None => GfxFile::new(disasm, file_num, compression)?,
+            };
             files.push(file);
         }
 
+        let exgfx_compression = lunar_magic.and_then(LunarMagic::gfx_compression).unwrap_or(compression);
+        let exgfx = ExGfx::parse(disasm, lunar_magic, exgfx_compression);
+
         Ok(Self {
             files,
-            color_palettes: ColorPalettes::parse(disasm, levels)?,
+            exgfx,
+            color_palettes: ColorPalettes::parse(disasm, lunar_magic)?,
             object_gfx_list: ObjectGfxList::parse(disasm)?,
+            sprite_gfx_list: SpriteGfxList::parse(disasm)?,
             animated_tile_data: AnimatedTileData::parse(disasm)?,
         })
     }
 
+    /// Returns an original GFX file or an ExGFX file, by the number used in GFX lists.
+    pub fn file(&self, file_num: usize) -> Option<&GfxFile> {
+        if file_num < FIRST_EXGFX_NUM {
+            self.files.get(file_num)
+        } else {
+            self.exgfx.get(file_num)
+        }
+    }
+
+    /// Sets one of the files loaded into FG1–FG4 for the tileset, which must exist.
+    pub fn set_object_gfx_file(&mut self, tileset: usize, slot: usize, file_num: usize) -> Result<(), GfxListError> {
+        let file_num = self.check_gfx_list_file(file_num)?;
+        self.object_gfx_list.set_gfx_file_num(tileset, slot, file_num)
+    }
+
+    /// Sets one of the files loaded into SP1–SP4 for the sprite GFX setting, which must exist.
+    pub fn set_sprite_gfx_file(&mut self, sprite_gfx: usize, slot: usize, file_num: usize) -> Result<(), GfxListError> {
+        let file_num = self.check_gfx_list_file(file_num)?;
+        self.sprite_gfx_list.set_gfx_file_num(sprite_gfx, slot, file_num)
+    }
+
+    fn check_gfx_list_file(&self, file_num: usize) -> Result<u8, GfxListError> {
+        let list_num = u8::try_from(file_num).map_err(|_| GfxListError::FileNumber(file_num))?;
+        self.file(file_num).ok_or(GfxListError::MissingFile(file_num))?;
+        Ok(list_num)
+    }
+
+    /// Inserts ExGFX files and writes the GFX lists and color palettes.
+    pub fn write_to_rom(
+        &mut self, disasm: &mut RomDisassembly, mut lunar_magic: Option<&mut LunarMagic>,
+    ) -> Result<(), GfxSaveError> {
+        self.exgfx.write_to_rom(disasm, lunar_magic.as_deref_mut()).map_err(GfxSaveError::ExGfx)?;
+        self.color_palettes.write_to_rom(disasm, lunar_magic).map_err(GfxSaveError::ColorPalettes)?;
+        self.object_gfx_list.write_to_rom(disasm).map_err(GfxSaveError::GfxList)?;
+        self.sprite_gfx_list.write_to_rom(disasm).map_err(GfxSaveError::GfxList)
+    }
+
+    /// Fails if the tileset's GFX list references a file that doesn't exist, e.g. an ExGFX number that was skipped.
     #[allow(clippy::erasing_op)]
     pub fn tiles_from_block(
         &self, block: &Block, tileset: usize, blue_pswitch: bool, silver_pswitch: bool, on_off_switch: bool,
         offset: u16,
-    ) -> BlockGfx {
+    ) -> Result<BlockGfx, BlockGfxError> {
         assert!(tileset < TILESETS_COUNT);
 
         const BLANK_ANIM: [AddrSnes; 4] =
@@ -88,32 +180,35 @@ impl Gfx {
                 let ref_gfx = |tile| {
                     let file_num = self.object_gfx_list.gfx_file_for_object_tile(tile, tileset);
                     let tile_num = tile.tile_number() as usize % 0x80;
-                    &self.files[file_num].tiles[tile_num]
+                    let file = self.file(file_num).ok_or(BlockGfxError::MissingFile(file_num))?;
+                    file.tiles.get(tile_num).ok_or(BlockGfxError::MissingTile(file_num, tile_num))
                 };
-                BlockGfx::Static([
-                    ref_gfx(block.upper_left),
-                    ref_gfx(block.lower_left),
-                    ref_gfx(block.upper_right),
-                    ref_gfx(block.lower_right),
-                ])
+                Ok(BlockGfx::Static([
+                    ref_gfx(block.upper_left)?,
+                    ref_gfx(block.lower_left)?,
+                    ref_gfx(block.upper_right)?,
+                    ref_gfx(block.lower_right)?,
+                ]))
             }
             Some(frame_addrs) => {
                 let frames = frame_addrs
+                    .into_iter()
                     .map(|frame_addr| {
                         let first_tile_vramadrr = block.upper_left.tile_vram_addr(offset);
                         let upper_left_offset = block.upper_left.tile_vram_addr(offset) - first_tile_vramadrr;
                         let lower_left_offset = block.lower_left.tile_vram_addr(offset) - first_tile_vramadrr;
                         let upper_right_offset = block.upper_right.tile_vram_addr(offset) - first_tile_vramadrr;
                         let lower_right_offset = block.lower_right.tile_vram_addr(offset) - first_tile_vramadrr;
-                        [
-                            self.tile_from_wram(frame_addr + upper_left_offset).unwrap(),
-                            self.tile_from_wram(frame_addr + lower_left_offset).unwrap(),
-                            self.tile_from_wram(frame_addr + upper_right_offset).unwrap(),
-                            self.tile_from_wram(frame_addr + lower_right_offset).unwrap(),
-                        ]
+                        Ok([
+                            self.tile_from_wram(frame_addr + upper_left_offset)?,
+                            self.tile_from_wram(frame_addr + lower_left_offset)?,
+                            self.tile_from_wram(frame_addr + upper_right_offset)?,
+                            self.tile_from_wram(frame_addr + lower_right_offset)?,
+                        ])
                     })
-                    .to_vec();
-                BlockGfx::Animated(frames)
+                    .collect::<Result<_, _>>()
+                    .map_err(BlockGfxError::AnimationFrame)?;
+                Ok(BlockGfx::Animated(frames))
             }
         }
     }
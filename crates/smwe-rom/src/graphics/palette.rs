@@ -2,13 +2,15 @@ use std::ops::RangeInclusive;
 
 use duplicate::duplicate;
 use nom::{combinator::map, multi::many1, number::complete::le_u16};
-use paste::paste;
 use smwe_render::color::{Abgr1555, ABGR1555_SIZE};
 use thiserror::Error;
 
 use crate::{
-    level::{headers::PrimaryHeader, Level},
-    snes_utils::{addr::AddrSnes, rom_slice::SnesSlice},
+    disassembler::free_space::FREE_SPACE_BANKS,
+    graphics::palette_file::{PaletteFile, PaletteFileFormat},
+    level::{headers::PrimaryHeader, LEVEL_COUNT},
+    lunar_magic::{LunarMagic, LEVEL_PALETTE_SIZE},
+    snes_utils::{addr::AddrSnes, rom::RomError, rom_slice::SnesSlice},
     DataBlock,
     DataKind,
     RomDisassembly,
@@ -28,6 +30,8 @@ pub enum ColorPaletteError {
     LvSprite,
     #[error("Failed to construct an overworld submap's layer 2 palette.")]
     OwLayer2,
+    #[error("Color palette has {found} colors instead of {expected}.")]
+    Size { expected: usize, found: usize },
 }
 
 #[derive(Copy, Clone, Debug, Error)]
@@ -57,18 +61,63 @@ pub enum ColorPaletteParseError {
     LevelBerryPalette,
     #[error("Level Animated Color")]
     LevelAnimatedColor,
-    #[error("Level {0:X}'s Back Area Color")]
+    #[error("Level Back Area Color {0:X}")]
     LevelBackAreaColor(usize),
-    #[error("Level {0:X}'s Background Color Palette")]
+    #[error("Level Background Color Palette {0:X}")]
     LevelBackgroundPalette(usize),
-    #[error("Level {0:X}'s Foreground Color Palette")]
+    #[error("Level Foreground Color Palette {0:X}")]
     LevelForegroundPalette(usize),
-    #[error("Level {0:X}'s Sprite Color Palette")]
+    #[error("Level Sprite Color Palette {0:X}")]
     LevelSpritePalette(usize),
+    #[error("Level {0:X}'s Custom Color Palette")]
+    LevelCustomPalette(usize),
+}
+
+#[derive(Debug, Error)]
+pub enum ColorPaletteSaveError {
+    #[error("Writing {0}:\n- {1}")]
+    Write(ColorPaletteParseError, RomError),
+    #[error("Lunar Magic's table of custom palettes is not installed, level {0:X}'s palette cannot be saved")]
+    NoLevelPaletteTable(usize),
+    #[error("Not enough free space for level {0:X}'s custom palette ({1} bytes)")]
+    NoFreeSpace(usize, usize),
 }
 
 // -------------------------------------------------------------------------------------------------
 
+/// Number of back area colors and background, foreground and sprite palettes selectable in level headers.
+pub const LEVEL_PALETTE_TABLE_LEN: usize = 8;
+
+duplicate! {
+    [
+        const_name                  addr        size;
+        [PLAYER_PALETTE]            [0x00B2C8]  [4 * 0x14];
+        [OW_LAYER1_PALETTES]        [0x00B528]  [ABGR1555_SIZE * 7 * 6];
+        [OW_LAYER3_PALETTES]        [0x00B5EC]  [ABGR1555_SIZE * 8 * 2];
+        [OW_SPRITE_PALETTES]        [0x00B58A]  [ABGR1555_SIZE * 7 * 7];
+        [LV_WTF_PALETTE]            [0x00B250]  [ABGR1555_SIZE * ((0xD - 0x4 + 1) * (0x7 - 0x2 + 1))];
+        [LV_LAYER3_PALETTE]         [0x00B170]  [0x20];
+        [LV_BERRY_PALETTE]          [0x00B674]  [3 * 0x0E];
+        [LV_ANIMATED_COLOR]         [0x00B60C]  [ABGR1555_SIZE * 8];
+
+        [BACK_AREA_COLORS]          [0x00B0A0]  [ABGR1555_SIZE];
+        [BG_PALETTES]               [0x00B0B0]  [0x18];
+        [FG_PALETTES]               [0x00B190]  [0x18];
+        [SPRITE_PALETTES]           [0x00B318]  [0x18];
+
+        [LAYER2_NORMAL_PALETTES]    [0x00B3D8]  [ABGR1555_SIZE * 7 * 4];
+        [LAYER2_SPECIAL_PALETTES]   [0x00B732]  [ABGR1555_SIZE * 7 * 4];
+        [LAYER2_PALETTE_INDIRECT1]  [0x00AD1E]  [7];
+        [LAYER2_PALETTE_INDIRECT2]  [0x00ABDF]  [7];
+    ]
+    const const_name: SnesSlice = SnesSlice::new(AddrSnes(addr), size);
+}
+
+/// Colors of the common level palettes used by overworld palettes.
+const OW_WTF_COLORS: RangeInclusive<usize> = 23..=27;
+
+// -------------------------------------------------------------------------------------------------
+
 pub trait ColorPalette {
     fn set_colors(
         &mut self, //
@@ -175,6 +224,8 @@ pub struct LevelColorPaletteSet {
     pub bg_palettes:      Vec<Box<[Abgr1555]>>,
     pub fg_palettes:      Vec<Box<[Abgr1555]>>,
     pub sprite_palettes:  Vec<Box<[Abgr1555]>>,
+    /// Lunar Magic's custom palettes by level number, used in place of the shared palettes.
+    pub custom_palettes:  Vec<Option<PaletteFile>>,
 }
 
 #[derive(Debug, Clone)]
@@ -217,44 +268,50 @@ fn make_color_parser(
     }
 }
 
-impl ColorPalettes {
-    pub fn parse(disasm: &mut RomDisassembly, levels: &[Level]) -> Result<Self, ColorPaletteParseError> {
-        duplicate! {
-            [
-                const_name              addr        size;
-                [PLAYER_PALETTE]        [0x00B2C8]  [4 * 0x14];
-                [OW_LAYER1_PALETTES]    [0x00B528]  [ABGR1555_SIZE * 7 * 6];
-                [OW_LAYER3_PALETTES]    [0x00B5EC]  [ABGR1555_SIZE * 8 * 2];
-                [OW_SPRITE_PALETTES]    [0x00B58A]  [ABGR1555_SIZE * 7 * 7];
-                [LV_WTF_PALETTE]        [0x00B250]  [ABGR1555_SIZE * ((0xD - 0x4 + 1) * (0x7 - 0x2 + 1))];
-                [LV_LAYER3_PALETTE]     [0x00B170]  [0x20];
-                [LV_BERRY_PALETTE]      [0x00B674]  [3 * 0x0E];
-                [LV_ANIMATED_COLOR]     [0x00B60C]  [ABGR1555_SIZE * 8];
-            ]
-            const const_name: SnesSlice = SnesSlice::new(AddrSnes(addr), size);
-        }
+fn write_colors(
+    disasm: &mut RomDisassembly, slice: SnesSlice, colors: &[Abgr1555], what: ColorPaletteParseError,
+) -> Result<(), ColorPaletteSaveError> {
+    debug_assert_eq!(colors.len() * ABGR1555_SIZE, slice.size);
+    let bytes: Vec<u8> = colors.iter().flat_map(|color| color.0.to_le_bytes()).collect();
+    disasm.rom.write_lorom(slice.begin, &bytes).map_err(|e| ColorPaletteSaveError::Write(what, e))
+}
+
+fn check_size(colors: &[Abgr1555], slice: SnesSlice) -> Result<(), ColorPaletteError> {
+    let expected = slice.size / ABGR1555_SIZE;
+    if colors.len() == expected {
+        Ok(())
+    } else {
+        Err(ColorPaletteError::Size { expected, found: colors.len() })
+    }
+}
 
+impl ColorPalettes {
+    /// Custom palettes of levels are read from Lunar Magic's table, if it's installed.
+    pub fn parse(
+        disasm: &mut RomDisassembly, lunar_magic: Option<&LunarMagic>,
+    ) -> Result<Self, ColorPaletteParseError> {
         let mut parse_colors = make_color_parser(disasm);
-        let data_block = DataBlock::empty_with_kind(DataKind::ColorPaletteLevel);
+        let common_block = DataBlock::empty_with_kind(DataKind::ColorPaletteCommon);
+        let ow_block = DataBlock::empty_with_kind(DataKind::ColorPaletteOverworld);
 
         duplicate! {
             [
-                var_name                slice                   error;
-                [players]               [PLAYER_PALETTE]        [PlayerPalette];
-                [ow_layer1_colors]      [OW_LAYER1_PALETTES]    [OverworldLayer1Palette];
-                [ow_layer3_colors]      [OW_LAYER3_PALETTES]    [OverworldLayer3Palette];
-                [ow_sprite_colors]      [OW_SPRITE_PALETTES]    [OverworldSpritePalette];
-                [lv_wtf]                [LV_WTF_PALETTE]        [LevelMiscPalette];
-                [lv_layer3]             [LV_LAYER3_PALETTE]     [LevelLayer3Palette];
-                [lv_berry]              [LV_BERRY_PALETTE]      [LevelBerryPalette];
-                [lv_animated]           [LV_ANIMATED_COLOR]     [LevelAnimatedColor];
+                var_name                block           slice                   error;
+                [players]               [common_block]  [PLAYER_PALETTE]        [PlayerPalette];
+                [ow_layer1_colors]      [ow_block]      [OW_LAYER1_PALETTES]    [OverworldLayer1Palette];
+                [ow_layer3_colors]      [ow_block]      [OW_LAYER3_PALETTES]    [OverworldLayer3Palette];
+                [ow_sprite_colors]      [ow_block]      [OW_SPRITE_PALETTES]    [OverworldSpritePalette];
+                [lv_wtf]                [common_block]  [LV_WTF_PALETTE]        [LevelMiscPalette];
+                [lv_layer3]             [common_block]  [LV_LAYER3_PALETTE]     [LevelLayer3Palette];
+                [lv_berry]              [common_block]  [LV_BERRY_PALETTE]      [LevelBerryPalette];
+                [lv_animated]           [common_block]  [LV_ANIMATED_COLOR]     [LevelAnimatedColor];
             ]
-            let var_name = parse_colors(data_block.with_slice(slice), ColorPaletteParseError::error)?;
+            let var_name = parse_colors(block.with_slice(slice), ColorPaletteParseError::error)?;
         }
 
         drop(parse_colors);
 
-        let lv_specific_set = LevelColorPaletteSet::parse(disasm, levels)?;
+        let lv_specific_set = LevelColorPaletteSet::parse(disasm, lunar_magic)?;
         let ow_specific_set = OverworldColorPaletteSet::parse(disasm)?;
 
         Ok(ColorPalettes {
@@ -275,45 +332,130 @@ impl ColorPalettes {
         self.lv_specific_set.get_level_palette(header, self)
     }
 
+    /// Palette of the level as loaded in the game: its custom palette if it has one, the shared palettes selected by
+    /// its header otherwise.
+    pub fn get_palette_of_level(
+        &self, level_num: usize, header: &PrimaryHeader,
+    ) -> Result<SpecificLevelColorPalette, ColorPaletteError> {
+        let mut palette = self.get_level_palette(header)?;
+        if let Some(custom_palette) = self.lv_specific_set.custom_palettes.get(level_num).and_then(Option::as_ref) {
+            palette.import_palette_file(custom_palette);
+        }
+        Ok(palette)
+    }
+
     pub fn get_submap_palette(
         &self, submap: usize, ow_state: OverworldState,
     ) -> Result<SpecificOverworldColorPalette, ColorPaletteError> {
         self.ow_specific_set.get_submap_palette(submap, ow_state, self)
     }
-}
 
-impl LevelColorPaletteSet {
-    fn parse(disasm: &mut RomDisassembly, levels: &[Level]) -> Result<Self, ColorPaletteParseError> {
+    /// Stores all colors of the palette in the tables selected by the header, as well as in the palettes common to
+    /// all levels. This affects every level using the same tables.
+    pub fn set_level_palette(
+        &mut self, header: &PrimaryHeader, palette: &SpecificLevelColorPalette,
+    ) -> Result<(), ColorPaletteError> {
+        self.lv_specific_set.set_level_palette(header, palette)?;
         duplicate! {
             [
-                const_name              addr        size;
-                [BACK_AREA_COLORS]      [0x00B0A0]  [ABGR1555_SIZE];
-                [BG_PALETTES]           [0x00B0B0]  [0x18];
-                [FG_PALETTES]           [0x00B190]  [0x18];
-                [SPRITE_PALETTES]       [0x00B318]  [0x18];
+                palette_field   slice;
+                [players]       [PLAYER_PALETTE];
+                [wtf]           [LV_WTF_PALETTE];
+                [layer3]        [LV_LAYER3_PALETTE];
+                [berry]         [LV_BERRY_PALETTE];
+                [animated]      [LV_ANIMATED_COLOR];
             ]
-            const const_name: SnesSlice = SnesSlice::new(AddrSnes(addr), size);
+            check_size(&palette.palette_field, slice)?;
         }
+        self.players = palette.players.clone();
+        self.wtf = palette.wtf.clone();
+        self.lv_layer3 = palette.layer3.clone();
+        self.lv_berry = palette.berry.clone();
+        self.lv_animated = palette.animated.clone();
+        Ok(())
+    }
 
-        let mut parse_colors = make_color_parser(disasm);
+    /// Stores the colors of the submap's layer 2 palette, as well as the palettes common to all submaps.
+    pub fn set_submap_palette(
+        &mut self, submap: usize, ow_state: OverworldState, palette: &SpecificOverworldColorPalette,
+    ) -> Result<(), ColorPaletteError> {
+        duplicate! {
+            [
+                palette_field   slice;
+                [layer1]        [OW_LAYER1_PALETTES];
+                [layer3]        [OW_LAYER3_PALETTES];
+                [sprite]        [OW_SPRITE_PALETTES];
+                [players]       [PLAYER_PALETTE];
+            ]
+            check_size(&palette.palette_field, slice)?;
+        }
+        if palette.wtf.len() != OW_WTF_COLORS.count() {
+            return Err(ColorPaletteError::Size { expected: OW_WTF_COLORS.count(), found: palette.wtf.len() });
+        }
 
-        let mut palette_set = Self {
-            back_area_colors: Vec::with_capacity(8),
-            bg_palettes:      Vec::with_capacity(8),
-            fg_palettes:      Vec::with_capacity(8),
-            sprite_palettes:  Vec::with_capacity(8),
-        };
+        self.ow_specific_set.set_submap_palette(submap, ow_state, &palette.layer2)?;
+        self.ow_layer1 = palette.layer1.clone();
+        self.ow_layer3 = palette.layer3.clone();
+        self.ow_sprite = palette.sprite.clone();
+        self.players = palette.players.clone();
+        // The first color is replaced with white when the palette is loaded.
+        self.wtf[OW_WTF_COLORS][1..].copy_from_slice(&palette.wtf[1..]);
+        Ok(())
+    }
 
-        for (level_num, level) in levels.iter().enumerate() {
-            let header = &level.primary_header;
+    /// Writes all palettes into their tables, and custom palettes of levels into free space.
+    pub fn write_to_rom(
+        &self, disasm: &mut RomDisassembly, lunar_magic: Option<&mut LunarMagic>,
+    ) -> Result<(), ColorPaletteSaveError> {
+        use ColorPaletteParseError::*;
+        duplicate! {
+            [
+                field           slice                   error;
+                [players]       [PLAYER_PALETTE]        [PlayerPalette];
+                [ow_layer1]     [OW_LAYER1_PALETTES]    [OverworldLayer1Palette];
+                [ow_layer3]     [OW_LAYER3_PALETTES]    [OverworldLayer3Palette];
+                [ow_sprite]     [OW_SPRITE_PALETTES]    [OverworldSpritePalette];
+                [wtf]           [LV_WTF_PALETTE]        [LevelMiscPalette];
+                [lv_layer3]     [LV_LAYER3_PALETTE]     [LevelLayer3Palette];
+                [lv_berry]      [LV_BERRY_PALETTE]      [LevelBerryPalette];
+                [lv_animated]   [LV_ANIMATED_COLOR]     [LevelAnimatedColor];
+            ]
+            write_colors(disasm, slice, &self.field, error)?;
+        }
+        self.ow_specific_set.write_to_rom(disasm)?;
+        self.lv_specific_set.write_to_rom(disasm, lunar_magic)
+    }
+}
+
+impl LevelColorPaletteSet {
+    duplicate! {
+        [
+            setter                  field               slice               error;
+            [set_bg_palette]        [bg_palettes]       [BG_PALETTES]       [LvBackground];
+            [set_fg_palette]        [fg_palettes]       [FG_PALETTES]       [LvForeground];
+            [set_sprite_palette]    [sprite_palettes]   [SPRITE_PALETTES]   [LvSprite];
+        ]
+        pub fn setter(&mut self, index: usize, colors: &[Abgr1555]) -> Result<(), ColorPaletteError> {
+            check_size(colors, slice)?;
+            let palette = self.field.get_mut(index).ok_or(ColorPaletteError::error)?;
+            palette.copy_from_slice(colors);
+            Ok(())
+        }
+    }
 
-            let idx_bc = header.back_area_color() as usize;
-            let idx_bg = header.palette_bg() as usize;
-            let idx_fg = header.palette_fg() as usize;
-            let idx_sp = header.palette_sprite() as usize;
+    fn parse(disasm: &mut RomDisassembly, lunar_magic: Option<&LunarMagic>) -> Result<Self, ColorPaletteParseError> {
+        let mut parse_colors = make_color_parser(disasm);
+        let data_block = DataBlock::empty_with_kind(DataKind::ColorPaletteLevel);
 
-            let data_block = DataBlock::empty_with_kind(DataKind::ColorPaletteLevel);
+        let mut palette_set = Self {
+            back_area_colors: Vec::with_capacity(LEVEL_PALETTE_TABLE_LEN),
+            bg_palettes:      Vec::with_capacity(LEVEL_PALETTE_TABLE_LEN),
+            fg_palettes:      Vec::with_capacity(LEVEL_PALETTE_TABLE_LEN),
+            sprite_palettes:  Vec::with_capacity(LEVEL_PALETTE_TABLE_LEN),
+            custom_palettes:  vec![None; LEVEL_COUNT],
+        };
 
+        for idx in 0..LEVEL_PALETTE_TABLE_LEN {
             duplicate! {
                 [
                     vec     slice               error;
@@ -322,36 +464,32 @@ impl LevelColorPaletteSet {
                     [fg]    [FG_PALETTES]       [LevelForegroundPalette];
                     [sp]    [SPRITE_PALETTES]   [LevelSpritePalette];
                 ]
-                let vec = parse_colors(
-                    data_block.with_slice(slice.skip_forward(paste!([<idx_ vec>]))),
-                    ColorPaletteParseError::error(level_num),
-                )?;
+                let vec = parse_colors(data_block.with_slice(slice.skip_forward(idx)), ColorPaletteParseError::error(idx))?;
             }
 
-            duplicate! {
-                [
-                    sub_palette         index       val;
-                    [back_area_colors]  [idx_bc]    [Abgr1555::default()];
-                    duplicate! {
-                        [
-                            sp                  i           slice;
-                            [bg_palettes]       [idx_bg]    [BG_PALETTES];
-                            [fg_palettes]       [idx_fg]    [FG_PALETTES];
-                            [sprite_palettes]   [idx_sp]    [SPRITE_PALETTES];
-                        ]
-                        [sp][i][Box::from([Abgr1555::default(); slice.size / ABGR1555_SIZE])];
-                    }
-                ]
-                if palette_set.sub_palette.len() < index + 1 {
-                    let value = val;
-                    palette_set.sub_palette.resize(index + 1, value);
-                }
-            }
+            palette_set.back_area_colors.push(bc[0]);
+            palette_set.bg_palettes.push(bg.into());
+            palette_set.fg_palettes.push(fg.into());
+            palette_set.sprite_palettes.push(sp.into());
+        }
 
-            palette_set.back_area_colors[idx_bc] = bc[0];
-            palette_set.bg_palettes[idx_bg] = bg.into();
-            palette_set.fg_palettes[idx_fg] = fg.into();
-            palette_set.sprite_palettes[idx_sp] = sp.into();
+        drop(parse_colors);
+
+        for level_num in 0..LEVEL_COUNT {
+            let Some(addr) = lunar_magic.and_then(|lm| lm.level_palette_addr(level_num as u32)) else {
+                continue;
+            };
+            let block =
+                DataBlock { slice: SnesSlice::new(addr, LEVEL_PALETTE_SIZE), kind: DataKind::ColorPaletteLevel };
+            if let Err(e) = disasm.mark_data_block(block) {
+                log::warn!("Cannot mark level {level_num:X}'s custom palette at {addr}: {e}");
+            }
+            let bytes = disasm.rom.view().slice_lorom(block.slice).and_then(|view| view.as_bytes());
+            match bytes.map(|bytes| PaletteFile::from_bytes(bytes, PaletteFileFormat::Mw3)) {
+                Ok(Ok(palette)) => palette_set.custom_palettes[level_num] = Some(palette),
+                Ok(Err(e)) => log::warn!("Cannot parse level {level_num:X}'s custom palette at {addr}: {e}"),
+                Err(e) => log::warn!("Cannot read level {level_num:X}'s custom palette at {addr}: {e}"),
+            }
         }
 
         Ok(palette_set)
@@ -387,36 +525,127 @@ impl LevelColorPaletteSet {
             players:         palettes.players.clone(),
         })
     }
-}
 
-impl OverworldColorPaletteSet {
-    fn parse(disasm: &mut RomDisassembly) -> Result<OverworldColorPaletteSet, ColorPaletteParseError> {
-        let mut parse_colors = make_color_parser(disasm);
+    pub fn set_back_area_color(&mut self, index: usize, color: Abgr1555) -> Result<(), ColorPaletteError> {
+        *self.back_area_colors.get_mut(index).ok_or(ColorPaletteError::LvBackAreaColor)? = color;
+        Ok(())
+    }
+
+    /// Stores the back area color and the background, foreground and sprite palettes in the tables selected by the
+    /// header. The palettes common to all levels are left alone.
+    pub fn set_level_palette(
+        &mut self, header: &PrimaryHeader, palette: &SpecificLevelColorPalette,
+    ) -> Result<(), ColorPaletteError> {
+        check_size(&palette.background, BG_PALETTES)?;
+        check_size(&palette.foreground, FG_PALETTES)?;
+        check_size(&palette.sprite, SPRITE_PALETTES)?;
+        self.set_back_area_color(header.back_area_color() as usize, palette.back_area_color)?;
+        self.set_bg_palette(header.palette_bg() as usize, &palette.background)?;
+        self.set_fg_palette(header.palette_fg() as usize, &palette.foreground)?;
+        self.set_sprite_palette(header.palette_sprite() as usize, &palette.sprite)
+    }
+
+    /// Gives the level a custom palette with all colors of the given one, which is saved in free space and doesn't
+    /// affect other levels. `None` makes the level use the shared palette tables again.
+    pub fn set_custom_palette(&mut self, level_num: usize, palette: Option<&SpecificLevelColorPalette>) {
+        self.custom_palettes[level_num] = palette.map(SpecificLevelColorPalette::to_palette_file);
+    }
 
+    fn write_to_rom(
+        &self, disasm: &mut RomDisassembly, mut lunar_magic: Option<&mut LunarMagic>,
+    ) -> Result<(), ColorPaletteSaveError> {
+        for (idx, color) in self.back_area_colors.iter().enumerate() {
+            let slice = BACK_AREA_COLORS.skip_forward(idx);
+            write_colors(disasm, slice, std::slice::from_ref(color), ColorPaletteParseError::LevelBackAreaColor(idx))?;
+        }
         duplicate! {
             [
-                const_name                  addr        size;
-                [LAYER2_NORMAL_PALETTES]    [0x00B3D8]  [ABGR1555_SIZE * 7 * 4];
-                [LAYER2_SPECIAL_PALETTES]   [0x00B732]  [ABGR1555_SIZE * 7 * 4];
-                [LAYER2_PALETTE_INDIRECT1]  [0x00AD1E]  [7];
-                [LAYER2_PALETTE_INDIRECT2]  [0x00ABDF]  [7];
+                field               slice               error;
+                [bg_palettes]       [BG_PALETTES]       [LevelBackgroundPalette];
+                [fg_palettes]       [FG_PALETTES]       [LevelForegroundPalette];
+                [sprite_palettes]   [SPRITE_PALETTES]   [LevelSpritePalette];
             ]
-            const const_name: SnesSlice = SnesSlice::new(AddrSnes(addr), size);
+            for (idx, colors) in self.field.iter().enumerate() {
+                write_colors(disasm, slice.skip_forward(idx), colors, ColorPaletteParseError::error(idx))?;
+            }
+        }
+
+        for (level_num, custom_palette) in self.custom_palettes.iter().enumerate() {
+            Self::write_custom_palette(disasm, lunar_magic.as_deref_mut(), level_num, custom_palette.as_ref())?;
         }
+        Ok(())
+    }
+
+    fn write_custom_palette(
+        disasm: &mut RomDisassembly, lunar_magic: Option<&mut LunarMagic>, level_num: usize,
+        custom_palette: Option<&PaletteFile>,
+    ) -> Result<(), ColorPaletteSaveError> {
+        let write_error = |e| ColorPaletteSaveError::Write(ColorPaletteParseError::LevelCustomPalette(level_num), e);
+        let lm = match lunar_magic {
+            Some(lm) if lm.has_level_palettes() => lm,
+            _ if custom_palette.is_none() => return Ok(()),
+            _ => return Err(ColorPaletteSaveError::NoLevelPaletteTable(level_num)),
+        };
+
+        let old_addr = lm.level_palette_addr(level_num as u32);
+        let shared = old_addr.is_some_and(|addr| {
+            (0..LEVEL_COUNT as u32).filter(|&other| lm.level_palette_addr(other) == Some(addr)).count() > 1
+        });
+        let Some(custom_palette) = custom_palette else {
+            if let Some(old_addr) = old_addr {
+                lm.set_level_palette_addr(disasm, level_num as u32, None).map_err(write_error)?;
+                // A pointer outside of the ROM is dropped when parsing, there's no data to free then.
+                let readable = disasm.rom.view().slice_lorom(SnesSlice::new(old_addr, LEVEL_PALETTE_SIZE)).is_ok();
+                if !shared && readable {
+                    disasm.free(old_addr).map_err(write_error)?;
+                }
+            }
+            return Ok(());
+        };
+
+        let data = custom_palette.to_bytes(PaletteFileFormat::Mw3);
+        if let Some(old_addr) = old_addr {
+            let old_slice = SnesSlice::new(old_addr, LEVEL_PALETTE_SIZE);
+            let old_data = disasm.rom.view().slice_lorom(old_slice).and_then(|view| view.as_bytes());
+            if old_data.is_ok_and(|old_data| old_data == data) {
+                return Ok(());
+            }
+            if !shared {
+                return disasm.rom.write_lorom(old_addr, &data).map_err(write_error);
+            }
+        }
+
+        let new_addr = disasm.allocate(data.len(), FREE_SPACE_BANKS).map_err(|e| match e {
+            RomError::NoFreeSpace(size) => ColorPaletteSaveError::NoFreeSpace(level_num, size),
+            e => write_error(e),
+        })?;
+        disasm.rom.write_lorom(new_addr, &data).map_err(write_error)?;
+        disasm
+            .mark_data_block(DataBlock {
+                slice: SnesSlice::new(new_addr, data.len()),
+                kind:  DataKind::ColorPaletteLevel,
+            })
+            .map_err(write_error)?;
+        lm.set_level_palette_addr(disasm, level_num as u32, Some(new_addr)).map_err(write_error)
+    }
+}
+
+impl OverworldColorPaletteSet {
+    fn parse(disasm: &mut RomDisassembly) -> Result<OverworldColorPaletteSet, ColorPaletteParseError> {
+        let mut parse_colors = make_color_parser(disasm);
 
         let mut layer2_pre_special = Vec::with_capacity(6);
         let mut layer2_post_special = Vec::with_capacity(6);
         let mut layer2_indices = Vec::with_capacity(7);
 
         for i in 0..6 {
-            let subworld_pal_idx = i * 14 * 4;
             let data_block = DataBlock::empty_with_kind(DataKind::ColorPaletteOverworld);
             let layer2_colors_normal = parse_colors(
-                data_block.with_slice(LAYER2_NORMAL_PALETTES.offset_forward(subworld_pal_idx)),
+                data_block.with_slice(LAYER2_NORMAL_PALETTES.skip_forward(i)),
                 ColorPaletteParseError::OverworldLayer2NormalPalette(i),
             )?;
             let layer2_colors_special = parse_colors(
-                data_block.with_slice(LAYER2_SPECIAL_PALETTES.offset_forward(subworld_pal_idx)),
+                data_block.with_slice(LAYER2_SPECIAL_PALETTES.skip_forward(i)),
                 ColorPaletteParseError::OverworldLayer2SpecialPalette(i),
             )?;
 
@@ -471,11 +700,37 @@ impl OverworldColorPaletteSet {
             layer3:  palettes.ow_layer3.clone(),
             sprite:  palettes.ow_sprite.clone(),
             players: palettes.players.clone(),
-            wtf:     palettes.wtf.clone()[23..=27].into(),
+            wtf:     palettes.wtf[OW_WTF_COLORS].into(),
         };
         palette.wtf[0] = Abgr1555::WHITE;
         Ok(palette)
     }
+
+    /// Stores the submap's layer 2 palette, which other submaps may share.
+    pub fn set_submap_palette(
+        &mut self, submap: usize, ow_state: OverworldState, colors: &[Abgr1555],
+    ) -> Result<(), ColorPaletteError> {
+        check_size(colors, LAYER2_NORMAL_PALETTES)?;
+        let i_submap_palette = *self.layer2_indices.get(submap).ok_or(ColorPaletteError::OwLayer2)?;
+        let layer2_pal = match ow_state {
+            OverworldState::PreSpecial => &mut self.layer2_pre_special,
+            OverworldState::PostSpecial => &mut self.layer2_post_special,
+        };
+        layer2_pal.get_mut(i_submap_palette).ok_or(ColorPaletteError::OwLayer2)?.copy_from_slice(colors);
+        Ok(())
+    }
+
+    fn write_to_rom(&self, disasm: &mut RomDisassembly) -> Result<(), ColorPaletteSaveError> {
+        for (i, colors) in self.layer2_pre_special.iter().enumerate() {
+            let slice = LAYER2_NORMAL_PALETTES.skip_forward(i);
+            write_colors(disasm, slice, colors, ColorPaletteParseError::OverworldLayer2NormalPalette(i))?;
+        }
+        for (i, colors) in self.layer2_post_special.iter().enumerate() {
+            let slice = LAYER2_SPECIAL_PALETTES.skip_forward(i);
+            write_colors(disasm, slice, colors, ColorPaletteParseError::OverworldLayer2SpecialPalette(i))?;
+        }
+        Ok(())
+    }
 }
 
 impl_color_palette!(SpecificLevelColorPalette {
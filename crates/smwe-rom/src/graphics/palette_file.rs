@@ -0,0 +1,243 @@
+//! Palette files exchanged with Lunar Magic and tile editors. Colors are stored row by row, 16 colors per row:
+//!
+//! | Format | Contents                                                                                    |
+//! |--------|---------------------------------------------------------------------------------------------|
+//! | `.pal` | Up to 256 colors as 24-bit `RRGGBB`.                                                        |
+//! | `.tpl` | Tile Layer Pro's `TPL` followed by a type byte: `00` for 24-bit colors, `02` for SNES ones. |
+//! | `.mw3` | Up to 256 SNES colors in LE, followed by the back area color in full 256-color files.       |
+
+use std::{
+    fmt::{self, Display, Formatter},
+    path::Path,
+};
+
+use smwe_render::color::{Abgr1555, ABGR1555_SIZE};
+use thiserror::Error;
+
+use crate::graphics::palette::{ColorPalette, SpecificLevelColorPalette};
+
+// -------------------------------------------------------------------------------------------------
+
+#[derive(Debug, Error)]
+pub enum PaletteFileError {
+    #[error("{format} palette file of {size} bytes doesn't hold a whole number of colors, or holds more than 256")]
+    Size { format: PaletteFileFormat, size: usize },
+    #[error("Missing 'TPL' header")]
+    TplHeader,
+    #[error("Unsupported TPL palette type {0:#04X}")]
+    TplType(u8),
+}
+
+// -------------------------------------------------------------------------------------------------
+
+pub const PALETTE_FILE_COLOR_COUNT: usize = 16 * 16;
+
+const RGB888_SIZE: usize = 3;
+const TPL_MAGIC: &[u8] = b"TPL";
+const TPL_TYPE_RGB: u8 = 0x00;
+const TPL_TYPE_SNES: u8 = 0x02;
+
+// -------------------------------------------------------------------------------------------------
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum PaletteFileFormat {
+    Pal,
+    Tpl,
+    Mw3,
+}
+
+/// Colors of a palette file, from the first row and column.
+#[derive(Debug, Clone)]
+pub struct PaletteFile {
+    pub colors:          Vec<Abgr1555>,
+    /// Only stored in `.mw3` files.
+    pub back_area_color: Option<Abgr1555>,
+}
+
+// -------------------------------------------------------------------------------------------------
+
+impl Display for PaletteFileFormat {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Pal => "PAL",
+            Self::Tpl => "TPL",
+            Self::Mw3 => "MW3",
+        })
+    }
+}
+
+impl PaletteFileFormat {
+    pub const ALL: [Self; 3] = [Self::Pal, Self::Tpl, Self::Mw3];
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Pal => "pal",
+            Self::Tpl => "tpl",
+            Self::Mw3 => "mw3",
+        }
+    }
+
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let extension = path.extension()?.to_str()?;
+        Self::ALL.into_iter().find(|format| format.extension().eq_ignore_ascii_case(extension))
+    }
+}
+
+impl PaletteFile {
+    pub fn from_bytes(bytes: &[u8], format: PaletteFileFormat) -> Result<Self, PaletteFileError> {
+        let size_error = || PaletteFileError::Size { format, size: bytes.len() };
+        match format {
+            PaletteFileFormat::Pal => {
+                let colors = read_rgb888(bytes).ok_or_else(size_error)?;
+                Ok(Self { colors, back_area_color: None })
+            }
+            PaletteFileFormat::Tpl => {
+                let rest = bytes.strip_prefix(TPL_MAGIC).ok_or(PaletteFileError::TplHeader)?;
+                let (&tpl_type, colors) = rest.split_first().ok_or(PaletteFileError::TplHeader)?;
+                let colors = match tpl_type {
+                    TPL_TYPE_RGB => read_rgb888(colors),
+                    TPL_TYPE_SNES => read_abgr1555(colors),
+                    _ => return Err(PaletteFileError::TplType(tpl_type)),
+                };
+                Ok(Self { colors: colors.ok_or_else(size_error)?, back_area_color: None })
+            }
+            PaletteFileFormat::Mw3 => {
+                let full_size = PALETTE_FILE_COLOR_COUNT * ABGR1555_SIZE;
+                let (colors, back_area_color) = match bytes.split_at(bytes.len().min(full_size)) {
+                    (colors, &[low, high]) => (colors, Some(Abgr1555(u16::from_le_bytes([low, high])))),
+                    (_, []) => (bytes, None),
+                    _ => return Err(size_error()),
+                };
+                Ok(Self { colors: read_abgr1555(colors).ok_or_else(size_error)?, back_area_color })
+            }
+        }
+    }
+
+    /// Colors missing from `.mw3` files are filled with black, as the back area color follows the whole palette.
+    pub fn to_bytes(&self, format: PaletteFileFormat) -> Vec<u8> {
+        let colors = &self.colors[..self.colors.len().min(PALETTE_FILE_COLOR_COUNT)];
+        match format {
+            PaletteFileFormat::Pal => colors.iter().flat_map(|color| color.to_rgb888()).collect(),
+            PaletteFileFormat::Tpl => {
+                let mut bytes = [TPL_MAGIC, &[TPL_TYPE_SNES]].concat();
+                bytes.extend(colors.iter().flat_map(|color| color.0.to_le_bytes()));
+                bytes
+            }
+            PaletteFileFormat::Mw3 => {
+                let mut bytes: Vec<u8> = colors.iter().flat_map(|color| color.0.to_le_bytes()).collect();
+                if let Some(back_area_color) = self.back_area_color {
+                    bytes.resize(PALETTE_FILE_COLOR_COUNT * ABGR1555_SIZE, 0);
+                    bytes.extend(back_area_color.0.to_le_bytes());
+                }
+                bytes
+            }
+        }
+    }
+
+    /// All 256 colors of the palette, including the ones not stored in the ROM.
+    pub fn from_palette(palette: &impl ColorPalette) -> Self {
+        let colors = (0..16).flat_map(|row| palette.get_row(row)).collect();
+        Self { colors, back_area_color: None }
+    }
+
+    /// Sets colors of the palette, skipping the ones not stored in the ROM.
+    pub fn apply_to(&self, palette: &mut impl ColorPalette) {
+        for (idx, &color) in self.colors.iter().enumerate().take(PALETTE_FILE_COLOR_COUNT) {
+            palette.set_color_at(idx / 16, idx % 16, color);
+        }
+    }
+}
+
+impl SpecificLevelColorPalette {
+    pub fn to_palette_file(&self) -> PaletteFile {
+        PaletteFile { back_area_color: Some(self.back_area_color), ..PaletteFile::from_palette(self) }
+    }
+
+    pub fn import_palette_file(&mut self, file: &PaletteFile) {
+        file.apply_to(self);
+        if let Some(back_area_color) = file.back_area_color {
+            self.back_area_color = back_area_color;
+        }
+    }
+}
+
+fn read_rgb888(bytes: &[u8]) -> Option<Vec<Abgr1555>> {
+    (bytes.len() % RGB888_SIZE == 0 && bytes.len() <= PALETTE_FILE_COLOR_COUNT * RGB888_SIZE)
+        .then(|| bytes.chunks_exact(RGB888_SIZE).map(|rgb| Abgr1555::from_rgb888([rgb[0], rgb[1], rgb[2]])).collect())
+}
+
+fn read_abgr1555(bytes: &[u8]) -> Option<Vec<Abgr1555>> {
+    (bytes.len() % ABGR1555_SIZE == 0 && bytes.len() <= PALETTE_FILE_COLOR_COUNT * ABGR1555_SIZE).then(|| {
+        bytes.chunks_exact(ABGR1555_SIZE).map(|color| Abgr1555(u16::from_le_bytes([color[0], color[1]]))).collect()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn level_palette(seed: u16) -> SpecificLevelColorPalette {
+        let colors = |offset: u16, count: u16| -> Box<[Abgr1555]> {
+            (0..count).map(|i| Abgr1555(seed.wrapping_add(offset + i * 0x421) & 0x7FFF)).collect()
+        };
+        SpecificLevelColorPalette {
+            back_area_color: Abgr1555(seed),
+            background:      colors(1, 12),
+            foreground:      colors(2, 12),
+            sprite:          colors(3, 12),
+            players:         colors(4, 10),
+            wtf:             colors(5, 60),
+            layer3:          colors(6, 16),
+            berry:           colors(7, 21),
+            animated:        colors(8, 8),
+        }
+    }
+
+    #[test]
+    fn test_level_palette_round_trip() {
+        let palette = level_palette(0x1234);
+        let file = palette.to_palette_file();
+        assert_eq!(file.colors.len(), PALETTE_FILE_COLOR_COUNT);
+
+        for format in PaletteFileFormat::ALL {
+            let bytes = file.to_bytes(format);
+            let expected_size = match format {
+                PaletteFileFormat::Pal => 0x300,
+                PaletteFileFormat::Tpl => 0x204,
+                PaletteFileFormat::Mw3 => 0x202,
+            };
+            assert_eq!(bytes.len(), expected_size, "{format}");
+
+            let mut imported = level_palette(0);
+            imported.import_palette_file(&PaletteFile::from_bytes(&bytes, format).unwrap());
+            let reexported = imported.to_palette_file();
+            let colors = |file: &PaletteFile| file.colors.iter().map(|color| color.0).collect::<Vec<_>>();
+            assert_eq!(colors(&reexported), colors(&file), "{format}");
+            assert_eq!(reexported.to_bytes(format), bytes, "{format}");
+        }
+    }
+
+    #[test]
+    fn test_rgb888_quantization() {
+        let file = PaletteFile::from_bytes(&[0xFF, 0xF8, 0x07, 0x08, 0x10, 0x84], PaletteFileFormat::Pal).unwrap();
+        assert_eq!(file.colors.iter().map(|color| color.0).collect::<Vec<_>>(), [0x03FF, 0x4041]);
+        assert_eq!(file.to_bytes(PaletteFileFormat::Pal), [0xFF, 0xFF, 0x00, 0x08, 0x10, 0x84]);
+        assert_eq!(Abgr1555::WHITE.to_rgb888(), [0xFF; 3]);
+        assert_eq!(Abgr1555(0x7BDE).to_rgb888(), [0xF7; 3]);
+    }
+
+    #[test]
+    fn test_palette_file_errors() {
+        use PaletteFileFormat::*;
+        assert!(matches!(PaletteFile::from_bytes(&[0; 4], Pal), Err(PaletteFileError::Size { size: 4, .. })));
+        assert!(matches!(PaletteFile::from_bytes(&[0; 0x303], Pal), Err(PaletteFileError::Size { .. })));
+        assert!(matches!(PaletteFile::from_bytes(&[0; 0x203], Mw3), Err(PaletteFileError::Size { .. })));
+        assert!(matches!(PaletteFile::from_bytes(b"TPX\x02", Tpl), Err(PaletteFileError::TplHeader)));
+        assert!(matches!(PaletteFile::from_bytes(b"TPL\x01\x0F", Tpl), Err(PaletteFileError::TplType(1))));
+
+        let rgb_tpl = PaletteFile::from_bytes(b"TPL\x00\xF8\x00\x00", Tpl).unwrap();
+        assert_eq!(rgb_tpl.colors[0].0, Abgr1555::RED.0);
+        assert!(PaletteFile::from_bytes(&[0; 0x20], Mw3).unwrap().back_area_color.is_none());
+        assert_eq!(PaletteFileFormat::from_path(Path::new("Levels/105.MW3")), Some(Mw3));
+    }
+}
@@ -0,0 +1,65 @@
+use thiserror::Error;
+
+use crate::{graphics::GfxListError, AddrSnes, DataBlock, DataKind, RomDisassembly, RomError, SnesSlice};
+
+// -------------------------------------------------------------------------------------------------
+
+#[derive(Debug, Error)]
+#[error("Could not parse sprite GFX list at:\n- {0}")]
+pub struct SpriteGfxListParseError(pub SnesSlice);
+
+// -------------------------------------------------------------------------------------------------
+
+const SPRITE_GFX_LIST: SnesSlice = SnesSlice::new(AddrSnes(0x00A8C3), 26 * 4);
+
+// -------------------------------------------------------------------------------------------------
+
+/// Numbers of the four GFX files loaded into SP1–SP4 for each sprite GFX setting of the level header. Numbers from
+/// `80` refer to ExGFX files.
+#[derive(Debug)]
+pub struct SpriteGfxList {
+    gfx_file_nums: Vec<u8>,
+}
+
+// -------------------------------------------------------------------------------------------------
+
+impl SpriteGfxList {
+    pub fn parse(disasm: &mut RomDisassembly) -> Result<Self, SpriteGfxListParseError> {
+        let block = DataBlock { slice: SPRITE_GFX_LIST, kind: DataKind::GfxListSprites };
+        let gfx_file_nums =
+            disasm.rom_slice_at_block(block, |_| SpriteGfxListParseError(SPRITE_GFX_LIST))?.as_bytes()?.to_vec();
+        Ok(Self { gfx_file_nums })
+    }
+
+    /// Files loaded into SP1–SP4 for the sprite GFX setting.
+    pub fn gfx_file_nums(&self, sprite_gfx: usize) -> &[u8] {
+        &self.gfx_file_nums[sprite_gfx * 4..][..4]
+    }
+
+    pub fn set_gfx_file_num(&mut self, sprite_gfx: usize, slot: usize, file_num: u8) -> Result<(), GfxListError> {
+        if slot >= 4 {
+            return Err(GfxListError::Slot(slot));
+        }
+        let entry = self.gfx_file_nums.get_mut(sprite_gfx * 4 + slot).ok_or(GfxListError::List(sprite_gfx))?;
+        *entry = file_num;
+        Ok(())
+    }
+
+    pub fn write_to_rom(&self, disasm: &mut RomDisassembly) -> Result<(), RomError> {
+        disasm.rom.write_lorom(SPRITE_GFX_LIST.begin, &self.gfx_file_nums)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_set_gfx_file_num() {
+        let mut list = SpriteGfxList { gfx_file_nums: vec![0; SPRITE_GFX_LIST.size] };
+        list.set_gfx_file_num(25, 3, 0x80).unwrap();
+        assert_eq!(list.gfx_file_nums(25), &[0, 0, 0, 0x80]);
+        assert!(matches!(list.set_gfx_file_num(0, 4, 0x80), Err(GfxListError::Slot(4))));
+        assert!(matches!(list.set_gfx_file_num(26, 0, 0x80), Err(GfxListError::List(26))));
+    }
+}
@@ -12,7 +12,7 @@ use thiserror::Error;
 use crate::{
     snes_utils::{
         addr::{AddrPc, AddrSnes},
-        rom::Rom,
+        rom::{checksum, Rom},
         rom_slice::PcSlice,
     },
     RomError,
@@ -53,10 +53,19 @@ pub enum InternalHeaderParseError {
     ReadEmulationModeInterruptVectors(RomError),
 }
 
+#[derive(Debug, Error)]
+#[error("Unsupported game: \"{name}\", region: {region}, version: {version}")]
+pub struct UnsupportedGameError {
+    pub name:    String,
+    pub region:  String,
+    pub version: u8,
+}
+
 // -------------------------------------------------------------------------------------------------
 
 #[rustfmt::skip]
 pub mod offsets {
+    pub const ROM_SIZE:         usize = 0x17;
     pub const COMPLEMENT_CHECK: usize = 0x1C;
     pub const CHECKSUM:         usize = 0x1E;
 }
@@ -67,6 +76,9 @@ pub mod sizes {
     pub const INTERNAL_ROM_NAME: usize = 21;
 }
 
+pub const HEADER_LOROM: PcSlice = PcSlice::new(AddrPc(0x007FC0), sizes::INTERNAL_HEADER);
+pub const HEADER_HIROM: PcSlice = PcSlice::new(AddrPc(0x00FFC0), sizes::INTERNAL_HEADER);
+
 // -------------------------------------------------------------------------------------------------
 
 #[derive(Debug)]
@@ -139,6 +151,17 @@ pub enum RomType {
     RomCustomSram     = 0xF6,
 }
 
+/// Releases of Super Mario World with a known memory layout.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum GameVersion {
+    Japan,
+    NorthAmerica,
+    Europe1_0,
+    Europe1_1,
+    /// The game as included in Super Mario All-Stars + Super Mario World.
+    AllStars,
+}
+
 #[derive(Debug, TryFromPrimitive)]
 #[repr(u8)]
 pub enum RegionCode {
@@ -222,10 +245,20 @@ impl RomInternalHeader {
         })
     }
 
-    fn find(rom: &Rom) -> Result<PcSlice, InternalHeaderParseError> {
-        const HEADER_LOROM: PcSlice = PcSlice::new(AddrPc(0x007FC0), sizes::INTERNAL_HEADER);
-        const HEADER_HIROM: PcSlice = PcSlice::new(AddrPc(0x00FFC0), sizes::INTERNAL_HEADER);
+    /// Checks whether `bytes` contain an internal header with a valid checksum complement at the LoROM or HiROM
+    /// location.
+    pub fn exists_in(bytes: &[u8]) -> bool {
+        [HEADER_LOROM, HEADER_HIROM].into_iter().any(|location| {
+            let begin = location.begin.as_index() + offsets::COMPLEMENT_CHECK;
+            bytes.get(begin..begin + 4).is_some_and(|cpl_csm| {
+                let complement = u16::from_le_bytes([cpl_csm[0], cpl_csm[1]]);
+                let checksum = u16::from_le_bytes([cpl_csm[2], cpl_csm[3]]);
+                (checksum ^ complement) == 0xFFFF
+            })
+        })
+    }
 
+    fn find(rom: &Rom) -> Result<PcSlice, InternalHeaderParseError> {
         let lo_cpl_csm = HEADER_LOROM.offset_forward(offsets::COMPLEMENT_CHECK).resize(4);
         let hi_cpl_csm = HEADER_HIROM.offset_forward(offsets::COMPLEMENT_CHECK).resize(4);
 
@@ -251,11 +284,92 @@ impl RomInternalHeader {
         }
     }
 
+    /// Writes the ROM size, the checksum and its complement into the internal header in `rom`. The checksum is
+    /// computed after the ROM size is written.
+    pub fn write_to_rom(&self, rom: &mut Rom) -> Result<(), RomError> {
+        let location = self.location();
+        rom.write_pc(location.begin + offsets::ROM_SIZE, &[self.rom_size])?;
+        Self::write_checksum(rom.bytes_mut(), location)
+    }
+
+    /// Writes the checksum of `rom` and its complement into the internal header at `location`, which is either
+    /// [`HEADER_LOROM`] or [`HEADER_HIROM`].
+    pub fn write_checksum(rom: &mut [u8], location: PcSlice) -> Result<(), RomError> {
+        let range = location.begin.as_index()..location.begin.as_index() + location.size;
+        if range.end > rom.len() {
+            return Err(RomError::SlicePc(location));
+        }
+
+        // The checksum covers its own bytes, so they're set to a valid pair before summing up.
+        Self::write_checksum_into(&mut rom[range.clone()], 0);
+        let checksum = checksum(rom);
+        Self::write_checksum_into(&mut rom[range], checksum);
+        Ok(())
+    }
+
+    /// Writes `checksum` and its complement into `header`, the bytes of an internal header.
+    pub fn write_checksum_into(header: &mut [u8], checksum: u16) {
+        let [cpl_lo, cpl_hi] = (!checksum).to_le_bytes();
+        let [csm_lo, csm_hi] = checksum.to_le_bytes();
+        header[offsets::COMPLEMENT_CHECK..offsets::CHECKSUM + 2].copy_from_slice(&[cpl_lo, cpl_hi, csm_lo, csm_hi]);
+    }
+
+    /// Writes the ROM size field of a ROM of `size` bytes into `header`, the bytes of an internal header.
+    pub fn write_rom_size_into(header: &mut [u8], size: usize) {
+        header[offsets::ROM_SIZE] = Self::rom_size_exponent(size);
+    }
+
+    /// Checks whether the checksum stored in the internal header matches the contents of `rom`.
+    pub fn has_valid_checksum(&self, rom: &Rom) -> bool {
+        let checksum_location = self.location().offset_forward(offsets::CHECKSUM).resize(2);
+        rom.view().slice_pc(checksum_location).parse(le_u16).is_ok_and(|checksum| checksum == rom.checksum())
+    }
+
+    pub fn location(&self) -> PcSlice {
+        if self.map_mode.is_lorom() {
+            HEADER_LOROM
+        } else {
+            HEADER_HIROM
+        }
+    }
+
+    /// Identifies the release of the game from its region and version number.
+    ///
+    /// The name isn't required to match, since hacks often change it. It only tells apart the All-Stars release, which
+    /// has the same region and version number as the North American one.
+    pub fn game_version(&self) -> Result<GameVersion, UnsupportedGameError> {
+        let version = match (&self.region_code, self.version_number) {
+            (RegionCode::Japan, 0) => Some(GameVersion::Japan),
+            (RegionCode::NorthAmerica, 0) if self.internal_rom_name.starts_with("ALL_STARS") => {
+                Some(GameVersion::AllStars)
+            }
+            (RegionCode::NorthAmerica, 0) => Some(GameVersion::NorthAmerica),
+            (RegionCode::Europe, 0) => Some(GameVersion::Europe1_0),
+            (RegionCode::Europe, 1) => Some(GameVersion::Europe1_1),
+            _ => None,
+        };
+        version.ok_or_else(|| UnsupportedGameError {
+            name:    self.internal_rom_name.clone(),
+            region:  self.region_code.to_string(),
+            version: self.version_number,
+        })
+    }
+
     pub fn rom_size_in_kb(&self) -> u32 {
         let exponent = self.rom_size as u32;
         2u32.pow(exponent)
     }
 
+    /// Sets the ROM size to the smallest power of two kilobytes that fits `size` bytes.
+    pub fn set_rom_size_in_bytes(&mut self, size: usize) {
+        self.rom_size = Self::rom_size_exponent(size);
+    }
+
+    /// Value of the ROM size field for a ROM of `size` bytes.
+    fn rom_size_exponent(size: usize) -> u8 {
+        ((size + 0x3FF) / 0x400).next_power_of_two().trailing_zeros() as u8
+    }
+
     pub fn sram_size_in_kb(&self) -> u32 {
         match self.sram_size as u32 {
             0 => 0,
@@ -1,20 +1,27 @@
-use crate::compression::{lc_rle1, DecompressionError};
+use crate::compression::{Compression, DecompressionError};
 
 // -------------------------------------------------------------------------------------------------
 
 pub type BackgroundTileID = u8;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Eq, PartialEq)]
 pub struct BackgroundData {
-    _tile_ids: Vec<BackgroundTileID>,
+    pub tile_ids:    Vec<BackgroundTileID>,
+    /// Format in which the background is stored, LC-RLE1 in the original game.
+    pub compression: Compression,
 }
 
 // -------------------------------------------------------------------------------------------------
 
 impl BackgroundData {
     /// Returns self and the number of bytes consumed by parsing.
-    pub fn read_from(input: &[u8]) -> Result<(Self, usize), DecompressionError> {
-        let (tile_ids, bytes_consumed) = lc_rle1::decompress(input)?;
-        Ok((Self { _tile_ids: tile_ids }, bytes_consumed))
+    pub fn read_from(input: &[u8], compression: Compression) -> Result<(Self, usize), DecompressionError> {
+        let (tile_ids, bytes_consumed) = compression.decompress(input)?;
+        Ok((Self { tile_ids, compression }, bytes_consumed))
+    }
+
+    /// Encodes tile IDs into compressed data, including its terminator.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.compression.compress(&self.tile_ids)
     }
 }
@@ -1,9 +1,11 @@
 use std::convert::TryInto;
 
 use nom::{bytes::complete::take, IResult};
+use num_enum::{FromPrimitive, IntoPrimitive, TryFromPrimitive};
 
 use crate::{
     disassembler::binary_block::{DataBlock, DataKind},
+    level::ValueOutOfRangeError,
     snes_utils::{addr::AddrSnes, rom::noop_error_mapper, rom_slice::SnesSlice},
     RomDisassembly,
     RomError,
@@ -13,15 +15,117 @@ pub const PRIMARY_HEADER_SIZE: usize = 5;
 pub const SECONDARY_HEADER_SIZE: usize = 4;
 pub const SPRITE_HEADER_SIZE: usize = 1;
 
-#[derive(Debug, Clone)]
+/// Tables holding one byte of the secondary header of every level each.
+pub const SECONDARY_HEADER_TABLES: [SnesSlice; SECONDARY_HEADER_SIZE] = [
+    SnesSlice::new(AddrSnes(0x05F000), 0x200),
+    SnesSlice::new(AddrSnes(0x05F200), 0x200),
+    SnesSlice::new(AddrSnes(0x05F400), 0x200),
+    SnesSlice::new(AddrSnes(0x05F600), 0x200),
+];
+
+// -------------------------------------------------------------------------------------------------
+
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
 pub struct PrimaryHeader(pub [u8; PRIMARY_HEADER_SIZE]);
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
 pub struct SecondaryHeader(pub [u8; SECONDARY_HEADER_SIZE]);
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
 pub struct SpriteHeader(pub u8);
 
+/// Determines how the level's layers are laid out and rendered. Modes without a name are either unused by the original
+/// game or only differ from the named ones in details of rendering.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, FromPrimitive, IntoPrimitive)]
+#[repr(u8)]
+pub enum LevelMode {
+    Horizontal          = 0x00,
+    HorizontalLayer2    = 0x01,
+    VerticalLayer2      = 0x07,
+    BossMortonRoyLudwig = 0x09,
+    Vertical            = 0x0A,
+    BossIggyLarry       = 0x0B,
+    BossReznor          = 0x10,
+    #[num_enum(catch_all)]
+    Other(u8),
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, IntoPrimitive, TryFromPrimitive)]
+#[repr(u8)]
+pub enum Music {
+    Overworld   = 0,
+    Athletic    = 1,
+    Underground = 2,
+    Water       = 3,
+    Castle      = 4,
+    GhostHouse  = 5,
+    Bonus       = 6,
+    Boss        = 7,
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, IntoPrimitive, TryFromPrimitive)]
+#[repr(u8)]
+pub enum VerticalScroll {
+    Always      = 0,
+    Never       = 1,
+    /// Only when Mario is flying, climbing or swimming.
+    WhenFlying  = 2,
+    /// Neither vertical nor horizontal scrolling.
+    NoScrolling = 3,
+}
+
+/// Index of one of the 16 ways Layer2 scrolls relative to Layer1.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct Layer2Scroll(u8);
+
+/// Index of one of the sprite slot configurations, which determine how many sprites can be loaded at once.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct SpriteMemory(u8);
+
+// -------------------------------------------------------------------------------------------------
+
+impl Layer2Scroll {
+    pub const MAX: u8 = 0b1111;
+
+    pub fn new(index: u8) -> Result<Self, ValueOutOfRangeError> {
+        ValueOutOfRangeError::check("Layer2 scroll", index, Self::MAX as u32)?;
+        Ok(Self(index))
+    }
+
+    pub fn index(self) -> u8 {
+        self.0
+    }
+}
+
+impl SpriteMemory {
+    pub const MAX: u8 = 0b111111;
+
+    pub fn new(index: u8) -> Result<Self, ValueOutOfRangeError> {
+        ValueOutOfRangeError::check("Sprite memory", index, Self::MAX as u32)?;
+        Ok(Self(index))
+    }
+
+    pub fn index(self) -> u8 {
+        self.0
+    }
+}
+
+/// Replaces the bits of `byte` selected by `mask` with `value`, checking that `value` fits in them.
+fn set_field(byte: &mut u8, mask: u8, value: u8, name: &'static str) -> Result<(), ValueOutOfRangeError> {
+    let shift = mask.trailing_zeros();
+    ValueOutOfRangeError::check(name, value, (mask >> shift) as u32)?;
+    *byte = (*byte & !mask) | (value << shift);
+    Ok(())
+}
+
+fn set_flag(byte: &mut u8, mask: u8, value: bool) {
+    if value {
+        *byte |= mask;
+    } else {
+        *byte &= !mask;
+    }
+}
+
 impl PrimaryHeader {
     pub fn new(bytes: &[u8]) -> Self {
         Self(bytes.try_into().unwrap())
@@ -33,22 +137,38 @@ impl PrimaryHeader {
         self.0[0] >> 5
     }
 
+    pub fn set_palette_bg(&mut self, palette: u8) -> Result<(), ValueOutOfRangeError> {
+        set_field(&mut self.0[0], 0b11100000, palette, "BG palette")
+    }
+
     pub fn level_length(&self) -> u8 {
         // ---LLLLL -------- -------- -------- --------
         // level_length = LLLLL
         self.0[0] & 0b11111
     }
 
+    pub fn set_level_length(&mut self, screens: u8) -> Result<(), ValueOutOfRangeError> {
+        set_field(&mut self.0[0], 0b00011111, screens, "Level length")
+    }
+
     pub fn back_area_color(&self) -> u8 {
         // -------- CCC----- -------- -------- --------
         // back_area_color = CCC
         self.0[1] >> 5
     }
 
-    pub fn level_mode(&self) -> u8 {
+    pub fn set_back_area_color(&mut self, color: u8) -> Result<(), ValueOutOfRangeError> {
+        set_field(&mut self.0[1], 0b11100000, color, "Back area color")
+    }
+
+    pub fn level_mode(&self) -> LevelMode {
         // -------- ---MMMMM -------- -------- --------
         // level_mode = MMMMM
-        self.0[1] & 0b11111
+        LevelMode::from(self.0[1] & 0b11111)
+    }
+
+    pub fn set_level_mode(&mut self, mode: LevelMode) -> Result<(), ValueOutOfRangeError> {
+        set_field(&mut self.0[1], 0b00011111, mode.into(), "Level mode")
     }
 
     pub fn layer3_priority(&self) -> bool {
@@ -57,10 +177,18 @@ impl PrimaryHeader {
         (self.0[2] >> 7) != 0
     }
 
-    pub fn music(&self) -> u8 {
+    pub fn set_layer3_priority(&mut self, priority: bool) {
+        set_flag(&mut self.0[2], 0b10000000, priority);
+    }
+
+    pub fn music(&self) -> Music {
         // -------- -------- -MMM---- -------- --------
         // music = MMM
-        (self.0[2] >> 4) & 0b111
+        Music::try_from((self.0[2] >> 4) & 0b111).unwrap()
+    }
+
+    pub fn set_music(&mut self, music: Music) {
+        set_field(&mut self.0[2], 0b01110000, music.into(), "Music").unwrap();
     }
 
     pub fn sprite_gfx(&self) -> u8 {
@@ -69,34 +197,58 @@ impl PrimaryHeader {
         self.0[2] & 0b1111
     }
 
+    pub fn set_sprite_gfx(&mut self, gfx: u8) -> Result<(), ValueOutOfRangeError> {
+        set_field(&mut self.0[2], 0b00001111, gfx, "Sprite GFX")
+    }
+
     pub fn timer(&self) -> u8 {
         // -------- -------- -------- TT------ --------
         // timer = TT
         self.0[3] >> 6
     }
 
+    pub fn set_timer(&mut self, timer: u8) -> Result<(), ValueOutOfRangeError> {
+        set_field(&mut self.0[3], 0b11000000, timer, "Timer")
+    }
+
     pub fn palette_sprite(&self) -> u8 {
         // -------- -------- -------- --PPP--- --------
         // palette_sprite = PPP
         (self.0[3] >> 3) & 0b111
     }
 
+    pub fn set_palette_sprite(&mut self, palette: u8) -> Result<(), ValueOutOfRangeError> {
+        set_field(&mut self.0[3], 0b00111000, palette, "Sprite palette")
+    }
+
     pub fn palette_fg(&self) -> u8 {
         // -------- -------- -------- -----FFF --------
         // palette_fg = FFF
         self.0[3] & 0b111
     }
 
+    pub fn set_palette_fg(&mut self, palette: u8) -> Result<(), ValueOutOfRangeError> {
+        set_field(&mut self.0[3], 0b00000111, palette, "FG palette")
+    }
+
     pub fn item_memory(&self) -> u8 {
         // -------- -------- -------- -------- II------
         // item_memory = II
         self.0[4] >> 6
     }
 
-    pub fn vertical_scroll(&self) -> u8 {
+    pub fn set_item_memory(&mut self, item_memory: u8) -> Result<(), ValueOutOfRangeError> {
+        set_field(&mut self.0[4], 0b11000000, item_memory, "Item memory")
+    }
+
+    pub fn vertical_scroll(&self) -> VerticalScroll {
         // -------- -------- -------- -------- --VV----
         // vertical_scroll = VV
-        (self.0[4] >> 4) & 0b11
+        VerticalScroll::try_from((self.0[4] >> 4) & 0b11).unwrap()
+    }
+
+    pub fn set_vertical_scroll(&mut self, scroll: VerticalScroll) {
+        set_field(&mut self.0[4], 0b00110000, scroll.into(), "Vertical scroll").unwrap();
     }
 
     pub fn fg_bg_gfx(&self) -> u8 {
@@ -104,27 +256,39 @@ impl PrimaryHeader {
         // fg_bg_gfx = GGGG
         self.0[4] & 0b1111
     }
+
+    pub fn set_fg_bg_gfx(&mut self, gfx: u8) -> Result<(), ValueOutOfRangeError> {
+        set_field(&mut self.0[4], 0b00001111, gfx, "FG/BG GFX")
+    }
 }
 
 impl SecondaryHeader {
     pub fn read_from_rom(disasm: &mut RomDisassembly, level_num: u32) -> Result<Self, RomError> {
-        let mut bytes = [0; 4];
-        let byte_table_addrs = [0x05F000, 0x05F200, 0x05F400, 0x05F600];
-        for (byte, addr) in bytes.iter_mut().zip(byte_table_addrs) {
-            let data_block = DataBlock {
-                slice: SnesSlice::new(AddrSnes(addr), 0x200),
-                kind:  DataKind::LevelHeaderSecondaryByteTable,
-            };
+        let mut bytes = [0; SECONDARY_HEADER_SIZE];
+        for (byte, slice) in bytes.iter_mut().zip(SECONDARY_HEADER_TABLES) {
+            let data_block = DataBlock { slice, kind: DataKind::LevelHeaderSecondaryByteTable };
             let byte_table = disasm.rom_slice_at_block(data_block, noop_error_mapper)?.as_bytes()?;
             *byte = byte_table[level_num as usize];
         }
         Ok(Self(bytes))
     }
 
-    pub fn layer2_scroll(&self) -> u8 {
+    /// Writes each byte of the header into its table.
+    pub fn write_to_rom(&self, disasm: &mut RomDisassembly, level_num: u32) -> Result<(), RomError> {
+        for (byte, slice) in self.0.iter().zip(SECONDARY_HEADER_TABLES) {
+            disasm.rom.write_lorom(slice.begin + level_num, &[*byte])?;
+        }
+        Ok(())
+    }
+
+    pub fn layer2_scroll(&self) -> Layer2Scroll {
         // SSSS---- -------- -------- --------
         // layer2_scroll = SSSS
-        (self.0[0] >> 4) & 0b1111
+        Layer2Scroll((self.0[0] >> 4) & 0b1111)
+    }
+
+    pub fn set_layer2_scroll(&mut self, scroll: Layer2Scroll) {
+        set_field(&mut self.0[0], 0b11110000, scroll.0, "Layer2 scroll").unwrap();
     }
 
     pub fn main_entrance_xy_pos(&self) -> (u8, u8) {
@@ -135,59 +299,101 @@ impl SecondaryHeader {
         (x, y)
     }
 
+    pub fn set_main_entrance_xy_pos(&mut self, x: u8, y: u8) -> Result<(), ValueOutOfRangeError> {
+        ValueOutOfRangeError::check("Main entrance X position", x, 0b111)?;
+        set_field(&mut self.0[0], 0b00001111, y, "Main entrance Y position")?;
+        set_field(&mut self.0[1], 0b00000111, x, "Main entrance X position")
+    }
+
     pub fn layer3(&self) -> u8 {
         // -------- LL------ -------- --------
         // layer3 = LL
         (self.0[1] >> 6) & 0b11
     }
 
+    pub fn set_layer3(&mut self, layer3: u8) -> Result<(), ValueOutOfRangeError> {
+        set_field(&mut self.0[1], 0b11000000, layer3, "Layer3")
+    }
+
     pub fn main_entrance_mario_action(&self) -> u8 {
         // -------- --AAA--- -------- --------
         // main_entrance_mario_action = AAA
         (self.0[1] >> 3) & 0b111
     }
 
+    pub fn set_main_entrance_mario_action(&mut self, action: u8) -> Result<(), ValueOutOfRangeError> {
+        set_field(&mut self.0[1], 0b00111000, action, "Main entrance Mario action")
+    }
+
     pub fn midway_entrance_screen(&self) -> u8 {
         // -------- -------- SSSS---- --------
         // midway_entrance_screen = SSSS
         (self.0[2] >> 4) & 0b1111
     }
 
+    pub fn set_midway_entrance_screen(&mut self, screen: u8) -> Result<(), ValueOutOfRangeError> {
+        set_field(&mut self.0[2], 0b11110000, screen, "Midway entrance screen")
+    }
+
     pub fn fg_initial_pos(&self) -> u8 {
         // -------- -------- ----FF-- --------
         // fg_initial_pos = FF
         (self.0[2] >> 2) & 0b11
     }
 
+    pub fn set_fg_initial_pos(&mut self, pos: u8) -> Result<(), ValueOutOfRangeError> {
+        set_field(&mut self.0[2], 0b00001100, pos, "FG initial position")
+    }
+
     pub fn bg_initial_pos(&self) -> u8 {
         // -------- -------- ------BB --------
         // bg_initial_pos = BB
         self.0[2] & 0b11
     }
 
+    pub fn set_bg_initial_pos(&mut self, pos: u8) -> Result<(), ValueOutOfRangeError> {
+        set_field(&mut self.0[2], 0b00000011, pos, "BG initial position")
+    }
+
     pub fn no_yoshi_level(&self) -> bool {
         // -------- -------- -------- Y-------
         // no_yoshi_level = Y
         (self.0[3] >> 7) != 0
     }
 
+    pub fn set_no_yoshi_level(&mut self, no_yoshi: bool) {
+        set_flag(&mut self.0[3], 0b10000000, no_yoshi);
+    }
+
     pub fn unknown_vertical_pos_level(&self) -> bool {
         // -------- -------- -------- -U------
         // unknown_vertical_pos_level = U
         (self.0[3] & 0b01000000) != 0
     }
 
+    pub fn set_unknown_vertical_pos_level(&mut self, value: bool) {
+        set_flag(&mut self.0[3], 0b01000000, value);
+    }
+
     pub fn vertical_level(&self) -> bool {
         // -------- -------- -------- --V-----
         // vertical_level = V
         (self.0[3] & 0b00100000) != 0
     }
 
+    pub fn set_vertical_level(&mut self, vertical: bool) {
+        set_flag(&mut self.0[3], 0b00100000, vertical);
+    }
+
     pub fn main_entrance_screen(&self) -> u8 {
         // -------- -------- -------- ---EEEEE
         // main_entrance_screen = EEEEE
         self.0[3] & 0b11111
     }
+
+    pub fn set_main_entrance_screen(&mut self, screen: u8) -> Result<(), ValueOutOfRangeError> {
+        set_field(&mut self.0[3], 0b00011111, screen, "Main entrance screen")
+    }
 }
 
 impl SpriteHeader {
@@ -202,15 +408,124 @@ impl SpriteHeader {
         (self.0 & 0b10000000) != 0
     }
 
+    pub fn set_sprite_buoyancy(&mut self, buoyancy: bool) {
+        set_flag(&mut self.0, 0b10000000, buoyancy);
+    }
+
     pub fn disable_layer2_interaction(&self) -> bool {
         // -L------
         // disable_layer2_interaction = L
         (self.0 & 0b01000000) != 0
     }
 
-    pub fn sprite_memory(&self) -> u8 {
+    pub fn set_disable_layer2_interaction(&mut self, disable: bool) {
+        set_flag(&mut self.0, 0b01000000, disable);
+    }
+
+    pub fn sprite_memory(&self) -> SpriteMemory {
         // --MMMMMM
         // sprite_memory = MMMMMM
-        self.0 & 0b00111111
+        SpriteMemory(self.0 & 0b00111111)
+    }
+
+    pub fn set_sprite_memory(&mut self, memory: SpriteMemory) {
+        set_field(&mut self.0, 0b00111111, memory.0, "Sprite memory").unwrap();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::snes_utils::{addr::AddrPc, rom::Rom};
+
+    #[test]
+    fn test_primary_header_setters() {
+        let mut header = PrimaryHeader([0xFF; PRIMARY_HEADER_SIZE]);
+        header.set_timer(2).unwrap();
+        header.set_item_memory(1).unwrap();
+        header.set_vertical_scroll(VerticalScroll::Never);
+        header.set_level_mode(LevelMode::Vertical).unwrap();
+        header.set_music(Music::Castle);
+
+        assert_eq!(header.timer(), 2);
+        assert_eq!(header.palette_sprite(), 0b111);
+        assert_eq!(header.item_memory(), 1);
+        assert_eq!(header.vertical_scroll(), VerticalScroll::Never);
+        assert_eq!(header.fg_bg_gfx(), 0b1111);
+        assert_eq!(header.level_mode(), LevelMode::Vertical);
+        assert_eq!(header.music(), Music::Castle);
+        assert!(header.layer3_priority());
+
+        assert!(header.set_timer(4).is_err());
+        assert!(header.set_level_mode(LevelMode::Other(0x20)).is_err());
+        assert_eq!(header.level_mode(), LevelMode::Vertical);
+        assert_eq!(PrimaryHeader([0, 0x03, 0, 0, 0]).level_mode(), LevelMode::Other(0x03));
+    }
+
+    #[test]
+    fn test_secondary_header_setters() {
+        let mut header = SecondaryHeader([0xFF; SECONDARY_HEADER_SIZE]);
+        header.set_layer2_scroll(Layer2Scroll::new(3).unwrap());
+        header.set_main_entrance_xy_pos(5, 2).unwrap();
+        header.set_layer3(1).unwrap();
+        header.set_main_entrance_mario_action(4).unwrap();
+        header.set_midway_entrance_screen(0xA).unwrap();
+        header.set_fg_initial_pos(0).unwrap();
+        header.set_bg_initial_pos(2).unwrap();
+        header.set_no_yoshi_level(false);
+        header.set_vertical_level(false);
+        header.set_main_entrance_screen(0x13).unwrap();
+
+        assert_eq!(header.layer2_scroll(), Layer2Scroll::new(3).unwrap());
+        assert_eq!(header.main_entrance_xy_pos(), (5, 2));
+        assert_eq!(header.layer3(), 1);
+        assert_eq!(header.main_entrance_mario_action(), 4);
+        assert_eq!(header.midway_entrance_screen(), 0xA);
+        assert_eq!(header.fg_initial_pos(), 0);
+        assert_eq!(header.bg_initial_pos(), 2);
+        assert!(!header.no_yoshi_level());
+        assert!(header.unknown_vertical_pos_level());
+        assert!(!header.vertical_level());
+        assert_eq!(header.main_entrance_screen(), 0x13);
+
+        assert!(Layer2Scroll::new(0x10).is_err());
+        assert!(header.set_main_entrance_xy_pos(8, 0).is_err());
+        assert!(header.set_main_entrance_xy_pos(0, 0x10).is_err());
+        assert_eq!(header.main_entrance_xy_pos(), (5, 2));
+        assert!(header.set_layer3(4).is_err());
+        assert!(header.set_main_entrance_screen(0x20).is_err());
+        assert_eq!(header.main_entrance_screen(), 0x13);
+    }
+
+    #[test]
+    fn test_secondary_header_write_to_rom() {
+        let mut disasm = RomDisassembly::unanalysed(Rom::new(vec![0; 0x80000]).unwrap());
+        let header = SecondaryHeader([0x12, 0x34, 0x56, 0x78]);
+        header.write_to_rom(&mut disasm, 0x105).unwrap();
+
+        for (byte, table) in header.0.iter().zip(SECONDARY_HEADER_TABLES) {
+            let addr = AddrPc::try_from_lorom(table.begin + 0x105u32).unwrap();
+            assert_eq!(disasm.rom_bytes()[addr.as_index()], *byte);
+        }
+        assert_eq!(SecondaryHeader::read_from_rom(&mut disasm, 0x105).unwrap(), header);
+        assert_eq!(SecondaryHeader::read_from_rom(&mut disasm, 0x104).unwrap(), SecondaryHeader::default());
+    }
+
+    #[test]
+    fn test_sprite_header_setters() {
+        let mut header = SpriteHeader(0);
+        header.set_sprite_buoyancy(true);
+        header.set_sprite_memory(SpriteMemory::new(0x0A).unwrap());
+
+        assert!(header.sprite_buoyancy());
+        assert!(!header.disable_layer2_interaction());
+        assert_eq!(header.sprite_memory(), SpriteMemory::new(0x0A).unwrap());
+        assert_eq!(header.0, 0x8A);
+
+        header.set_disable_layer2_interaction(true);
+        header.set_sprite_buoyancy(false);
+        assert_eq!(header.0, 0x4A);
+        assert!(SpriteMemory::new(0x40).is_err());
+        assert_eq!(SpriteHeader::read_from(&[0xC1, 0xFF]).unwrap(), (&[0xFF][..], SpriteHeader(0xC1)));
     }
 }
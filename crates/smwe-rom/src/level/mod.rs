@@ -1,3 +1,5 @@
+use std::fmt;
+
 use nom::{
     combinator::map,
     multi::count,
@@ -7,13 +9,28 @@ use thiserror::Error;
 
 pub use self::{
     background::{BackgroundData, BackgroundTileID},
-    headers::{PrimaryHeader, SecondaryHeader, SpriteHeader, PRIMARY_HEADER_SIZE, SPRITE_HEADER_SIZE},
-    object_layer::ObjectLayer,
-    sprite_layer::SpriteLayer,
+    headers::{
+        Layer2Scroll,
+        LevelMode,
+        Music,
+        PrimaryHeader,
+        SecondaryHeader,
+        SpriteHeader,
+        SpriteMemory,
+        VerticalScroll,
+        PRIMARY_HEADER_SIZE,
+        SPRITE_HEADER_SIZE,
+    },
+    object_layer::{ObjectEncodeError, ObjectLayer},
+    sprite_layer::{SpriteError, SpriteLayer},
 };
 use crate::{
-    compression::DecompressionError,
-    disassembler::binary_block::{DataBlock, DataKind},
+    compression::{Compression, DecompressionError},
+    disassembler::{
+        binary_block::{DataBlock, DataKind},
+        free_space::FREE_SPACE_BANKS,
+    },
+    lunar_magic::LunarMagic,
     snes_utils::{addr::AddrSnes, rom_slice::SnesSlice},
     RomDisassembly,
     RomError,
@@ -56,16 +73,67 @@ pub enum LevelParseError {
     SpriteRead(RomError),
 }
 
+#[derive(Debug, Error)]
+pub enum LevelEncodeError {
+    #[error("Encoding Layer1 objects:\n- {0}")]
+    Layer1(ObjectEncodeError),
+    #[error("Encoding Layer2 objects:\n- {0}")]
+    Layer2(ObjectEncodeError),
+    #[error("Encoding sprite data:\n- {0}")]
+    Sprites(SpriteError),
+}
+
+#[derive(Debug, Error)]
+pub enum LevelSaveError {
+    #[error("Encoding level {0:X}:\n- {1}")]
+    Encode(u32, LevelEncodeError),
+    #[error("Reading pointer to level {0:X}'s {1}:\n- {2}")]
+    PointerRead(u32, LevelDataType, RomError),
+    #[error("Reading level {0:X}'s original {1}:\n- {2}")]
+    OriginalDataRead(u32, LevelDataType, RomError),
+    #[error("Writing level {0:X}'s {1}:\n- {2}")]
+    Write(u32, LevelDataType, RomError),
+    #[error("Not enough free space for level {0:X}'s {1} ({2} bytes)")]
+    NoFreeSpace(u32, LevelDataType, usize),
+    #[error(
+        "Level {0:X}'s {1} no longer fits in bank ${2:02X}, which it cannot leave without Lunar Magic's bank table"
+    )]
+    FixedBank(u32, LevelDataType, u8),
+    #[error("Writing level {0:X}'s secondary header:\n- {1}")]
+    SecondaryHeaderWrite(u32, RomError),
+}
+
+/// Returned by setters of level properties when the value doesn't fit in the bits reserved for it.
+#[derive(Debug, Error)]
+#[error("{name} out of range: {value:#X} (maximum: {max:#X})")]
+pub struct ValueOutOfRangeError {
+    pub name:  &'static str,
+    pub value: u32,
+    pub max:   u32,
+}
+
 // -------------------------------------------------------------------------------------------------
 
 pub const LEVEL_COUNT: usize = 0x200;
 
+pub const LAYER1_POINTERS: SnesSlice = SnesSlice::new(AddrSnes(0x05E000), LEVEL_COUNT * 3);
+pub const LAYER2_POINTERS: SnesSlice = SnesSlice::new(AddrSnes(0x05E600), LEVEL_COUNT * 3);
+pub const SPRITE_POINTERS: SnesSlice = SnesSlice::new(AddrSnes(0x05EC00), LEVEL_COUNT * 2);
+
+/// Bank in which Layer2 backgrounds are stored, indicated by bank `0xFF` in Layer2 pointers. Lunar Magic can move
+/// them to other banks, see [`LunarMagic::layer2_background_bank`].
+pub const LAYER2_BACKGROUND_BANK: u8 = 0x0C;
+/// Bank in which sprite data is stored, since sprite pointers are 16-bit. Lunar Magic can move it to other banks, see
+/// [`LunarMagic::sprite_data_bank`].
+pub const SPRITE_DATA_BANK: u8 = 0x07;
+
 // -------------------------------------------------------------------------------------------------
 
 #[derive(Debug, Clone)]
 pub enum Layer2Data {
     Background(BackgroundData),
-    Objects(ObjectLayer),
+    /// Layer2 objects are preceded by a header in the same format as the primary header, which is ignored by the game.
+    Objects(PrimaryHeader, ObjectLayer),
 }
 
 #[derive(Debug, Clone)]
@@ -78,13 +146,34 @@ pub struct Level {
     pub sprite_layer:     SpriteLayer,
 }
 
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum LevelDataType {
+    Layer1,
+    Layer2,
+    Layer2Background(Compression),
+    Sprites,
+}
+
+/// Level data encoded in the format in which it is stored in the ROM.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct SerializedLevel {
+    /// Primary header followed by Layer1 objects.
+    pub layer1:  Vec<u8>,
+    /// Layer2 header followed by Layer2 objects, or compressed Layer2 background.
+    pub layer2:  Vec<u8>,
+    /// Sprite header followed by sprites.
+    pub sprites: Vec<u8>,
+}
+
 // -------------------------------------------------------------------------------------------------
 
 impl Level {
-    pub fn parse(disasm: &mut RomDisassembly, level_num: u32) -> Result<Self, LevelParseError> {
+    pub fn parse(
+        disasm: &mut RomDisassembly, level_num: u32, lunar_magic: Option<&LunarMagic>,
+    ) -> Result<Self, LevelParseError> {
         let (primary_header, layer1) = Self::parse_ph_and_l1(disasm, level_num)?;
-        let layer2 = Self::parse_l2(disasm, level_num)?;
-        let (sprite_header, sprite_layer) = Self::parse_sh_and_sl(disasm, level_num)?;
+        let layer2 = Self::parse_l2(disasm, level_num, lunar_magic)?;
+        let (sprite_header, sprite_layer) = Self::parse_sh_and_sl(disasm, level_num, lunar_magic)?;
         let secondary_header =
             SecondaryHeader::read_from_rom(disasm, level_num).map_err(LevelParseError::SecondaryHeaderRead)?;
 
@@ -94,11 +183,10 @@ impl Level {
     fn parse_ph_and_l1(
         disasm: &mut RomDisassembly, level_num: u32,
     ) -> Result<(PrimaryHeader, ObjectLayer), LevelParseError> {
-        let l1_ptr_block =
-            DataBlock { slice: SnesSlice::new(AddrSnes(0x05E000), 0x200 * 3), kind: DataKind::LevelPointersLayer1 };
+        let l1_ptr_block = DataBlock { slice: LAYER1_POINTERS, kind: DataKind::LevelPointersLayer1 };
         let ph_addr = disasm
             .rom_slice_at_block(l1_ptr_block, LevelParseError::Layer1AddressRead)?
-            .parse(count(map(le_u24, AddrSnes), 0x200))?[level_num as usize];
+            .parse(count(map(le_u24, AddrSnes), LEVEL_COUNT))?[level_num as usize];
 
         let ph_block =
             DataBlock { slice: SnesSlice::new(ph_addr, PRIMARY_HEADER_SIZE), kind: DataKind::LevelHeaderPrimary };
@@ -117,46 +205,55 @@ impl Level {
         Ok((primary_header, layer1))
     }
 
-    fn parse_l2(disasm: &mut RomDisassembly, level_num: u32) -> Result<Layer2Data, LevelParseError> {
-        const LAYER2_DATA: AddrSnes = AddrSnes(0x05E600);
-
-        let l2_addr_block =
-            DataBlock { slice: SnesSlice::new(LAYER2_DATA + (3 * level_num), 3), kind: DataKind::LevelPointersLayer2 };
+    fn parse_l2(
+        disasm: &mut RomDisassembly, level_num: u32, lunar_magic: Option<&LunarMagic>,
+    ) -> Result<Layer2Data, LevelParseError> {
+        let l2_addr_block = DataBlock {
+            slice: SnesSlice::new(LAYER2_POINTERS.begin + (3 * level_num), 3),
+            kind:  DataKind::LevelPointersLayer2,
+        };
         let l2_ptr = disasm
             .rom_slice_at_block(l2_addr_block, LevelParseError::Layer2AddressRead)?
             .parse(map(le_u24, AddrSnes))?;
 
         if l2_ptr.bank() == 0xFF {
+            let compression = layer2_background_compression(lunar_magic);
             let background = disasm.parse_and_mark_data(
-                l2_ptr.with_bank(0x0C),
+                l2_ptr.with_bank(layer2_background_bank(lunar_magic, level_num)),
                 DataKind::LevelLayer2Background,
                 LevelParseError::Layer2Isolate,
                 |rom_view| {
                     let bytes = rom_view.as_bytes()?;
-                    BackgroundData::read_from(bytes).map_err(LevelParseError::Layer2BackgroundRead)
+                    BackgroundData::read_from(bytes, compression).map_err(LevelParseError::Layer2BackgroundRead)
                 },
             )?;
             Ok(Layer2Data::Background(background))
         } else {
+            let header_block =
+                DataBlock { slice: SnesSlice::new(l2_ptr, PRIMARY_HEADER_SIZE), kind: DataKind::LevelHeaderPrimary };
+            let header = {
+                let bytes = disasm.rom_slice_at_block(header_block, LevelParseError::Layer2Read)?.as_bytes()?;
+                PrimaryHeader::new(bytes)
+            };
             let objects = disasm.parse_and_mark_data(
                 l2_ptr + PRIMARY_HEADER_SIZE as u32,
                 DataKind::LevelLayer2Objects,
                 LevelParseError::Layer2Read,
                 |rom_view| rom_view.parse(ObjectLayer::parse),
             )?;
-            Ok(Layer2Data::Objects(objects))
+            Ok(Layer2Data::Objects(header, objects))
         }
     }
 
     fn parse_sh_and_sl(
-        disasm: &mut RomDisassembly, level_num: u32,
+        disasm: &mut RomDisassembly, level_num: u32, lunar_magic: Option<&LunarMagic>,
     ) -> Result<(SpriteHeader, SpriteLayer), LevelParseError> {
-        const SPRITE_DATA: AddrSnes = AddrSnes(0x05EC00);
-
-        let sprite_ptr_block =
-            DataBlock { slice: SnesSlice::new(SPRITE_DATA + (2 * level_num), 2), kind: DataKind::LevelPointersSprite };
+        let sprite_ptr_block = DataBlock {
+            slice: SnesSlice::new(SPRITE_POINTERS.begin + (2 * level_num), 2),
+            kind:  DataKind::LevelPointersSprite,
+        };
         let sh_addr = disasm.rom_slice_at_block(sprite_ptr_block, LevelParseError::SpriteAddressRead)?.parse(le_u16)?;
-        let sh_addr = AddrSnes(sh_addr as _).with_bank(0x07);
+        let sh_addr = AddrSnes(sh_addr as _).with_bank(sprite_data_bank(lunar_magic, level_num));
 
         let sh_block =
             DataBlock { slice: SnesSlice::new(sh_addr, SPRITE_HEADER_SIZE), kind: DataKind::LevelHeaderSprites };
@@ -172,4 +269,265 @@ impl Level {
 
         Ok((sprite_header, sprite_layer))
     }
+
+    /// Encodes the level's headers, objects and sprites into the format in which they are stored in the ROM.
+    pub fn serialize(&self) -> Result<SerializedLevel, LevelEncodeError> {
+        let mut layer1 = self.primary_header.0.to_vec();
+        layer1.extend(self.layer1.to_bytes().map_err(LevelEncodeError::Layer1)?);
+
+        let layer2 = match &self.layer2 {
+            Layer2Data::Background(background) => background.to_bytes(),
+            Layer2Data::Objects(header, objects) => {
+                let mut layer2 = header.0.to_vec();
+                layer2.extend(objects.to_bytes().map_err(LevelEncodeError::Layer2)?);
+                layer2
+            }
+        };
+
+        let sprites = self.sprite_layer.to_bytes_with_header(&self.sprite_header).map_err(LevelEncodeError::Sprites)?;
+
+        Ok(SerializedLevel { layer1, layer2, sprites })
+    }
+
+    /// Writes the level's data into the ROM and updates its pointers.
+    ///
+    /// Data that didn't change is left untouched. Changed data is written in place if it fits in the space taken by
+    /// the original data and isn't shared with other levels; otherwise, it gets moved to free space. The secondary
+    /// header is written into its tables.
+    ///
+    /// Sprite data and Layer2 backgrounds of ROMs not saved with Lunar Magic have 16-bit pointers into a fixed bank in
+    /// the original ROM, where there is no free space, so they can only be written in place.
+    pub fn write_to_rom(
+        &self, disasm: &mut RomDisassembly, level_num: u32, mut lunar_magic: Option<&mut LunarMagic>,
+    ) -> Result<(), LevelSaveError> {
+        let serialized = self.serialize().map_err(|e| LevelSaveError::Encode(level_num, e))?;
+        LevelDataType::Layer1.write(disasm, level_num, &serialized.layer1, lunar_magic.as_deref_mut())?;
+        let layer2_type = match self.layer2 {
+            Layer2Data::Background(ref background) => LevelDataType::Layer2Background(background.compression),
+            Layer2Data::Objects(..) => LevelDataType::Layer2,
+        };
+        layer2_type.write(disasm, level_num, &serialized.layer2, lunar_magic.as_deref_mut())?;
+        LevelDataType::Sprites.write(disasm, level_num, &serialized.sprites, lunar_magic)?;
+        self.secondary_header
+            .write_to_rom(disasm, level_num)
+            .map_err(|e| LevelSaveError::SecondaryHeaderWrite(level_num, e))?;
+        Ok(())
+    }
+}
+
+impl fmt::Display for LevelDataType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            LevelDataType::Layer1 => "Layer1",
+            LevelDataType::Layer2 => "Layer2",
+            LevelDataType::Layer2Background(_) => "Layer2 background",
+            LevelDataType::Sprites => "sprite data",
+        })
+    }
+}
+
+impl LevelDataType {
+    fn pointer_table(self) -> SnesSlice {
+        match self {
+            LevelDataType::Layer1 => LAYER1_POINTERS,
+            LevelDataType::Layer2 | LevelDataType::Layer2Background(_) => LAYER2_POINTERS,
+            LevelDataType::Sprites => SPRITE_POINTERS,
+        }
+    }
+
+    fn pointer_size(self) -> usize {
+        self.pointer_table().size / LEVEL_COUNT
+    }
+
+    fn header_size(self) -> usize {
+        match self {
+            LevelDataType::Layer1 | LevelDataType::Layer2 => PRIMARY_HEADER_SIZE,
+            LevelDataType::Layer2Background(_) => 0,
+            LevelDataType::Sprites => SPRITE_HEADER_SIZE,
+        }
+    }
+
+    fn data_kinds(self) -> (Option<DataKind>, DataKind) {
+        match self {
+            LevelDataType::Layer1 => (Some(DataKind::LevelHeaderPrimary), DataKind::LevelLayer1Objects),
+            LevelDataType::Layer2 => (Some(DataKind::LevelHeaderPrimary), DataKind::LevelLayer2Objects),
+            LevelDataType::Layer2Background(_) => (None, DataKind::LevelLayer2Background),
+            LevelDataType::Sprites => (Some(DataKind::LevelHeaderSprites), DataKind::LevelSpriteLayer),
+        }
+    }
+
+    /// Banks with 16-bit pointers are fixed, unless Lunar Magic stores a bank for each level.
+    fn fixed_bank(self, lunar_magic: Option<&LunarMagic>) -> Option<u8> {
+        let has_bank_table = |has: fn(&LunarMagic) -> bool| lunar_magic.is_some_and(has);
+        match self {
+            LevelDataType::Layer2Background(_) if !has_bank_table(LunarMagic::has_layer2_background_banks) => {
+                Some(LAYER2_BACKGROUND_BANK)
+            }
+            LevelDataType::Sprites if !has_bank_table(LunarMagic::has_sprite_data_banks) => Some(SPRITE_DATA_BANK),
+            _ => None,
+        }
+    }
+
+    /// Returns pointers to this kind of data for all levels, as they are stored in the ROM.
+    fn read_pointers(
+        self, disasm: &RomDisassembly, lunar_magic: Option<&LunarMagic>,
+    ) -> Result<Vec<AddrSnes>, RomError> {
+        let view = disasm.rom.view().slice_lorom(self.pointer_table())?;
+        match self {
+            LevelDataType::Layer1 | LevelDataType::Layer2 | LevelDataType::Layer2Background(_) => {
+                view.parse(count(map(le_u24, AddrSnes), LEVEL_COUNT))
+            }
+            LevelDataType::Sprites => {
+                let pointers = view.parse(count(le_u16, LEVEL_COUNT))?;
+                Ok((0..LEVEL_COUNT as u32)
+                    .zip(pointers)
+                    .map(|(level_num, ptr)| AddrSnes(ptr as u32).with_bank(sprite_data_bank(lunar_magic, level_num)))
+                    .collect())
+            }
+        }
+    }
+
+    /// Returns the address of data pointed to by `ptr`, or `None` if `ptr` points to a different kind of data.
+    fn data_addr(self, ptr: AddrSnes, level_num: u32, lunar_magic: Option<&LunarMagic>) -> Option<AddrSnes> {
+        match self {
+            LevelDataType::Layer1 | LevelDataType::Sprites => Some(ptr),
+            LevelDataType::Layer2 => (ptr.bank() != 0xFF).then_some(ptr),
+            LevelDataType::Layer2Background(_) => {
+                (ptr.bank() == 0xFF).then(|| ptr.with_bank(layer2_background_bank(lunar_magic, level_num)))
+            }
+        }
+    }
+
+    fn pointer_to(self, addr: AddrSnes) -> AddrSnes {
+        match self {
+            LevelDataType::Layer2Background(_) => addr.with_bank(0xFF),
+            _ => addr,
+        }
+    }
+
+    /// Size of the data currently stored at `addr`, including its header and terminator.
+    fn stored_size(self, disasm: &RomDisassembly, addr: AddrSnes) -> Result<usize, RomError> {
+        let data_addr = addr + self.header_size();
+        let view = disasm.rom.view().slice_lorom(SnesSlice::new(data_addr, usize::MAX))?;
+        let data_size = match self {
+            LevelDataType::Layer1 | LevelDataType::Layer2 => view.parse(ObjectLayer::parse)?.1,
+            LevelDataType::Layer2Background(compression) => {
+                compression.decompress(view.as_bytes()?).map_err(RomError::Decompress)?.1
+            }
+            LevelDataType::Sprites => view.parse(SpriteLayer::parse)?.1,
+        };
+        Ok(self.header_size() + data_size)
+    }
+
+    /// Compressed data is compared after decompression, so that unchanged data compressed differently than the
+    /// original is not considered modified.
+    fn same_contents(self, old_data: &[u8], new_data: &[u8]) -> bool {
+        match self {
+            LevelDataType::Layer2Background(compression) if old_data != new_data => {
+                let decompress = |data| compression.decompress(data).ok().map(|(tile_ids, _)| tile_ids);
+                decompress(old_data).is_some_and(|old_tile_ids| Some(old_tile_ids) == decompress(new_data))
+            }
+            _ => old_data == new_data,
+        }
+    }
+
+    fn write(
+        self, disasm: &mut RomDisassembly, level_num: u32, data: &[u8], lunar_magic: Option<&mut LunarMagic>,
+    ) -> Result<(), LevelSaveError> {
+        let lm = lunar_magic.as_deref();
+        let pointers = self.read_pointers(disasm, lm).map_err(|e| LevelSaveError::PointerRead(level_num, self, e))?;
+        let old_ptr = pointers[level_num as usize];
+        let shared = pointers.iter().filter(|&&ptr| ptr == old_ptr).count() > 1;
+
+        // If the level used a different kind of Layer2 data before, new data always goes to free space.
+        if let Some(old_addr) = self.data_addr(old_ptr, level_num, lm) {
+            let old_size =
+                self.stored_size(disasm, old_addr).map_err(|e| LevelSaveError::OriginalDataRead(level_num, self, e))?;
+            let old_data = disasm
+                .rom
+                .view()
+                .slice_lorom(SnesSlice::new(old_addr, old_size))
+                .and_then(|view| view.as_bytes())
+                .map_err(|e| LevelSaveError::OriginalDataRead(level_num, self, e))?;
+            if self.same_contents(old_data, data) {
+                return Ok(());
+            }
+
+            if !shared && data.len() <= old_size {
+                return disasm.rom.write_lorom(old_addr, data).map_err(|e| LevelSaveError::Write(level_num, self, e));
+            }
+        }
+
+        // Layer2 data of the other kind, which the pointer may have been referring to, is released as well.
+        let old_addr = match old_ptr.bank() {
+            0xFF => old_ptr.with_bank(layer2_background_bank(lm, level_num)),
+            _ => old_ptr,
+        };
+
+        if let Some(bank) = self.fixed_bank(lm) {
+            return Err(LevelSaveError::FixedBank(level_num, self, bank));
+        }
+        let new_addr = disasm.allocate(data.len(), FREE_SPACE_BANKS).map_err(|e| match e {
+            RomError::NoFreeSpace(size) => LevelSaveError::NoFreeSpace(level_num, self, size),
+            e => LevelSaveError::Write(level_num, self, e),
+        })?;
+        disasm.rom.write_lorom(new_addr, data).map_err(|e| LevelSaveError::Write(level_num, self, e))?;
+
+        let (header_kind, data_kind) = self.data_kinds();
+        let header_block =
+            header_kind.map(|kind| DataBlock { slice: SnesSlice::new(new_addr, self.header_size()), kind });
+        let data_block = DataBlock {
+            slice: SnesSlice::new(new_addr + self.header_size(), data.len() - self.header_size()),
+            kind:  data_kind,
+        };
+        for block in header_block.into_iter().chain([data_block]) {
+            disasm.mark_data_block(block).map_err(|e| LevelSaveError::Write(level_num, self, e))?;
+        }
+
+        let ptr_addr = self.pointer_table().begin + (self.pointer_size() * level_num as usize);
+        let ptr_bytes = &self.pointer_to(new_addr).0.to_le_bytes()[..self.pointer_size()];
+        disasm.rom.write_lorom(ptr_addr, ptr_bytes).map_err(|e| LevelSaveError::Write(level_num, self, e))?;
+        if let Some(lunar_magic) = lunar_magic {
+            match self {
+                LevelDataType::Layer2Background(_) => {
+                    lunar_magic.set_layer2_background_bank(disasm, level_num, new_addr.bank())
+                }
+                LevelDataType::Sprites => lunar_magic.set_sprite_data_bank(disasm, level_num, new_addr.bank()),
+                _ => Ok(()),
+            }
+            .map_err(|e| LevelSaveError::Write(level_num, self, e))?;
+        }
+
+        // Data that moved is released only if it was placed in free space before, vanilla data is left alone.
+        if !shared {
+            disasm.free(old_addr).map_err(|e| LevelSaveError::Write(level_num, self, e))?;
+        }
+        Ok(())
+    }
+}
+
+impl ValueOutOfRangeError {
+    pub(crate) fn check(name: &'static str, value: impl Into<u32>, max: u32) -> Result<(), Self> {
+        let value = value.into();
+        if value <= max {
+            Ok(())
+        } else {
+            Err(Self { name, value, max })
+        }
+    }
+}
+
+/// Bank of the level's sprite data, which Lunar Magic can move out of [`SPRITE_DATA_BANK`].
+fn sprite_data_bank(lunar_magic: Option<&LunarMagic>, level_num: u32) -> u8 {
+    lunar_magic.and_then(|lm| lm.sprite_data_bank(level_num)).unwrap_or(SPRITE_DATA_BANK)
+}
+
+/// Bank of the level's Layer2 background, which Lunar Magic can move out of [`LAYER2_BACKGROUND_BANK`].
+fn layer2_background_bank(lunar_magic: Option<&LunarMagic>, level_num: u32) -> u8 {
+    lunar_magic.and_then(|lm| lm.layer2_background_bank(level_num)).unwrap_or(LAYER2_BACKGROUND_BANK)
+}
+
+/// Compression of Layer2 backgrounds, which Lunar Magic can change from LC-RLE1.
+fn layer2_background_compression(lunar_magic: Option<&LunarMagic>) -> Compression {
+    lunar_magic.and_then(LunarMagic::layer2_background_compression).unwrap_or(Compression::LcRle1)
 }
@@ -1,159 +1,398 @@
-#![allow(dead_code)]
-
-use std::convert::TryInto;
-
 use nom::{
     bytes::complete::{tag, take},
     multi::many_till,
     IResult,
 };
+use thiserror::Error;
+
+use crate::level::ValueOutOfRangeError;
 
 pub const NON_EXIT_INSTANCE_SIZE: usize = 3;
 pub const EXIT_INSTANCE_SIZE: usize = 4;
 
+pub const MAX_SCREEN_NUMBER: u8 = 0x1F;
+
 pub type StandardObjectID = u8;
 pub type ExtendedObjectID = u8;
 
-#[derive(Debug, Clone)]
-pub struct StandardObject([u8; NON_EXIT_INSTANCE_SIZE]);
+pub const MAX_STANDARD_OBJECT_ID: StandardObjectID = 0x3F;
+/// Extended object numbers below this one encode exits and screen jumps.
+pub const MIN_EXTENDED_OBJECT_ID: ExtendedObjectID = 2;
 
-#[derive(Debug, Clone)]
-pub struct ExitObject([u8; EXIT_INSTANCE_SIZE]);
+// -------------------------------------------------------------------------------------------------
 
-#[derive(Debug, Clone)]
-pub struct ScreenJumpObject([u8; NON_EXIT_INSTANCE_SIZE]);
+#[derive(Debug, Error)]
+pub enum ObjectEncodeError {
+    #[error("Object {0}: {1}")]
+    OutOfRange(usize, ValueOutOfRangeError),
+    #[error("Object {0}: standard object number 0 is reserved for extended objects")]
+    ReservedStandardId(usize),
+    #[error("Object {0}: extended object number {1} is reserved for exits and screen jumps")]
+    ReservedExtendedId(usize, ExtendedObjectID),
+}
 
-#[derive(Debug, Clone)]
-pub struct ExtendedOtherObject([u8; NON_EXIT_INSTANCE_SIZE]);
+// -------------------------------------------------------------------------------------------------
 
-#[derive(Debug, Clone)]
-pub enum ExtendedInstance {
-    Exit(ExitObject),
-    ScreenJump(ScreenJumpObject),
-    Other(ExtendedOtherObject),
+/// `NBBYYYYY bbbbXXXX SSSSSSSS`
+///
+/// | Value      | Comment                |
+/// |------------|------------------------|
+/// | `N`        | New Screen flag        |
+/// | `BBbbbb`   | Standard object number |
+/// | `YYYYY`    | Y position             |
+/// | `XXXX`     | X position             |
+/// | `SSSSSSSS` | Settings               |
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct StandardObject {
+    pub screen:   u8,
+    pub x:        u8,
+    pub y:        u8,
+    pub id:       StandardObjectID,
+    pub settings: u8,
 }
 
-#[derive(Debug, Clone)]
+/// `N00YYYYY 0000XXXX BBBBBBBB`
+///
+/// | Value      | Comment                |
+/// |------------|------------------------|
+/// | `N`        | New Screen flag        |
+/// | `YYYYY`    | Y position             |
+/// | `XXXX`     | X position             |
+/// | `BBBBBBBB` | Extended object number |
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct ExtendedObject {
+    pub screen: u8,
+    pub x:      u8,
+    pub y:      u8,
+    pub id:     ExtendedObjectID,
+}
+
+/// `N00ppppp 0000wush 00000000 dddddddd`
+///
+/// | Value       | Comment                               |
+/// |-------------|---------------------------------------|
+/// | `N`         | New Screen flag                       |
+/// | `ppppp`     | Screen number                         |
+/// | `w`         | Midway                                |
+/// | `u`         | Unused                                |
+/// | `s`         | Secondary exit flag                   |
+/// | `hdddddddd` | Destination level / secondary exit ID |
+///
+/// The new screen flag of an exit moves the objects that follow it to the next screen, like for any other object.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct ExitObject {
+    pub new_screen:  bool,
+    pub screen:      u8,
+    pub midway:      bool,
+    pub unused:      bool,
+    pub secondary:   bool,
+    pub destination: u16,
+}
+
+/// `N00HHHHH 00000000 00000001`
+///
+/// | Value   | Comment                              |
+/// |---------|--------------------------------------|
+/// | `N`     | New Screen flag, overridden by jump  |
+/// | `HHHHH` | Screen number                        |
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct ScreenJumpObject {
+    pub new_screen: bool,
+    pub screen:     u8,
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
 pub enum ObjectInstance {
     Standard(StandardObject),
-    Extended(ExtendedInstance),
+    Extended(ExtendedObject),
+    Exit(ExitObject),
+    ScreenJump(ScreenJumpObject),
 }
 
-#[derive(Debug, Clone)]
+/// Objects of a level layer in the order in which they are stored in the ROM.
+///
+/// Standard and extended objects hold the absolute number of the screen they're on. The new screen flags are derived
+/// from it when encoding, and screen jumps are inserted wherever a flag is not enough to reach an object's screen.
+/// Screen jumps that are already in the list are kept as they are.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
 pub struct ObjectLayer {
-    _objects: Vec<ObjectInstance>,
+    pub objects: Vec<ObjectInstance>,
 }
 
-impl ExitObject {
-    pub fn screen_number(&self) -> u8 {
-        // ---ppppp -------- -------- --------
-        // screen_number = ppppp
-        self.0[0] & 0b11111
+// -------------------------------------------------------------------------------------------------
+
+impl StandardObject {
+    fn from_bytes(bytes: [u8; NON_EXIT_INSTANCE_SIZE], screen: u8) -> Self {
+        // -BB----- bbbb---- --------
+        // id = BBbbbb
+        let id_hi = (bytes[0] >> 1) & 0b110000;
+        let id_lo = bytes[1] >> 4;
+        Self { screen, x: bytes[1] & 0b1111, y: bytes[0] & 0b11111, id: id_hi | id_lo, settings: bytes[2] }
     }
 
-    pub fn secondary_exit(&self) -> bool {
-        // -------- ------s- -------- --------
-        // secondary_exit = s
-        (self.0[1] & 0b10) != 0
+    fn validate(&self, index: usize) -> Result<(), ObjectEncodeError> {
+        if self.id == 0 {
+            return Err(ObjectEncodeError::ReservedStandardId(index));
+        }
+        check_position(index, self.screen, self.x, self.y)?;
+        check_field(index, "Standard object number", self.id, MAX_STANDARD_OBJECT_ID as u32)
     }
 
-    pub fn destination_level(&self) -> u16 {
-        // -------- -------D -------- dddddddd
-        // destination_level = Ddddddddd
-        let hi = (self.0[1] as u16 & 0b1) << 8;
-        let lo = self.0[3] as u16;
-        hi | lo
+    fn to_bytes(self, new_screen: bool) -> [u8; NON_EXIT_INSTANCE_SIZE] {
+        [
+            ((new_screen as u8) << 7) | ((self.id & 0b110000) << 1) | (self.y & 0b11111),
+            ((self.id & 0b1111) << 4) | (self.x & 0b1111),
+            self.settings,
+        ]
     }
 }
 
-impl StandardObject {
-    pub fn new_screen(&self) -> bool {
-        // N----- -------- --------
-        // new_screen = N
-        (self.0[0] >> 7) != 0
+impl ExtendedObject {
+    fn from_bytes(bytes: [u8; NON_EXIT_INSTANCE_SIZE], screen: u8) -> Self {
+        Self { screen, x: bytes[1] & 0b1111, y: bytes[0] & 0b11111, id: bytes[2] }
     }
 
-    pub fn std_obj_num(&self) -> StandardObjectID {
-        // -BB----- bbbb---- --------
-        // std_obj_num = BBbbbb
-        let hi = (self.0[0] >> 1) & 0b110000;
-        let lo = (self.0[1] >> 4) & 0b1111;
-        hi | lo
+    fn validate(&self, index: usize) -> Result<(), ObjectEncodeError> {
+        if self.id < MIN_EXTENDED_OBJECT_ID {
+            return Err(ObjectEncodeError::ReservedExtendedId(index, self.id));
+        }
+        check_position(index, self.screen, self.x, self.y)
     }
 
-    pub fn ext_obj_num(&self) -> Option<ExtendedObjectID> {
-        // -------- -------- NNNNNNNN
-        // ext_obj_num = NNNNNNNN
-        self.is_extended().then_some(self.0[2])
+    fn to_bytes(self, new_screen: bool) -> [u8; NON_EXIT_INSTANCE_SIZE] {
+        [((new_screen as u8) << 7) | (self.y & 0b11111), self.x & 0b1111, self.id]
     }
+}
 
-    pub fn xy_pos(&self) -> (u8, u8) {
-        // ---YYYYY ----XXXX --------
-        // xy_pos = (XXXX, YYYYY)
-        let x = self.0[1] & 0b1111;
-        let y = self.0[0] & 0b11111;
-        (x, y)
+impl ExitObject {
+    fn from_bytes(bytes: [u8; EXIT_INSTANCE_SIZE]) -> Self {
+        Self {
+            new_screen:  (bytes[0] & 0x80) != 0,
+            screen:      bytes[0] & 0b11111,
+            midway:      (bytes[1] & 0b1000) != 0,
+            unused:      (bytes[1] & 0b100) != 0,
+            secondary:   (bytes[1] & 0b10) != 0,
+            destination: ((bytes[1] as u16 & 0b1) << 8) | bytes[3] as u16,
+        }
     }
 
-    pub fn settings(&self) -> u8 {
-        // -------- -------- SSSSSSSS
-        // settings = SSSSSSSS
-        self.0[2]
+    fn validate(&self, index: usize) -> Result<(), ObjectEncodeError> {
+        check_field(index, "Screen number", self.screen, MAX_SCREEN_NUMBER as u32)?;
+        check_field(index, "Exit destination", self.destination, 0x1FF)
     }
 
-    pub fn is_extended(&self) -> bool {
-        self.std_obj_num() == 0
+    fn to_bytes(self) -> [u8; EXIT_INSTANCE_SIZE] {
+        [
+            ((self.new_screen as u8) << 7) | (self.screen & 0b11111),
+            ((self.midway as u8) << 3)
+                | ((self.unused as u8) << 2)
+                | ((self.secondary as u8) << 1)
+                | ((self.destination >> 8) as u8 & 0b1),
+            0,
+            self.destination as u8,
+        ]
     }
 }
 
 impl ScreenJumpObject {
-    pub fn screen_number(&self) -> u8 {
-        // ---HHHHH -------- --------
-        // screen_number = HHHHH
-        self.0[0] & 0b11111
+    fn from_bytes(bytes: [u8; NON_EXIT_INSTANCE_SIZE]) -> Self {
+        Self { new_screen: (bytes[0] & 0x80) != 0, screen: bytes[0] & 0b11111 }
+    }
+
+    fn validate(&self, index: usize) -> Result<(), ObjectEncodeError> {
+        check_field(index, "Screen number", self.screen, MAX_SCREEN_NUMBER as u32)
+    }
+
+    fn to_bytes(self) -> [u8; NON_EXIT_INSTANCE_SIZE] {
+        [((self.new_screen as u8) << 7) | (self.screen & 0b11111), 0, 1]
     }
 }
 
 impl ObjectInstance {
     pub fn is_extended(&self) -> bool {
+        !matches!(self, ObjectInstance::Standard(_))
+    }
+
+    /// Screen on which the object is placed, or to which the exit or screen jump refers.
+    pub fn screen(&self) -> u8 {
         match self {
-            ObjectInstance::Standard(i) => i.is_extended(),
-            ObjectInstance::Extended(_) => true,
+            ObjectInstance::Standard(object) => object.screen,
+            ObjectInstance::Extended(object) => object.screen,
+            ObjectInstance::Exit(exit) => exit.screen,
+            ObjectInstance::ScreenJump(jump) => jump.screen,
         }
     }
 }
 
 impl ObjectLayer {
-    fn parse_object(input: &[u8]) -> IResult<&[u8], ObjectInstance> {
-        let (input, first_three) = take(3usize)(input)?;
+    fn parse_object<'i>(input: &'i [u8], current_screen: &mut u8) -> IResult<&'i [u8], ObjectInstance> {
+        let (input, first_three) = take(NON_EXIT_INSTANCE_SIZE)(input)?;
+        let first_three: [u8; NON_EXIT_INSTANCE_SIZE] = first_three.try_into().unwrap();
+        if first_three[0] & 0x80 != 0 {
+            *current_screen = current_screen.wrapping_add(1);
+        }
         if first_three[0] & 0b01100000 == 0 && first_three[1] & 0b11110000 == 0 {
             // Extended objects
             match first_three[2] {
                 0 => {
                     let (input, last) = take(1usize)(input)?;
-                    let instance = ExtendedInstance::Exit(ExitObject([first_three, last].concat().try_into().unwrap()));
-                    Ok((input, ObjectInstance::Extended(instance)))
+                    let [b0, b1, b2] = first_three;
+                    let exit = ExitObject::from_bytes([b0, b1, b2, last[0]]);
+                    Ok((input, ObjectInstance::Exit(exit)))
                 }
                 1 => {
-                    let instance = ExtendedInstance::ScreenJump(ScreenJumpObject(first_three.try_into().unwrap()));
-                    Ok((input, ObjectInstance::Extended(instance)))
+                    let jump = ScreenJumpObject::from_bytes(first_three);
+                    *current_screen = jump.screen;
+                    Ok((input, ObjectInstance::ScreenJump(jump)))
                 }
                 _ => {
-                    let instance = ExtendedInstance::Other(ExtendedOtherObject(first_three.try_into().unwrap()));
-                    Ok((input, ObjectInstance::Extended(instance)))
+                    let object = ExtendedObject::from_bytes(first_three, *current_screen);
+                    Ok((input, ObjectInstance::Extended(object)))
                 }
             }
         } else {
             // Standard objects
-            let instance = StandardObject(first_three.try_into().unwrap());
-            Ok((input, ObjectInstance::Standard(instance)))
+            let object = StandardObject::from_bytes(first_three, *current_screen);
+            Ok((input, ObjectInstance::Standard(object)))
         }
     }
 
     /// Returns self and the number of bytes consumed by parsing.
     pub fn parse(input: &[u8]) -> IResult<&[u8], (Self, usize)> {
-        let (rest, (objects, _)) = many_till(Self::parse_object, tag(&[0xFFu8]))(input)?;
+        let mut current_screen = 0;
+        let parse_object = |input| Self::parse_object(input, &mut current_screen);
+        let (rest, (objects, _)) = many_till(parse_object, tag(&[0xFFu8]))(input)?;
         let bytes_consumed = input.len() - rest.len();
-        Ok((rest, (Self { _objects: objects }, bytes_consumed)))
+        Ok((rest, (Self { objects }, bytes_consumed)))
+    }
+
+    /// Encodes object data, including the terminating `0xFF` byte.
+    ///
+    /// Fails if an object has a field which doesn't fit in the bits reserved for it, or an object number which would
+    /// be read back as a different kind of object.
+    pub fn to_bytes(&self) -> Result<Vec<u8>, ObjectEncodeError> {
+        let mut bytes = Vec::with_capacity(self.objects.len() * NON_EXIT_INSTANCE_SIZE + 1);
+        let mut current_screen = 0;
+        for (index, object) in self.objects.iter().enumerate() {
+            match *object {
+                ObjectInstance::Standard(object) => {
+                    object.validate(index)?;
+                    let new_screen = Self::enter_screen(&mut bytes, &mut current_screen, object.screen);
+                    bytes.extend(object.to_bytes(new_screen));
+                }
+                ObjectInstance::Extended(object) => {
+                    object.validate(index)?;
+                    let new_screen = Self::enter_screen(&mut bytes, &mut current_screen, object.screen);
+                    bytes.extend(object.to_bytes(new_screen));
+                }
+                ObjectInstance::Exit(exit) => {
+                    exit.validate(index)?;
+                    bytes.extend(exit.to_bytes());
+                    if exit.new_screen {
+                        current_screen = current_screen.wrapping_add(1);
+                    }
+                }
+                ObjectInstance::ScreenJump(jump) => {
+                    jump.validate(index)?;
+                    bytes.extend(jump.to_bytes());
+                    current_screen = jump.screen;
+                }
+            }
+        }
+        bytes.push(0xFF);
+        Ok(bytes)
+    }
+
+    /// Moves to the given screen before encoding an object placed on it. Returns whether the object needs to have
+    /// the new screen flag set, or inserts a screen jump if the flag is not enough.
+    fn enter_screen(bytes: &mut Vec<u8>, current_screen: &mut u8, screen: u8) -> bool {
+        let new_screen = screen == current_screen.wrapping_add(1);
+        if screen != *current_screen && !new_screen {
+            bytes.extend(ScreenJumpObject { new_screen: false, screen }.to_bytes());
+        }
+        *current_screen = screen;
+        new_screen
+    }
+}
+
+fn check_field(index: usize, name: &'static str, value: impl Into<u32>, max: u32) -> Result<(), ObjectEncodeError> {
+    ValueOutOfRangeError::check(name, value, max).map_err(|e| ObjectEncodeError::OutOfRange(index, e))
+}
+
+fn check_position(index: usize, screen: u8, x: u8, y: u8) -> Result<(), ObjectEncodeError> {
+    check_field(index, "Screen number", screen, MAX_SCREEN_NUMBER as u32)?;
+    check_field(index, "X position", x, 0b1111)?;
+    check_field(index, "Y position", y, 0b11111)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip_with_new_screen_flags() {
+        #[rustfmt::skip]
+        let bytes = [
+            0x02, 0x13, 0x05,       // standard object 0x01 on screen 0
+            0x81, 0x00, 0x00, 0x05, // exit on screen 1 with new screen flag, moving to screen 1
+            0x03, 0x20, 0x00,       // standard object 0x02 on screen 1
+            0x84, 0x00, 0x01,       // screen jump to screen 4 with new screen flag
+            0x80, 0x02, 0x30,       // extended object 0x30 on screen 5
+            0x01, 0x0F, 0x00, 0x23, // secondary midway exit to 0x123 with unused bit
+            0xFF,
+        ];
+        let (rest, (layer, consumed)) = ObjectLayer::parse(&bytes).unwrap();
+        assert!(rest.is_empty());
+        assert_eq!(consumed, bytes.len());
+        assert_eq!(layer.objects[2].screen(), 1);
+        assert_eq!(layer.objects[4].screen(), 5);
+        assert_eq!(layer.to_bytes().unwrap(), bytes);
+    }
+
+    #[test]
+    fn test_encoding_inserts_screen_jumps() {
+        let layer = ObjectLayer {
+            objects: vec![
+                ObjectInstance::Standard(StandardObject {
+                    screen:   1,
+                    x:        2,
+                    y:        3,
+                    id:       0x10,
+                    settings: 0,
+                }),
+                ObjectInstance::Extended(ExtendedObject { screen: 6, x: 0, y: 0, id: 0x40 }),
+            ],
+        };
+        let bytes = layer.to_bytes().unwrap();
+        assert_eq!(bytes, [0xA3, 0x02, 0x00, 0x06, 0x00, 0x01, 0x00, 0x00, 0x40, 0xFF]);
+        let (_, (reparsed, _)) = ObjectLayer::parse(&bytes).unwrap();
+        assert_eq!(reparsed.objects[0], layer.objects[0]);
+        assert_eq!(reparsed.objects[2], layer.objects[1]);
+    }
+
+    #[test]
+    fn test_encoding_rejects_invalid_objects() {
+        let encode = |object| ObjectLayer { objects: vec![object] }.to_bytes();
+        let standard = StandardObject { screen: 0, x: 0, y: 0, id: 1, settings: 0 };
+        assert!(matches!(
+            encode(ObjectInstance::Standard(StandardObject { id: 0, ..standard })),
+            Err(ObjectEncodeError::ReservedStandardId(0))
+        ));
+        assert!(matches!(
+            encode(ObjectInstance::Standard(StandardObject { screen: 0x20, ..standard })),
+            Err(ObjectEncodeError::OutOfRange(0, _))
+        ));
+        assert!(matches!(
+            encode(ObjectInstance::Standard(StandardObject { id: 0x40, ..standard })),
+            Err(ObjectEncodeError::OutOfRange(0, _))
+        ));
+        assert!(matches!(
+            encode(ObjectInstance::Extended(ExtendedObject { screen: 0, x: 0, y: 0, id: 1 })),
+            Err(ObjectEncodeError::ReservedExtendedId(0, 1))
+        ));
+        assert!(matches!(
+            encode(ObjectInstance::Exit(ExitObject { destination: 0x200, ..ExitObject::default() })),
+            Err(ObjectEncodeError::OutOfRange(0, _))
+        ));
     }
 }
@@ -1,13 +1,15 @@
 use crate::{
     disassembler::binary_block::{DataBlock, DataKind},
+    level::{ValueOutOfRangeError, LEVEL_COUNT},
     snes_utils::{addr::AddrSnes, rom::noop_error_mapper, rom_slice::SnesSlice},
     RomDisassembly,
     RomError,
 };
 
+/// First of four consecutive tables, each holding one byte of every secondary entrance.
 pub const SECONDARY_ENTRANCE_TABLE: SnesSlice = SnesSlice::new(AddrSnes(0x05F800), 512);
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Eq, PartialEq)]
 pub struct SecondaryEntrance([u8; 4]);
 
 impl SecondaryEntrance {
@@ -22,6 +24,15 @@ impl SecondaryEntrance {
         Ok(Self(bytes))
     }
 
+    /// Writes each byte of the entrance into its table.
+    pub fn write_to_rom(&self, disasm: &mut RomDisassembly, entrance_id: usize) -> Result<(), RomError> {
+        for (i, byte) in self.0.iter().enumerate() {
+            let addr = SECONDARY_ENTRANCE_TABLE.skip_forward(i).begin + entrance_id;
+            disasm.rom.write_lorom(addr, &[*byte])?;
+        }
+        Ok(())
+    }
+
     pub fn destination_level(&self) -> u16 {
         // dddddddd -------- -------- ----D---
         // destination_level = Ddddddddd
@@ -30,16 +41,35 @@ impl SecondaryEntrance {
         hi | lo
     }
 
+    pub fn set_destination_level(&mut self, level_num: u16) -> Result<(), ValueOutOfRangeError> {
+        ValueOutOfRangeError::check("Destination level", level_num, LEVEL_COUNT as u32 - 1)?;
+        self.0[0] = level_num as u8;
+        self.0[3] = (self.0[3] & !0b1000) | ((level_num >> 5) as u8 & 0b1000);
+        Ok(())
+    }
+
     pub fn bg_initial_pos(&self) -> u8 {
         // -------- bb------ -------- --------
-        // by_initial_pos = bb
+        // bg_initial_pos = bb
         self.0[1] >> 6
     }
 
+    pub fn set_bg_initial_pos(&mut self, pos: u8) -> Result<(), ValueOutOfRangeError> {
+        ValueOutOfRangeError::check("BG initial position", pos, 0b11)?;
+        self.0[1] = (self.0[1] & 0b00111111) | (pos << 6);
+        Ok(())
+    }
+
     pub fn fg_initial_pos(&self) -> u8 {
         // -------- --ff---- -------- --------
         // fg_initial_pos = ff
-        self.0[1] >> 4
+        (self.0[1] >> 4) & 0b11
+    }
+
+    pub fn set_fg_initial_pos(&mut self, pos: u8) -> Result<(), ValueOutOfRangeError> {
+        ValueOutOfRangeError::check("FG initial position", pos, 0b11)?;
+        self.0[1] = (self.0[1] & 0b11001111) | (pos << 4);
+        Ok(())
     }
 
     pub fn entrance_xy_pos(&self) -> (u8, u8) {
@@ -50,9 +80,62 @@ impl SecondaryEntrance {
         (x, y)
     }
 
+    pub fn set_entrance_xy_pos(&mut self, x: u8, y: u8) -> Result<(), ValueOutOfRangeError> {
+        ValueOutOfRangeError::check("Entrance X position", x, 0b111)?;
+        ValueOutOfRangeError::check("Entrance Y position", y, 0b1111)?;
+        self.0[1] = (self.0[1] & 0b11110000) | y;
+        self.0[2] = (self.0[2] & 0b00011111) | (x << 5);
+        Ok(())
+    }
+
     pub fn screen_number(&self) -> u8 {
         // -------- -------- ---SSSSS --------
         // screen_number = SSSSS
         self.0[2] & 0b11111
     }
+
+    pub fn set_screen_number(&mut self, screen: u8) -> Result<(), ValueOutOfRangeError> {
+        ValueOutOfRangeError::check("Screen number", screen, 0b11111)?;
+        self.0[2] = (self.0[2] & 0b11100000) | screen;
+        Ok(())
+    }
+
+    /// What Mario does when entering the level through this entrance, e.g. coming out of a pipe.
+    pub fn entry_action(&self) -> u8 {
+        // -------- -------- -------- -AAA----
+        // entry_action = AAA
+        (self.0[3] >> 4) & 0b111
+    }
+
+    pub fn set_entry_action(&mut self, action: u8) -> Result<(), ValueOutOfRangeError> {
+        ValueOutOfRangeError::check("Entry action", action, 0b111)?;
+        self.0[3] = (self.0[3] & 0b10001111) | (action << 4);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_setters_keep_other_fields() {
+        let mut entrance = SecondaryEntrance([0xFF; 4]);
+        entrance.set_destination_level(0x0C5).unwrap();
+        entrance.set_fg_initial_pos(1).unwrap();
+        entrance.set_entrance_xy_pos(2, 9).unwrap();
+        entrance.set_entry_action(5).unwrap();
+
+        assert_eq!(entrance.destination_level(), 0x0C5);
+        assert_eq!(entrance.bg_initial_pos(), 0b11);
+        assert_eq!(entrance.fg_initial_pos(), 1);
+        assert_eq!(entrance.entrance_xy_pos(), (2, 9));
+        assert_eq!(entrance.screen_number(), 0b11111);
+        assert_eq!(entrance.entry_action(), 5);
+        assert_eq!(entrance.0[3] & 0b10000111, 0b10000111);
+
+        assert!(entrance.set_destination_level(0x200).is_err());
+        assert!(entrance.set_screen_number(0x20).is_err());
+        assert_eq!(entrance.destination_level(), 0x0C5);
+    }
 }
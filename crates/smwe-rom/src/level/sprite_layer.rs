@@ -5,20 +5,49 @@ use nom::{
     multi::many_till,
     IResult,
 };
+use thiserror::Error;
+
+use crate::level::{headers::SpriteHeader, ValueOutOfRangeError};
 
 pub const SPRITE_INSTANCE_SIZE: usize = 3;
 
+pub const MAX_SPRITE_SCREEN_NUMBER: u8 = 0x1F;
+
 pub type SpriteID = u8;
 
-#[derive(Debug, Clone)]
-pub struct SpriteInstance([u8; SPRITE_INSTANCE_SIZE]);
+// -------------------------------------------------------------------------------------------------
+
+#[derive(Debug, Error)]
+pub enum SpriteError {
+    #[error(transparent)]
+    OutOfRange(#[from] ValueOutOfRangeError),
+    #[error("Sprite on screen {0:#X} at Y position 0x1F with extra bits 3 would be read as the end of sprite data")]
+    Terminator(u8),
+}
+
+// -------------------------------------------------------------------------------------------------
+
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct SpriteInstance(pub [u8; SPRITE_INSTANCE_SIZE]);
 
-#[derive(Debug, Clone)]
+/// Sprites of a level in the order in which they are stored in the ROM.
+///
+/// The game expects sprites to be sorted by screen number, which is enforced when encoding.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
 pub struct SpriteLayer {
-    _sprites: Vec<SpriteInstance>,
+    pub sprites: Vec<SpriteInstance>,
 }
 
 impl SpriteInstance {
+    pub fn new(sprite_id: SpriteID, screen_number: u8, (x, y): (u8, u8), extra_bits: u8) -> Result<Self, SpriteError> {
+        let mut sprite = Self::default();
+        sprite.set_sprite_id(sprite_id);
+        sprite.set_screen_number(screen_number)?;
+        sprite.set_xy_pos(x, y)?;
+        sprite.set_extra_bits(extra_bits)?;
+        Ok(sprite)
+    }
+
     pub fn xy_pos(&self) -> (u8, u8) {
         // yyyy---Y XXXX---- --------
         // xy_pos = (XXXX, Yyyyy)
@@ -50,6 +79,54 @@ impl SpriteInstance {
         // sprite_id = NNNNNNNN
         self.0[2]
     }
+
+    pub fn set_xy_pos(&mut self, x: u8, y: u8) -> Result<(), SpriteError> {
+        ValueOutOfRangeError::check("X position", x, 0b1111)?;
+        ValueOutOfRangeError::check("Y position", y, 0b11111)?;
+        // yyyy---Y XXXX---- --------
+        let mut sprite = *self;
+        sprite.0[0] = (sprite.0[0] & 0b00001110) | ((y & 0b1111) << 4) | (y >> 4);
+        sprite.0[1] = (sprite.0[1] & 0b00001111) | (x << 4);
+        self.replace_with(sprite)
+    }
+
+    pub fn set_extra_bits(&mut self, extra_bits: u8) -> Result<(), SpriteError> {
+        ValueOutOfRangeError::check("Extra bits", extra_bits, 0b11)?;
+        // ----EE-- -------- --------
+        let mut sprite = *self;
+        sprite.0[0] = (sprite.0[0] & 0b11110011) | (extra_bits << 2);
+        self.replace_with(sprite)
+    }
+
+    pub fn set_screen_number(&mut self, screen_number: u8) -> Result<(), SpriteError> {
+        ValueOutOfRangeError::check("Screen number", screen_number, MAX_SPRITE_SCREEN_NUMBER as u32)?;
+        // ------S- ----ssss --------
+        let mut sprite = *self;
+        sprite.0[0] = (sprite.0[0] & 0b11111101) | ((screen_number >> 3) & 0b10);
+        sprite.0[1] = (sprite.0[1] & 0b11110000) | (screen_number & 0b1111);
+        self.replace_with(sprite)
+    }
+
+    pub fn set_sprite_id(&mut self, sprite_id: SpriteID) {
+        // -------- -------- NNNNNNNN
+        self.0[2] = sprite_id;
+    }
+
+    /// A sprite whose first byte is `0xFF` is read by the game as the end of sprite data, which happens when it is
+    /// placed at Y position `0x1F` on screen `0x10` or higher, with both extra bits set.
+    pub fn validate(&self) -> Result<(), SpriteError> {
+        if self.0[0] == 0xFF {
+            Err(SpriteError::Terminator(self.screen_number()))
+        } else {
+            Ok(())
+        }
+    }
+
+    fn replace_with(&mut self, sprite: Self) -> Result<(), SpriteError> {
+        sprite.validate()?;
+        *self = sprite;
+        Ok(())
+    }
 }
 
 impl SpriteLayer {
@@ -59,6 +136,90 @@ impl SpriteLayer {
         let (rest, (sprites_raw, _)) = read_sprite_layer(input)?;
         let sprites = sprites_raw.into_iter().map(|spr| SpriteInstance(spr.try_into().unwrap())).collect();
         let bytes_consumed = input.len() - rest.len();
-        Ok((rest, (Self { _sprites: sprites }, bytes_consumed)))
+        Ok((rest, (Self { sprites }, bytes_consumed)))
+    }
+
+    /// Sorts sprites by screen number, keeping the order of sprites within the same screen.
+    pub fn sort(&mut self) {
+        self.sprites.sort_by_key(SpriteInstance::screen_number);
+    }
+
+    /// Encodes sprite data sorted by screen number, including the terminating `0xFF` byte.
+    ///
+    /// Fails if a sprite would be read as the terminator, see [`SpriteInstance::validate`].
+    pub fn to_bytes(&self) -> Result<Vec<u8>, SpriteError> {
+        let mut sorted = self.clone();
+        sorted.sort();
+        let mut bytes = Vec::with_capacity(sorted.sprites.len() * SPRITE_INSTANCE_SIZE + 1);
+        for sprite in sorted.sprites.iter() {
+            sprite.validate()?;
+            bytes.extend(sprite.0);
+        }
+        bytes.push(0xFF);
+        Ok(bytes)
+    }
+
+    /// Encodes sprite data preceded by the sprite header, in the format in which it is stored in the ROM.
+    pub fn to_bytes_with_header(&self, header: &SpriteHeader) -> Result<Vec<u8>, SpriteError> {
+        let mut bytes = vec![header.0];
+        bytes.extend(self.to_bytes()?);
+        Ok(bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_setters_keep_other_fields() {
+        let mut sprite = SpriteInstance::new(0x35, 0x13, (0xA, 0x1B), 2).unwrap();
+        assert_eq!(sprite.sprite_id(), 0x35);
+        assert_eq!(sprite.screen_number(), 0x13);
+        assert_eq!(sprite.xy_pos(), (0xA, 0x1B));
+        assert_eq!(sprite.extra_bits(), 2);
+
+        sprite.set_xy_pos(0x3, 0x04).unwrap();
+        sprite.set_screen_number(0x02).unwrap();
+        assert_eq!(sprite.xy_pos(), (0x3, 0x04));
+        assert_eq!(sprite.screen_number(), 0x02);
+        assert_eq!(sprite.extra_bits(), 2);
+        assert_eq!(sprite.sprite_id(), 0x35);
+
+        assert!(matches!(sprite.set_xy_pos(0x10, 0), Err(SpriteError::OutOfRange(_))));
+        assert!(matches!(sprite.set_xy_pos(0, 0x20), Err(SpriteError::OutOfRange(_))));
+        assert!(matches!(sprite.set_extra_bits(4), Err(SpriteError::OutOfRange(_))));
+        assert!(matches!(sprite.set_screen_number(0x20), Err(SpriteError::OutOfRange(_))));
+        assert_eq!(sprite.xy_pos(), (0x3, 0x04));
+        assert_eq!(sprite.screen_number(), 0x02);
+        assert_eq!(sprite.extra_bits(), 2);
+    }
+
+    #[test]
+    fn test_rejects_terminator() {
+        assert!(matches!(SpriteInstance::new(0, 0x10, (0, 0x1F), 3), Err(SpriteError::Terminator(0x10))));
+        assert!(SpriteInstance::new(0, 0x0F, (0, 0x1F), 3).is_ok());
+
+        let mut sprite = SpriteInstance::new(0, 0x10, (0, 0x1F), 2).unwrap();
+        assert!(matches!(sprite.set_extra_bits(3), Err(SpriteError::Terminator(0x10))));
+        assert_eq!(sprite.extra_bits(), 2);
+
+        let layer = SpriteLayer { sprites: vec![SpriteInstance([0xFF, 0x00, 0x00])] };
+        assert!(matches!(layer.to_bytes(), Err(SpriteError::Terminator(0x10))));
+    }
+
+    #[test]
+    fn test_encoding_sorts_by_screen() {
+        let sprite = |id, screen| SpriteInstance::new(id, screen, (0, 0), 0).unwrap();
+        let layer = SpriteLayer { sprites: vec![sprite(1, 0x12), sprite(2, 0x03), sprite(3, 0x12), sprite(4, 0x00)] };
+        let bytes = layer.to_bytes().unwrap();
+        assert_eq!(bytes.len(), 4 * SPRITE_INSTANCE_SIZE + 1);
+        assert_eq!(bytes.last(), Some(&0xFF));
+
+        let (rest, (decoded, consumed)) = SpriteLayer::parse(&bytes).unwrap();
+        assert!(rest.is_empty());
+        assert_eq!(consumed, bytes.len());
+        let order: Vec<_> = decoded.sprites.iter().map(|s| (s.sprite_id(), s.screen_number())).collect();
+        assert_eq!(order, [(4, 0x00), (2, 0x03), (1, 0x12), (3, 0x12)]);
     }
 }
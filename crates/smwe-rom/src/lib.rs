@@ -1,16 +1,22 @@
 #![allow(clippy::identity_op)]
 
+pub mod audio;
 pub mod compression;
 pub mod disassembler;
 pub mod graphics;
 pub mod internal_header;
 pub mod level;
+pub mod lunar_magic;
 pub mod objects;
+pub mod overworld;
+pub mod patch;
 pub mod snes_utils;
+pub mod text;
 
 use std::{fs, path::Path};
 
 use crate::{
+    audio::Audio,
     disassembler::{
         binary_block::{DataBlock, DataKind},
         RomDisassembly,
@@ -20,14 +26,18 @@ use crate::{
     level::{
         secondary_entrance::{SecondaryEntrance, SECONDARY_ENTRANCE_TABLE},
         Level,
+        LevelSaveError,
         LEVEL_COUNT,
     },
+    lunar_magic::LunarMagic,
     objects::tilesets::Tilesets,
+    overworld::Overworld,
     snes_utils::{
         addr::AddrSnes,
-        rom::{Rom, RomError},
+        rom::{CopierHeader, Rom, RomError},
         rom_slice::SnesSlice,
     },
+    text::Text,
 };
 
 // -------------------------------------------------------------------------------------------------
@@ -40,6 +50,19 @@ pub struct SmwRom {
     pub secondary_entrances: Vec<SecondaryEntrance>,
     pub gfx:                 Gfx,
     pub map16_tilesets:      Tilesets,
+    /// Absent if the overworld's data is not laid out like in the original game, e.g. when it has been edited with a
+    /// tool which moves it.
+    pub overworld:           Option<Overworld>,
+    /// Absent if the text is not laid out like in the original game.
+    pub text:                Option<Text>,
+    /// Absent if the sound engine's uploads are not laid out like in the original game, e.g. when music and samples
+    /// have been replaced with a tool which moves them.
+    pub audio:               Option<Audio>,
+    /// Present if the ROM has been edited with Lunar Magic.
+    pub lunar_magic:         Option<LunarMagic>,
+    /// Copier header of the file the ROM was read from. It is written back on save, so it can be replaced or removed
+    /// to save the ROM in a different form.
+    pub copier_header:       Option<CopierHeader>,
 }
 
 // -------------------------------------------------------------------------------------------------
@@ -48,9 +71,9 @@ impl SmwRom {
     pub fn from_file<P: AsRef<Path>>(path: P) -> anyhow::Result<Self> {
         log::info!("Reading ROM from file: {}", path.as_ref().display());
 
-        let bytes = fs::read(path)?;
+        let (copier_header, bytes) = CopierHeader::split_from(fs::read(path)?)?;
         let rom = Rom::new(bytes)?;
-        let smw_rom = Self::from_rom(rom);
+        let smw_rom = Self::from_rom(rom).map(|smw_rom| Self { copier_header, ..smw_rom });
 
         if smw_rom.is_ok() {
             log::info!("Success parsing ROM");
@@ -62,6 +85,9 @@ impl SmwRom {
     pub fn from_rom(rom: Rom) -> anyhow::Result<Self> {
         log::info!("Parsing internal ROM header");
         let internal_header = RomInternalHeader::parse(&rom)?;
+        if !internal_header.has_valid_checksum(&rom) {
+            log::warn!("ROM checksum doesn't match the one in the internal header");
+        }
 
         log::info!("Creating disassembly map");
         let mut disassembly = RomDisassembly::new(rom, &internal_header);
@@ -75,25 +101,123 @@ impl SmwRom {
             |_| InternalHeaderParseError::NotFound,
         )?;
 
+        log::info!("Detecting Lunar Magic");
+        let lunar_magic = LunarMagic::detect(&mut disassembly)?;
+
         log::info!("Parsing level data");
-        let levels = Self::parse_levels(&mut disassembly)?;
+        let levels = Self::parse_levels(&mut disassembly, lunar_magic.as_ref())?;
 
         log::info!("Parsing secondary entrances");
         let secondary_entrances = Self::parse_secondary_entrances(&mut disassembly)?;
 
         log::info!("Parsing GFX files");
-        let gfx = Gfx::parse(&mut disassembly, &levels, &internal_header)?;
+        let gfx = Gfx::parse(&mut disassembly, &internal_header, lunar_magic.as_ref())?;
 
         log::info!("Parsing Map16 tilesets");
         let map16_tilesets = Tilesets::parse(&mut disassembly)?;
 
-        Ok(Self { disassembly, internal_header, levels, secondary_entrances, gfx, map16_tilesets })
+        log::info!("Parsing overworld");
+        let overworld =
+            Overworld::parse(&mut disassembly).map_err(|e| log::warn!("Overworld cannot be edited:\n- {e}")).ok();
+
+        log::info!("Parsing text");
+        let text = Text::parse(&mut disassembly).map_err(|e| log::warn!("Text cannot be edited:\n- {e}")).ok();
+
+        log::info!("Parsing music and samples");
+        let audio =
+            Audio::parse(&mut disassembly).map_err(|e| log::warn!("Music and samples cannot be edited:\n- {e}")).ok();
+
+        Ok(Self {
+            disassembly,
+            internal_header,
+            levels,
+            secondary_entrances,
+            gfx,
+            map16_tilesets,
+            overworld,
+            text,
+            audio,
+            lunar_magic,
+            copier_header: None,
+        })
+    }
+
+    /// Writes all the ROM's data back into it and saves it to a file.
+    pub fn save_to_file<P: AsRef<Path>>(&mut self, path: P) -> anyhow::Result<()> {
+        log::info!("Saving levels");
+        self.save_levels()?;
+
+        log::info!("Saving secondary entrances");
+        self.save_secondary_entrances()?;
+
+        log::info!("Saving ExGFX files, GFX lists and color palettes");
+        self.gfx.write_to_rom(&mut self.disassembly, self.lunar_magic.as_mut())?;
+
+        if let Some(text) = &self.text {
+            log::info!("Saving text");
+            text.write_to_rom(&mut self.disassembly)?;
+        }
+
+        if let Some(overworld) = &self.overworld {
+            log::info!("Saving overworld events");
+            overworld.events.write_to_rom(&mut self.disassembly)?;
+        }
+
+        if let Some(audio) = &self.audio {
+            log::info!("Saving samples");
+            audio.write_to_rom(&mut self.disassembly)?;
+        }
+
+        log::info!("Updating internal ROM header");
+        self.internal_header.write_to_rom(&mut self.disassembly.rom)?;
+
+        log::info!("Writing ROM to file: {}", path.as_ref().display());
+        fs::write(path, CopierHeader::join(self.copier_header.as_ref(), self.disassembly.rom_bytes()))?;
+
+        Ok(())
+    }
+
+    /// Writes all levels into the ROM, moving their data into free space if it doesn't fit in the original location.
+    pub fn save_levels(&mut self) -> Result<(), LevelSaveError> {
+        for (level_num, level) in self.levels.iter().enumerate() {
+            level.write_to_rom(&mut self.disassembly, level_num as u32, self.lunar_magic.as_mut())?;
+        }
+        Ok(())
+    }
+
+    /// Writes all secondary entrances into their tables in the ROM.
+    pub fn save_secondary_entrances(&mut self) -> Result<(), RomError> {
+        for (entrance_id, entrance) in self.secondary_entrances.iter().enumerate() {
+            entrance.write_to_rom(&mut self.disassembly, entrance_id)?;
+        }
+        Ok(())
+    }
+
+    /// Returns IDs of secondary entrances leading to the level, along with the entrances.
+    pub fn secondary_entrances_into(&self, level_num: u16) -> impl Iterator<Item = (usize, &SecondaryEntrance)> {
+        self.secondary_entrances
+            .iter()
+            .enumerate()
+            .filter(move |(_, entrance)| entrance.destination_level() == level_num)
+    }
+
+    /// Expands the ROM to one of the [`LOROM_EXPANSION_SIZES`], updating the ROM size and checksum in its internal
+    /// header. The added banks are filled with zeros and can be used as free space.
+    ///
+    /// [`LOROM_EXPANSION_SIZES`]: snes_utils::rom::LOROM_EXPANSION_SIZES
+    pub fn expand(&mut self, new_size: usize) -> Result<(), RomError> {
+        if !self.internal_header.map_mode.is_lorom() {
+            return Err(RomError::ExpansionMapMode);
+        }
+        self.disassembly.expand(new_size)?;
+        self.internal_header.set_rom_size_in_bytes(new_size);
+        Ok(())
     }
 
-    fn parse_levels(disasm: &mut RomDisassembly) -> anyhow::Result<Vec<Level>> {
+    fn parse_levels(disasm: &mut RomDisassembly, lunar_magic: Option<&LunarMagic>) -> anyhow::Result<Vec<Level>> {
         let mut levels = Vec::with_capacity(LEVEL_COUNT);
         for level_num in 0..LEVEL_COUNT as u32 {
-            let level = Level::parse(disasm, level_num)?;
+            let level = Level::parse(disasm, level_num, lunar_magic)?;
             levels.push(level);
         }
         Ok(levels)
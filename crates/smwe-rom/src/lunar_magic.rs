@@ -0,0 +1,334 @@
+//! Support for ROMs edited with Lunar Magic, which moves data it modifies into free space and hijacks the game's code
+//! to read it through tables of its own.
+
+use crate::{
+    compression::Compression,
+    disassembler::{
+        binary_block::{DataBlock, DataKind},
+        RomDisassembly,
+    },
+    graphics::gfx_file::GFX_FILES_META,
+    level::LEVEL_COUNT,
+    snes_utils::{
+        addr::{AddrPc, AddrSnes},
+        rom_slice::SnesSlice,
+    },
+    RomError,
+};
+
+// -------------------------------------------------------------------------------------------------
+
+/// Notice written by Lunar Magic when it saves the ROM, followed by its version, e.g. `3.31`.
+pub const VERSION_NOTICE: SnesSlice = SnesSlice::new(AddrSnes(0x0FF0A0), 0x20);
+pub const VERSION_NOTICE_PREFIX: &[u8] = b"Lunar Magic Version ";
+
+/// Bank bytes of levels' sprite data, whose addresses are otherwise 16-bit pointers into bank `0x07`.
+pub const SPRITE_DATA_BANKS: SnesSlice = SnesSlice::new(AddrSnes(0x0EF100), LEVEL_COUNT);
+/// Bank bytes of levels' Layer2 backgrounds, whose pointers otherwise use bank `0xFF` to refer to bank `0x0C`.
+pub const LAYER2_BACKGROUND_BANKS: SnesSlice = SnesSlice::new(AddrSnes(0x0EF310), LEVEL_COUNT);
+
+/// Number of GFX files loaded through the original game's pointer tables, GFX00–GFX31.
+pub const GFX_FILE_POINTER_COUNT: usize = 0x32;
+/// Low, high and bank bytes of GFX file addresses, which Lunar Magic updates when it moves the files.
+pub const GFX_FILE_POINTERS: [SnesSlice; 3] = [
+    SnesSlice::new(AddrSnes(0x00B992), GFX_FILE_POINTER_COUNT),
+    SnesSlice::new(AddrSnes(0x00B9C4), GFX_FILE_POINTER_COUNT),
+    SnesSlice::new(AddrSnes(0x00B9F6), GFX_FILE_POINTER_COUNT),
+];
+
+/// Number of the first ExGFX file, whose numbers continue after the original files.
+pub const FIRST_EXGFX_NUM: usize = 0x80;
+pub const EXGFX_COUNT: usize = 0x80;
+/// 24-bit pointers to ExGFX80–ExGFXFF.
+pub const EXGFX_POINTERS: SnesSlice = SnesSlice::new(AddrSnes(0x0FF600), EXGFX_COUNT * 3);
+/// Number of the first ExGFX file listed in the extended table, up to ExGFXFFF.
+pub const FIRST_EXTENDED_EXGFX_NUM: usize = FIRST_EXGFX_NUM + EXGFX_COUNT;
+pub const EXTENDED_EXGFX_COUNT: usize = 0xF00;
+/// 24-bit pointer to the table of 24-bit pointers to ExGFX100–ExGFXFFF, which Lunar Magic places in free space.
+pub const EXTENDED_EXGFX_TABLE_POINTER: SnesSlice = SnesSlice::new(AddrSnes(0x0FF937), 3);
+
+/// 24-bit pointers to levels' custom palettes, zero for levels using the shared palette tables.
+pub const LEVEL_PALETTE_POINTERS: SnesSlice = SnesSlice::new(AddrSnes(0x0EF600), LEVEL_COUNT * 3);
+/// Custom palettes hold all 256 colors followed by the back area color, like `.mw3` files.
+pub const LEVEL_PALETTE_SIZE: usize = 0x202;
+
+// -------------------------------------------------------------------------------------------------
+
+/// Locations of data that Lunar Magic may have moved. Tables that Lunar Magic hasn't installed, or that contain banks
+/// outside the ROM, are ignored and the original game's locations are used instead.
+///
+/// Map16 pages and GFX lists that Lunar Magic stores per level are not supported yet, since the layout of the tables
+/// through which its hijacks read them hasn't been confirmed against a ROM. Levels are displayed with the original
+/// game's Map16 tilesets and object GFX lists, which is reported when the ROM is opened, and saving leaves Lunar
+/// Magic's copies unchanged.
+#[derive(Clone, Debug)]
+pub struct LunarMagic {
+    /// Version of Lunar Magic that last saved the ROM, e.g. `3.31`.
+    pub version:              String,
+    /// GFX files are compressed with LC-LZ3 instead of LC-LZ2, and Layer2 backgrounds instead of LC-RLE1.
+    pub lz3_gfx:              bool,
+    /// Addresses of GFX00–GFX31.
+    pub gfx_file_addrs:       Vec<AddrSnes>,
+    /// Addresses of ExGFX80–ExGFXFF, `None` for files that haven't been inserted.
+    pub exgfx_addrs:          Vec<Option<AddrSnes>>,
+    /// Addresses of ExGFX100–ExGFXFFF, empty if the extended table isn't installed.
+    pub extended_exgfx_addrs: Vec<Option<AddrSnes>>,
+    extended_exgfx_table:     Option<AddrSnes>,
+    level_palette_addrs:      Option<Vec<Option<AddrSnes>>>,
+    sprite_data_banks:        Option<Vec<u8>>,
+    layer2_background_banks:  Option<Vec<u8>>,
+}
+
+// -------------------------------------------------------------------------------------------------
+
+impl LunarMagic {
+    /// Returns `None` if the ROM hasn't been saved by Lunar Magic.
+    pub fn detect(disasm: &mut RomDisassembly) -> Result<Option<Self>, RomError> {
+        let notice = disasm.rom.view().slice_lorom(VERSION_NOTICE)?.as_bytes()?;
+        let Some(version) = notice.strip_prefix(VERSION_NOTICE_PREFIX) else {
+            return Ok(None);
+        };
+        let version_len = version.iter().take_while(|&&b| b.is_ascii_digit() || b == b'.').count();
+        if version_len == 0 {
+            return Ok(None);
+        }
+        let version = String::from_utf8_lossy(&version[..version_len]).into_owned();
+
+        let gfx_file_addrs = {
+            let [low, high, bank] =
+                GFX_FILE_POINTERS.map(|slice| disasm.rom.view().slice_lorom(slice).and_then(|view| view.as_bytes()));
+            let (low, high, bank) = (low?, high?, bank?);
+            (0..GFX_FILE_POINTER_COUNT)
+                .map(|i| AddrSnes(u32::from_le_bytes([low[i], high[i], bank[i], 0])))
+                .collect::<Vec<_>>()
+        };
+        let lz3_gfx = Self::uses_lz3(disasm, &gfx_file_addrs);
+
+        let exgfx_addrs = Self::read_exgfx_pointers(disasm, EXGFX_POINTERS)?;
+        let extended_exgfx_table = Self::read_extended_exgfx_table(disasm)?;
+        let extended_exgfx_addrs = match extended_exgfx_table {
+            Some(table) => Self::read_exgfx_pointers(disasm, Self::extended_exgfx_pointers(table))?,
+            None => Vec::new(),
+        };
+
+        let sprite_data_banks = Self::read_bank_table(disasm, SPRITE_DATA_BANKS, DataKind::LevelBanksSprite)?;
+        let layer2_background_banks =
+            Self::read_bank_table(disasm, LAYER2_BACKGROUND_BANKS, DataKind::LevelBanksLayer2Background)?;
+        let level_palette_addrs = Self::read_level_palette_pointers(disasm)?;
+
+        log::info!("Detected Lunar Magic {version}");
+        log::warn!("Per-level Map16 pages and GFX lists are not read, levels use their tilesets' ones instead");
+        Ok(Some(Self {
+            version,
+            lz3_gfx,
+            gfx_file_addrs,
+            exgfx_addrs,
+            extended_exgfx_addrs,
+            extended_exgfx_table,
+            level_palette_addrs,
+            sprite_data_banks,
+            layer2_background_banks,
+        }))
+    }
+
+    fn read_exgfx_pointers(disasm: &RomDisassembly, table: SnesSlice) -> Result<Vec<Option<AddrSnes>>, RomError> {
+        let addrs = disasm
+            .rom
+            .view()
+            .slice_lorom(table)?
+            .as_bytes()?
+            .chunks(3)
+            .map(|ptr| AddrSnes(u32::from_le_bytes([ptr[0], ptr[1], ptr[2], 0])))
+            .map(|addr| Self::is_in_rom(disasm, addr).then_some(addr))
+            .collect();
+        Ok(addrs)
+    }
+
+    /// Returns `None` if the table, or a part of it, is outside the ROM, which means that Lunar Magic hasn't
+    /// installed it.
+    fn read_extended_exgfx_table(disasm: &RomDisassembly) -> Result<Option<AddrSnes>, RomError> {
+        let ptr = disasm.rom.view().slice_lorom(EXTENDED_EXGFX_TABLE_POINTER)?.as_bytes()?;
+        let table = AddrSnes(u32::from_le_bytes([ptr[0], ptr[1], ptr[2], 0]));
+        let table_end = table + (EXTENDED_EXGFX_COUNT * 3 - 1);
+        let installed =
+            Self::is_in_rom(disasm, table) && Self::is_in_rom(disasm, table_end) && table.bank() == table_end.bank();
+        Ok(installed.then_some(table))
+    }
+
+    fn extended_exgfx_pointers(table: AddrSnes) -> SnesSlice {
+        SnesSlice::new(table, EXTENDED_EXGFX_COUNT * 3)
+    }
+
+    /// Original GFX files are compressed with LC-LZ2, which isn't compatible with LC-LZ3 used by Lunar Magic when it's
+    /// told to do so. Lunar Magic recompresses all GFX files when switching formats, so each of GFX00–GFX31 is
+    /// decompressed and the format that more of them decompress to 0x80 tiles with is used. Files that decompress with
+    /// both or neither formats, e.g. because they've been replaced with ones of a different size, are not counted.
+    fn uses_lz3(disasm: &RomDisassembly, gfx_file_addrs: &[AddrSnes]) -> bool {
+        let (mut lz2_files, mut lz3_files) = (0, 0);
+        for (&addr, (tile_format, _)) in gfx_file_addrs.iter().zip(GFX_FILES_META) {
+            let expected_size = 0x80 * tile_format.tile_size();
+            let Ok(bytes) =
+                disasm.rom.view().slice_lorom(SnesSlice::new(addr, usize::MAX)).and_then(|view| view.as_bytes())
+            else {
+                continue;
+            };
+            let decompresses_to_expected_size = |compression: Compression| {
+                compression.decompress(bytes).is_ok_and(|(data, _)| data.len() == expected_size)
+            };
+            let lz2 = decompresses_to_expected_size(Compression::LcLz2 { little_endian_in_repeat: false })
+                || decompresses_to_expected_size(Compression::LcLz2 { little_endian_in_repeat: true });
+            let lz3 = decompresses_to_expected_size(Compression::LcLz3);
+            match (lz2, lz3) {
+                (true, false) => lz2_files += 1,
+                (false, true) => lz3_files += 1,
+                _ => {}
+            }
+        }
+        lz3_files > lz2_files
+    }
+
+    /// Returns `None` if any of the banks in the table is outside the ROM, which means that Lunar Magic hasn't
+    /// installed the table.
+    fn read_bank_table(
+        disasm: &mut RomDisassembly, slice: SnesSlice, kind: DataKind,
+    ) -> Result<Option<Vec<u8>>, RomError> {
+        let banks = disasm.rom.view().slice_lorom(slice)?.as_bytes()?.to_vec();
+        if !banks.iter().all(|&bank| Self::is_in_rom(disasm, AddrSnes(0x8000).with_bank(bank))) {
+            return Ok(None);
+        }
+        if let Err(e) = disasm.mark_data_block(DataBlock { slice, kind }) {
+            log::warn!("Cannot mark Lunar Magic's table at {}: {e}", slice.begin);
+        }
+        Ok(Some(banks))
+    }
+
+    /// Returns `None` if any of the pointers is neither zero nor inside the ROM, which means that Lunar Magic hasn't
+    /// installed the table.
+    fn read_level_palette_pointers(disasm: &RomDisassembly) -> Result<Option<Vec<Option<AddrSnes>>>, RomError> {
+        let mut addrs = Vec::with_capacity(LEVEL_COUNT);
+        for ptr in disasm.rom.view().slice_lorom(LEVEL_PALETTE_POINTERS)?.as_bytes()?.chunks(3) {
+            let addr = AddrSnes(u32::from_le_bytes([ptr[0], ptr[1], ptr[2], 0]));
+            match addr {
+                AddrSnes(0) => addrs.push(None),
+                _ if Self::is_in_rom(disasm, addr) => addrs.push(Some(addr)),
+                _ => return Ok(None),
+            }
+        }
+        Ok(Some(addrs))
+    }
+
+    fn is_in_rom(disasm: &RomDisassembly, addr: AddrSnes) -> bool {
+        AddrPc::try_from_lorom(addr).is_ok_and(|pc| pc.as_index() < disasm.rom_bytes().len())
+    }
+
+    /// Compression of GFX00–GFX31 and ExGFX files.
+    pub fn gfx_compression(&self) -> Option<Compression> {
+        self.lz3_gfx.then_some(Compression::LcLz3)
+    }
+
+    /// Compression of Layer2 backgrounds, which Lunar Magic switches to LC-LZ3 along with GFX files.
+    pub fn layer2_background_compression(&self) -> Option<Compression> {
+        self.lz3_gfx.then_some(Compression::LcLz3)
+    }
+
+    /// Returns `None` if the table of sprite data banks isn't installed, in which case the original bank is used.
+    pub fn sprite_data_bank(&self, level_num: u32) -> Option<u8> {
+        self.sprite_data_banks.as_ref().map(|banks| banks[level_num as usize])
+    }
+
+    /// Returns `None` if the table of Layer2 background banks isn't installed, in which case the original bank is used.
+    pub fn layer2_background_bank(&self, level_num: u32) -> Option<u8> {
+        self.layer2_background_banks.as_ref().map(|banks| banks[level_num as usize])
+    }
+
+    /// Sprite data can be moved to any bank only if Lunar Magic's table of banks is installed.
+    pub fn has_sprite_data_banks(&self) -> bool {
+        self.sprite_data_banks.is_some()
+    }
+
+    /// Layer2 backgrounds can be moved to any bank only if Lunar Magic's table of banks is installed.
+    pub fn has_layer2_background_banks(&self) -> bool {
+        self.layer2_background_banks.is_some()
+    }
+
+    /// Address of an ExGFX file, `None` if it hasn't been inserted or if its number is outside ExGFX80–ExGFXFFF.
+    pub fn exgfx_addr(&self, file_num: usize) -> Option<AddrSnes> {
+        match file_num.checked_sub(FIRST_EXTENDED_EXGFX_NUM) {
+            Some(idx) => self.extended_exgfx_addrs.get(idx).copied().flatten(),
+            None => self.exgfx_addrs.get(file_num.checked_sub(FIRST_EXGFX_NUM)?).copied().flatten(),
+        }
+    }
+
+    /// ExGFX100–ExGFXFFF can only be inserted if the extended table is installed.
+    pub fn has_exgfx_pointer(&self, file_num: usize) -> bool {
+        match file_num {
+            FIRST_EXGFX_NUM..=0xFF => true,
+            FIRST_EXTENDED_EXGFX_NUM..=0xFFF => self.extended_exgfx_table.is_some(),
+            _ => false,
+        }
+    }
+
+    /// Writes the address of an ExGFX file into its pointer table. Does nothing if the table isn't installed.
+    pub fn set_exgfx_addr(
+        &mut self, disasm: &mut RomDisassembly, file_num: usize, addr: AddrSnes,
+    ) -> Result<(), RomError> {
+        if !self.has_exgfx_pointer(file_num) {
+            return Ok(());
+        }
+        let (table, addrs, idx) = match (file_num.checked_sub(FIRST_EXTENDED_EXGFX_NUM), self.extended_exgfx_table) {
+            (Some(idx), Some(table)) => (Self::extended_exgfx_pointers(table), &mut self.extended_exgfx_addrs, idx),
+            _ => (EXGFX_POINTERS, &mut self.exgfx_addrs, file_num - FIRST_EXGFX_NUM),
+        };
+        disasm.rom.write_lorom(table.begin + (idx * 3), &addr.0.to_le_bytes()[..3])?;
+        addrs[idx] = Some(addr);
+        Ok(())
+    }
+
+    /// Address of the level's custom palette, `None` if it uses the shared palette tables.
+    pub fn level_palette_addr(&self, level_num: u32) -> Option<AddrSnes> {
+        self.level_palette_addrs.as_ref().and_then(|addrs| addrs[level_num as usize])
+    }
+
+    /// Levels can have custom palettes only if Lunar Magic's table of pointers to them is installed.
+    pub fn has_level_palettes(&self) -> bool {
+        self.level_palette_addrs.is_some()
+    }
+
+    /// Writes the address of the level's custom palette into Lunar Magic's table, or zero to make the level use the
+    /// shared palette tables. Does nothing if the table isn't installed.
+    pub fn set_level_palette_addr(
+        &mut self, disasm: &mut RomDisassembly, level_num: u32, addr: Option<AddrSnes>,
+    ) -> Result<(), RomError> {
+        if let Some(addrs) = &mut self.level_palette_addrs {
+            let ptr = addr.unwrap_or(AddrSnes(0));
+            disasm.rom.write_lorom(LEVEL_PALETTE_POINTERS.begin + (level_num * 3), &ptr.0.to_le_bytes()[..3])?;
+            addrs[level_num as usize] = addr;
+        }
+        Ok(())
+    }
+
+    /// Writes the bank of the level's sprite data into Lunar Magic's table. Does nothing if the table isn't installed.
+    pub fn set_sprite_data_bank(
+        &mut self, disasm: &mut RomDisassembly, level_num: u32, bank: u8,
+    ) -> Result<(), RomError> {
+        Self::write_bank(&mut self.sprite_data_banks, SPRITE_DATA_BANKS, disasm, level_num, bank)
+    }
+
+    /// Writes the bank of the level's Layer2 background into Lunar Magic's table. Does nothing if the table isn't
+    /// installed.
+    pub fn set_layer2_background_bank(
+        &mut self, disasm: &mut RomDisassembly, level_num: u32, bank: u8,
+    ) -> Result<(), RomError> {
+        Self::write_bank(&mut self.layer2_background_banks, LAYER2_BACKGROUND_BANKS, disasm, level_num, bank)
+    }
+
+    fn write_bank(
+        banks: &mut Option<Vec<u8>>, table: SnesSlice, disasm: &mut RomDisassembly, level_num: u32, bank: u8,
+    ) -> Result<(), RomError> {
+        if let Some(banks) = banks {
+            disasm.rom.write_lorom(table.begin + level_num, &[bank])?;
+            banks[level_num as usize] = bank;
+        }
+        Ok(())
+    }
+}
@@ -1,6 +1,15 @@
 use thiserror::Error;
 
-use crate::{objects::map16::Tile8x8, AddrSnes, DataBlock, DataKind, RomDisassembly, SnesSlice};
+use crate::{
+    graphics::GfxListError,
+    objects::map16::Tile8x8,
+    AddrSnes,
+    DataBlock,
+    DataKind,
+    RomDisassembly,
+    RomError,
+    SnesSlice,
+};
 
 // -------------------------------------------------------------------------------------------------
 
@@ -14,6 +23,7 @@ const OBJECT_GFX_LIST: SnesSlice = SnesSlice::new(AddrSnes(0x00A92B), 26 * 4);
 
 // -------------------------------------------------------------------------------------------------
 
+/// Numbers of the four GFX files loaded into FG1–FG4 for each tileset. Numbers from `80` refer to ExGFX files.
 #[derive(Debug)]
 pub struct ObjectGfxList {
     gfx_file_nums: Vec<u8>,
@@ -33,4 +43,22 @@ impl ObjectGfxList {
         let idx = (tileset * 4) + tile.layer();
         self.gfx_file_nums[idx] as usize
     }
+
+    /// Files loaded into FG1–FG4 for the tileset.
+    pub fn gfx_file_nums(&self, tileset: usize) -> &[u8] {
+        &self.gfx_file_nums[tileset * 4..][..4]
+    }
+
+    pub fn set_gfx_file_num(&mut self, tileset: usize, slot: usize, file_num: u8) -> Result<(), GfxListError> {
+        if slot >= 4 {
+            return Err(GfxListError::Slot(slot));
+        }
+        let entry = self.gfx_file_nums.get_mut(tileset * 4 + slot).ok_or(GfxListError::List(tileset))?;
+        *entry = file_num;
+        Ok(())
+    }
+
+    pub fn write_to_rom(&self, disasm: &mut RomDisassembly) -> Result<(), RomError> {
+        disasm.rom.write_lorom(OBJECT_GFX_LIST.begin, &self.gfx_file_nums)
+    }
 }
@@ -0,0 +1,268 @@
+use num_enum::{FromPrimitive, IntoPrimitive};
+use thiserror::Error;
+
+use crate::{
+    compression::Compression,
+    disassembler::{
+        binary_block::{DataBlock, DataKind},
+        RomDisassembly,
+    },
+    overworld::{OverworldLayer2, OverworldParseError},
+    snes_utils::{addr::AddrSnes, rom::RomError, rom_slice::SnesSlice},
+};
+
+// -------------------------------------------------------------------------------------------------
+
+#[derive(Debug, Error)]
+pub enum OverworldEventsSaveError {
+    #[error("There are {0} events instead of {expected}", expected = EVENT_COUNT)]
+    EventCount(usize),
+    #[error("Events reveal {0} Layer2 tiles, the table has room for {max}", max = LAYER2_EVENT_TILE_COUNT)]
+    TooManyLayer2Tiles(usize),
+    #[error("Hard-coded path {0} has {1} steps instead of {2}")]
+    PathLength(usize, usize, usize),
+    #[error("Writing event tables:\n- {0}")]
+    Write(RomError),
+}
+
+// -------------------------------------------------------------------------------------------------
+
+pub const EVENT_COUNT: usize = 0x78;
+
+pub const LAYER2_EVENT_TILE_COUNT: usize = 0x2E6;
+/// Offsets in bytes of the first Layer2 tile of each event, followed by the end of the last event's tiles.
+pub const LAYER2_EVENT_OFFSETS: SnesSlice = SnesSlice::new(AddrSnes(0x04E359), (EVENT_COUNT + 1) * 2);
+/// Indices of revealed tiles in the Layer2 tilemap, see [`OverworldLayer2::tile_index`].
+pub const LAYER2_EVENT_TILE_POSITIONS: SnesSlice = SnesSlice::new(AddrSnes(0x04DD8D), LAYER2_EVENT_TILE_COUNT * 2);
+/// Low bytes of the Layer2 tilemap from which revealed tiles are copied, compressed with LC-RLE1.
+pub const LAYER2_EVENT_TILE_NUMBERS: AddrSnes = AddrSnes(0x0C8000);
+/// High bytes of the Layer2 tilemap from which revealed tiles are copied, compressed with LC-RLE1.
+pub const LAYER2_EVENT_PROPERTIES: AddrSnes = AddrSnes(0x0C8D00);
+
+/// Names and lengths of paths that the player walks along without following Layer1 path tiles, e.g. through pipes.
+pub const HARD_CODED_PATHS: [(&str, usize); 10] = [
+    ("Donut Plains 2 → Donut Plains 1", 6),
+    ("Donut Plains 1 → Donut Plains 2", 6),
+    ("Chocolate Island 3 → Chocolate Fortress", 4),
+    ("Chocolate Fortress → Chocolate Island 3", 4),
+    ("Forest of Illusion 4 → Forest of Illusion 2", 6),
+    ("Forest of Illusion 2 → Forest of Illusion 4", 6),
+    ("Chocolate Island 2 → Pipe", 15),
+    ("Pipe → Chocolate Island 2", 15),
+    ("Star Road → Front Door", 3),
+    ("Front Door → Star Road", 3),
+];
+pub const HARD_CODED_PATH_STEP_COUNT: usize = 0x44;
+/// Layer1 tiles passed at each step of the hard-coded paths.
+pub const HARD_CODED_PATH_TILES: SnesSlice = SnesSlice::new(AddrSnes(0x049086), HARD_CODED_PATH_STEP_COUNT);
+/// Directions in which the player walks at each step of the hard-coded paths.
+pub const HARD_CODED_PATH_DIRECTIONS: SnesSlice = SnesSlice::new(AddrSnes(0x0490CA), HARD_CODED_PATH_STEP_COUNT);
+
+// -------------------------------------------------------------------------------------------------
+
+/// Tiles revealed by overworld events and the hard-coded paths.
+///
+/// Only the tables whose extent matches the labels in the symbol files are modelled. Not modelled yet, and left
+/// unchanged when saving, are the Layer1 tiles revealed by events, castles destroyed by events, the events after which
+/// the game offers to save, and the directions of paths other than the hard-coded ones. The data tables in bank `0x04`
+/// near the event code that may hold them, bounded by the labels that follow them, are `$04D678` (0x71 bytes),
+/// `$04D85D` and `$04D93D` (0xE0 bytes each), `$04DA1D` and `$04DA33` (0x16 bytes each), and `$04E44B` (8 bytes).
+/// Which of them holds what hasn't been confirmed against a ROM.
+#[derive(Debug, Clone)]
+pub struct OverworldEvents {
+    /// Exactly [`EVENT_COUNT`] events.
+    pub events:               Vec<OverworldEvent>,
+    /// Tiles revealed by Layer2 events, placed where they appear on the overworld. Only tiles at positions listed by
+    /// the events are used.
+    pub layer2_event_tilemap: OverworldLayer2,
+    /// Steps of each of the [`HARD_CODED_PATHS`], whose lengths cannot be changed.
+    pub hard_coded_paths:     Vec<Vec<PathStep>>,
+}
+
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct OverworldEvent {
+    /// Indices in the Layer2 tilemap of tiles copied from the event tilemap.
+    pub layer2_tiles: Vec<u16>,
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct PathStep {
+    pub tile:      u8,
+    pub direction: PathDirection,
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, FromPrimitive, IntoPrimitive)]
+#[repr(u8)]
+pub enum PathDirection {
+    Up    = 0,
+    Down  = 1,
+    Left  = 2,
+    Right = 3,
+    #[num_enum(catch_all)]
+    Other(u8),
+}
+
+/// Event tables encoded in the format in which they are stored in the ROM.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct SerializedEvents {
+    pub layer2_offsets:        Vec<u8>,
+    pub layer2_tile_positions: Vec<u8>,
+    pub path_tiles:            Vec<u8>,
+    pub path_directions:       Vec<u8>,
+}
+
+// -------------------------------------------------------------------------------------------------
+
+impl OverworldEvents {
+    pub fn parse(disasm: &mut RomDisassembly) -> Result<Self, OverworldParseError> {
+        let mut read = |slice: SnesSlice, kind: DataKind| {
+            let block = DataBlock { slice, kind };
+            disasm.rom_slice_at_block(block, OverworldParseError::EventsRead)?.as_bytes().map(<[u8]>::to_vec)
+        };
+        let serialized = SerializedEvents {
+            layer2_offsets:        read(LAYER2_EVENT_OFFSETS, DataKind::OverworldEventLayer2)?,
+            layer2_tile_positions: read(LAYER2_EVENT_TILE_POSITIONS, DataKind::OverworldEventLayer2)?,
+            path_tiles:            read(HARD_CODED_PATH_TILES, DataKind::OverworldHardCodedPaths)?,
+            path_directions:       read(HARD_CODED_PATH_DIRECTIONS, DataKind::OverworldHardCodedPaths)?,
+        };
+        let (events, hard_coded_paths) = Self::deserialize(&serialized)?;
+
+        let mut read_tilemap = |addr: AddrSnes| {
+            disasm.parse_and_mark_data(
+                addr,
+                DataKind::OverworldEventLayer2Tilemap,
+                OverworldParseError::EventsRead,
+                |rom_view| {
+                    let bytes = rom_view.as_bytes()?;
+                    Compression::LcRle1.decompress(bytes).map_err(OverworldParseError::Layer2Decompress)
+                },
+            )
+        };
+        let tile_numbers = read_tilemap(LAYER2_EVENT_TILE_NUMBERS)?;
+        let properties = read_tilemap(LAYER2_EVENT_PROPERTIES)?;
+        let layer2_event_tilemap = OverworldLayer2::from_bytes(&tile_numbers, &properties)?;
+
+        Ok(Self { events, layer2_event_tilemap, hard_coded_paths })
+    }
+
+    fn deserialize(
+        serialized: &SerializedEvents,
+    ) -> Result<(Vec<OverworldEvent>, Vec<Vec<PathStep>>), OverworldParseError> {
+        let words = |bytes: &[u8]| bytes.chunks_exact(2).map(|w| u16::from_le_bytes([w[0], w[1]])).collect::<Vec<_>>();
+        let layer2_offsets = words(&serialized.layer2_offsets);
+        let layer2_positions = words(&serialized.layer2_tile_positions);
+
+        let mut events = Vec::with_capacity(EVENT_COUNT);
+        for event_num in 0..EVENT_COUNT {
+            let layer2_range = layer2_offsets[event_num] as usize / 2..layer2_offsets[event_num + 1] as usize / 2;
+            let layer2_tiles =
+                layer2_positions.get(layer2_range).ok_or(OverworldParseError::EventTiles(event_num))?.to_vec();
+
+            events.push(OverworldEvent { layer2_tiles });
+        }
+
+        let mut steps = serialized
+            .path_tiles
+            .iter()
+            .zip(&serialized.path_directions)
+            .map(|(&tile, &direction)| PathStep { tile, direction: direction.into() });
+        let hard_coded_paths = HARD_CODED_PATHS.iter().map(|&(_, len)| steps.by_ref().take(len).collect()).collect();
+
+        Ok((events, hard_coded_paths))
+    }
+
+    /// Encodes the events and hard-coded paths into tables in the format in which they are stored in the ROM. Tables
+    /// are padded to their original sizes.
+    pub fn serialize(&self) -> Result<SerializedEvents, OverworldEventsSaveError> {
+        if self.events.len() != EVENT_COUNT {
+            return Err(OverworldEventsSaveError::EventCount(self.events.len()));
+        }
+        let layer2_tile_count = self.events.iter().map(|event| event.layer2_tiles.len()).sum::<usize>();
+        if layer2_tile_count > LAYER2_EVENT_TILE_COUNT {
+            return Err(OverworldEventsSaveError::TooManyLayer2Tiles(layer2_tile_count));
+        }
+        for (path_num, &(_, len)) in HARD_CODED_PATHS.iter().enumerate() {
+            let path_len = self.hard_coded_paths.get(path_num).map_or(0, Vec::len);
+            if path_len != len {
+                return Err(OverworldEventsSaveError::PathLength(path_num, path_len, len));
+            }
+        }
+
+        let mut serialized = SerializedEvents {
+            layer2_offsets:        vec![0, 0],
+            layer2_tile_positions: Vec::with_capacity(LAYER2_EVENT_TILE_POSITIONS.size),
+            path_tiles:            Vec::with_capacity(HARD_CODED_PATH_STEP_COUNT),
+            path_directions:       Vec::with_capacity(HARD_CODED_PATH_STEP_COUNT),
+        };
+        for event in self.events.iter() {
+            for position in event.layer2_tiles.iter() {
+                serialized.layer2_tile_positions.extend(position.to_le_bytes());
+            }
+            serialized.layer2_offsets.extend((serialized.layer2_tile_positions.len() as u16).to_le_bytes());
+        }
+        for step in self.hard_coded_paths.iter().flatten() {
+            serialized.path_tiles.push(step.tile);
+            serialized.path_directions.push(step.direction.into());
+        }
+
+        serialized.layer2_tile_positions.resize(LAYER2_EVENT_TILE_POSITIONS.size, 0);
+        Ok(serialized)
+    }
+
+    /// Writes the events and hard-coded paths into their tables. The Layer2 event tilemap is not written.
+    pub fn write_to_rom(&self, disasm: &mut RomDisassembly) -> Result<(), OverworldEventsSaveError> {
+        let serialized = self.serialize()?;
+        for (slice, bytes) in [
+            (LAYER2_EVENT_OFFSETS, &serialized.layer2_offsets),
+            (LAYER2_EVENT_TILE_POSITIONS, &serialized.layer2_tile_positions),
+            (HARD_CODED_PATH_TILES, &serialized.path_tiles),
+            (HARD_CODED_PATH_DIRECTIONS, &serialized.path_directions),
+        ] {
+            debug_assert_eq!(slice.size, bytes.len());
+            disasm.rom.write_lorom(slice.begin, bytes).map_err(OverworldEventsSaveError::Write)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_serialization_round_trip() {
+        let mut events = vec![OverworldEvent::default(); EVENT_COUNT];
+        events[1].layer2_tiles = vec![0x1000, 0x1002];
+        events[EVENT_COUNT - 1].layer2_tiles = vec![0x1FFF];
+        let hard_coded_paths = HARD_CODED_PATHS
+            .iter()
+            .map(|&(_, len)| vec![PathStep { tile: 0x10, direction: PathDirection::Left }; len])
+            .collect::<Vec<_>>();
+        let layer2_event_tilemap = OverworldLayer2 { tiles: Vec::new() };
+        let overworld_events = OverworldEvents { events, layer2_event_tilemap, hard_coded_paths };
+
+        let serialized = overworld_events.serialize().unwrap();
+        assert_eq!(serialized.layer2_offsets.len(), LAYER2_EVENT_OFFSETS.size);
+        assert_eq!(serialized.layer2_tile_positions.len(), LAYER2_EVENT_TILE_POSITIONS.size);
+        assert_eq!(&serialized.layer2_offsets[2..6], &[0, 0, 4, 0]);
+
+        let (events, hard_coded_paths) = OverworldEvents::deserialize(&serialized).unwrap();
+        assert_eq!(events, overworld_events.events);
+        assert_eq!(hard_coded_paths, overworld_events.hard_coded_paths);
+    }
+
+    #[test]
+    fn test_too_many_tiles() {
+        let mut events = vec![OverworldEvent::default(); EVENT_COUNT];
+        events[0].layer2_tiles = vec![0; LAYER2_EVENT_TILE_COUNT + 1];
+        let overworld_events = OverworldEvents {
+            events,
+            layer2_event_tilemap: OverworldLayer2 { tiles: Vec::new() },
+            hard_coded_paths: vec![],
+        };
+        assert!(matches!(
+            overworld_events.serialize(),
+            Err(OverworldEventsSaveError::TooManyLayer2Tiles(count)) if count == LAYER2_EVENT_TILE_COUNT + 1
+        ));
+    }
+}
@@ -0,0 +1,347 @@
+use std::ops::RangeInclusive;
+
+use nom::{
+    combinator::map,
+    multi::count,
+    number::complete::{le_u16, le_u8},
+    sequence::tuple,
+};
+use num_enum::{IntoPrimitive, TryFromPrimitive};
+use thiserror::Error;
+
+use crate::{
+    compression::{Compression, DecompressionError},
+    disassembler::{
+        binary_block::{DataBlock, DataKind},
+        RomDisassembly,
+    },
+    objects::map16::Tile8x8,
+    overworld::events::OverworldEvents,
+    snes_utils::{addr::AddrSnes, rom::RomError, rom_slice::SnesSlice},
+};
+
+// -------------------------------------------------------------------------------------------------
+
+#[derive(Debug, Error)]
+pub enum OverworldParseError {
+    #[error("Reading Layer1 tiles:\n- {0}")]
+    Layer1Read(RomError),
+    #[error("Reading Layer2 tile numbers:\n- {0}")]
+    Layer2TileNumbersRead(RomError),
+    #[error("Reading Layer2 tile properties:\n- {0}")]
+    Layer2PropertiesRead(RomError),
+    #[error("Decompressing Layer2 tilemap:\n- {0}")]
+    Layer2Decompress(DecompressionError),
+    #[error("Layer2 tilemap decompressed to {0:#X} bytes instead of {expected:#X}", expected = LAYER2_TILE_COUNT)]
+    Layer2Size(usize),
+    #[error("Reading sprites:\n- {0}")]
+    SpritesRead(RomError),
+    #[error("Reading event tables:\n- {0}")]
+    EventsRead(RomError),
+    #[error("Tiles of event {0:X} are outside the event tables")]
+    EventTiles(usize),
+}
+
+pub mod events;
+
+// -------------------------------------------------------------------------------------------------
+
+/// Number of map areas. The main map takes up the first one, all the other submaps share the second one.
+pub const MAP_AREA_COUNT: usize = 2;
+
+/// Width and height of a map area in 16x16 Layer1 tiles.
+pub const LAYER1_AREA_SIZE: usize = 32;
+pub const LAYER1_TILE_COUNT: usize = MAP_AREA_COUNT * LAYER1_AREA_SIZE * LAYER1_AREA_SIZE;
+pub const LAYER1_TILES: SnesSlice = SnesSlice::new(AddrSnes(0x0CF7DF), LAYER1_TILE_COUNT);
+
+/// Width and height of a map area in 8x8 Layer2 tiles.
+pub const LAYER2_AREA_SIZE: usize = 64;
+pub const LAYER2_TILE_COUNT: usize = MAP_AREA_COUNT * LAYER2_AREA_SIZE * LAYER2_AREA_SIZE;
+/// Low bytes of Layer2 tiles, compressed with LC-RLE1.
+pub const LAYER2_TILE_NUMBERS: AddrSnes = AddrSnes(0x04A533);
+/// High bytes of Layer2 tiles (`YXPCCCTT`), compressed with LC-RLE1.
+pub const LAYER2_PROPERTIES: AddrSnes = AddrSnes(0x04C02B);
+
+/// Layer1 tiles which are levels. Each of them gets a translevel number, in the order in which they are stored in the
+/// Layer1 tilemap, starting from 1.
+pub const LEVEL_TILES: RangeInclusive<u8> = 0x56..=0x80;
+/// Translevels from this one on are the levels from `0x101` on.
+pub const FIRST_SECOND_HALF_TRANSLEVEL: u8 = 0x25;
+
+pub const SPRITE_COUNT: usize = 13;
+pub const SPRITE_SIZE: usize = 5;
+pub const SPRITES: SnesSlice = SnesSlice::new(AddrSnes(0x04F625), SPRITE_COUNT * SPRITE_SIZE);
+
+// -------------------------------------------------------------------------------------------------
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash, IntoPrimitive, TryFromPrimitive)]
+#[repr(u8)]
+pub enum Submap {
+    MainMap          = 0,
+    YoshisIsland     = 1,
+    VanillaDome      = 2,
+    ForestOfIllusion = 3,
+    ValleyOfBowser   = 4,
+    SpecialWorld     = 5,
+    StarWorld        = 6,
+}
+
+#[derive(Debug, Clone)]
+pub struct Overworld {
+    pub layer1:  OverworldLayer1,
+    pub layer2:  OverworldLayer2,
+    pub sprites: Vec<OverworldSprite>,
+    pub events:  OverworldEvents,
+}
+
+/// Path and level tiles. Each map area is made of 2x2 screens of 16x16 tiles, stored one screen after another, row
+/// by row.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct OverworldLayer1 {
+    pub tiles: Vec<u8>,
+}
+
+/// Terrain of all the submaps. Each map area is made of 2x2 screens of 32x32 tiles, in the same order as in VRAM.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct OverworldLayer2 {
+    pub tiles: Vec<Tile8x8>,
+}
+
+/// A level tile on the Layer1 tilemap and the level the player enters through it.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct LevelTile {
+    /// Index in the Layer1 tilemap.
+    pub position:   usize,
+    pub translevel: u8,
+}
+
+/// `ss xx XX yy YY`
+///
+/// | Value     | Comment                                                          |
+/// |-----------|------------------------------------------------------------------|
+/// | `ss`      | Sprite number, empty slots are `0`                               |
+/// | `XX xx`   | X position in pixels                                             |
+/// | `YY yy`   | Y position in pixels, counted from the top of the main map area  |
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct OverworldSprite {
+    pub sprite_id: u8,
+    pub x:         u16,
+    pub y:         u16,
+}
+
+// -------------------------------------------------------------------------------------------------
+
+impl Submap {
+    pub const ALL: [Submap; 7] = [
+        Submap::MainMap,
+        Submap::YoshisIsland,
+        Submap::VanillaDome,
+        Submap::ForestOfIllusion,
+        Submap::ValleyOfBowser,
+        Submap::SpecialWorld,
+        Submap::StarWorld,
+    ];
+
+    /// Index of the map area in which the submap is located.
+    pub fn map_area(self) -> usize {
+        match self {
+            Submap::MainMap => 0,
+            _ => 1,
+        }
+    }
+}
+
+impl Overworld {
+    pub fn parse(disasm: &mut RomDisassembly) -> Result<Self, OverworldParseError> {
+        let layer1 = OverworldLayer1::parse(disasm)?;
+        let layer2 = OverworldLayer2::parse(disasm)?;
+        let sprites = OverworldSprite::parse_all(disasm)?;
+        let events = OverworldEvents::parse(disasm)?;
+        Ok(Self { layer1, layer2, sprites, events })
+    }
+}
+
+impl OverworldLayer1 {
+    fn parse(disasm: &mut RomDisassembly) -> Result<Self, OverworldParseError> {
+        let block = DataBlock { slice: LAYER1_TILES, kind: DataKind::OverworldLayer1 };
+        let tiles = disasm.rom_slice_at_block(block, OverworldParseError::Layer1Read)?.as_bytes()?.to_vec();
+        Ok(Self { tiles })
+    }
+
+    /// Index of the tile at `(x, y)` in 16x16 tiles from the top-left corner of the map area.
+    pub fn tile_index(map_area: usize, x: usize, y: usize) -> usize {
+        debug_assert!(map_area < MAP_AREA_COUNT && x < LAYER1_AREA_SIZE && y < LAYER1_AREA_SIZE);
+        let screen = (y / 16) * 2 + (x / 16);
+        (map_area * LAYER1_AREA_SIZE * LAYER1_AREA_SIZE) + (screen * 16 * 16) + ((y % 16) * 16) + (x % 16)
+    }
+
+    pub fn tile_at(&self, map_area: usize, x: usize, y: usize) -> u8 {
+        self.tiles[Self::tile_index(map_area, x, y)]
+    }
+
+    pub fn set_tile(&mut self, map_area: usize, x: usize, y: usize, tile: u8) {
+        self.tiles[Self::tile_index(map_area, x, y)] = tile;
+    }
+
+    /// Returns all level tiles along with their translevel numbers. Adding or removing a level tile changes the
+    /// numbers of all the following ones.
+    pub fn level_tiles(&self) -> impl Iterator<Item = LevelTile> + '_ {
+        self.tiles
+            .iter()
+            .enumerate()
+            .filter(|(_, tile)| LEVEL_TILES.contains(tile))
+            .zip(1..=u8::MAX)
+            .map(|((position, _), translevel)| LevelTile { position, translevel })
+    }
+
+    /// Returns the translevel number of the level tile at the position, or `None` if there is no level tile there.
+    pub fn translevel_at(&self, position: usize) -> Option<u8> {
+        self.level_tiles().find(|tile| tile.position == position).map(|tile| tile.translevel)
+    }
+}
+
+impl LevelTile {
+    pub fn level_num(&self) -> u16 {
+        translevel_to_level(self.translevel)
+    }
+}
+
+/// Translevels `0x00–0x24` are levels `0x000–0x024`, translevels `0x25–0x5F` are levels `0x101–0x13B`.
+pub fn translevel_to_level(translevel: u8) -> u16 {
+    if translevel < FIRST_SECOND_HALF_TRANSLEVEL {
+        translevel as u16
+    } else {
+        translevel as u16 + 0x100 - FIRST_SECOND_HALF_TRANSLEVEL as u16 + 1
+    }
+}
+
+/// Returns `None` for levels that cannot be entered from the overworld, e.g. sublevels.
+pub fn level_to_translevel(level_num: u16) -> Option<u8> {
+    match level_num {
+        0x000..=0x024 => Some(level_num as u8),
+        0x101..=0x13B => Some((level_num - 0x101) as u8 + FIRST_SECOND_HALF_TRANSLEVEL),
+        _ => None,
+    }
+}
+
+impl OverworldLayer2 {
+    fn parse(disasm: &mut RomDisassembly) -> Result<Self, OverworldParseError> {
+        let tile_numbers = Self::parse_stream(disasm, LAYER2_TILE_NUMBERS, OverworldParseError::Layer2TileNumbersRead)?;
+        let properties = Self::parse_stream(disasm, LAYER2_PROPERTIES, OverworldParseError::Layer2PropertiesRead)?;
+        Self::from_bytes(&tile_numbers, &properties)
+    }
+
+    fn parse_stream(
+        disasm: &mut RomDisassembly, addr: AddrSnes, error_mapper: fn(RomError) -> OverworldParseError,
+    ) -> Result<Vec<u8>, OverworldParseError> {
+        disasm.parse_and_mark_data(addr, DataKind::OverworldLayer2, error_mapper, |rom_view| {
+            let bytes = rom_view.as_bytes()?;
+            Compression::LcRle1.decompress(bytes).map_err(OverworldParseError::Layer2Decompress)
+        })
+    }
+
+    /// Joins decompressed low and high bytes of tiles.
+    pub fn from_bytes(tile_numbers: &[u8], properties: &[u8]) -> Result<Self, OverworldParseError> {
+        for len in [tile_numbers.len(), properties.len()] {
+            if len != LAYER2_TILE_COUNT {
+                return Err(OverworldParseError::Layer2Size(len));
+            }
+        }
+        let tiles = tile_numbers
+            .iter()
+            .zip(properties)
+            .map(|(&number, &props)| Tile8x8(u16::from_le_bytes([number, props])))
+            .collect();
+        Ok(Self { tiles })
+    }
+
+    /// Index of the tile at `(x, y)` in 8x8 tiles from the top-left corner of the map area.
+    pub fn tile_index(map_area: usize, x: usize, y: usize) -> usize {
+        debug_assert!(map_area < MAP_AREA_COUNT && x < LAYER2_AREA_SIZE && y < LAYER2_AREA_SIZE);
+        let screen = (y / 32) * 2 + (x / 32);
+        (map_area * LAYER2_AREA_SIZE * LAYER2_AREA_SIZE) + (screen * 32 * 32) + ((y % 32) * 32) + (x % 32)
+    }
+
+    pub fn tile_at(&self, map_area: usize, x: usize, y: usize) -> Tile8x8 {
+        self.tiles[Self::tile_index(map_area, x, y)]
+    }
+}
+
+impl OverworldSprite {
+    fn parse_all(disasm: &mut RomDisassembly) -> Result<Vec<Self>, OverworldParseError> {
+        let block = DataBlock { slice: SPRITES, kind: DataKind::OverworldSpriteLayer };
+        disasm.rom_slice_at_block(block, OverworldParseError::SpritesRead)?.parse(count(Self::read_from, SPRITE_COUNT))
+    }
+
+    fn read_from(input: &[u8]) -> nom::IResult<&[u8], Self> {
+        map(tuple((le_u8, le_u16, le_u16)), |(sprite_id, x, y)| Self { sprite_id, x, y })(input)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sprite_id == 0
+    }
+
+    /// Index of the map area in which the sprite is placed.
+    pub fn map_area(&self) -> usize {
+        ((self.y as usize) / (LAYER1_AREA_SIZE * 16)).min(MAP_AREA_COUNT - 1)
+    }
+
+    pub fn to_bytes(self) -> [u8; SPRITE_SIZE] {
+        let [x_lo, x_hi] = self.x.to_le_bytes();
+        let [y_lo, y_hi] = self.y.to_le_bytes();
+        [self.sprite_id, x_lo, x_hi, y_lo, y_hi]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_layer2_from_bytes() {
+        let numbers: Vec<u8> = (0..LAYER2_TILE_COUNT).map(|i| i as u8).collect();
+        let properties = vec![0b10010101; LAYER2_TILE_COUNT];
+        let layer2 = OverworldLayer2::from_bytes(&numbers, &properties).unwrap();
+
+        let tile = layer2.tile_at(0, 33, 1);
+        assert_eq!(OverworldLayer2::tile_index(0, 33, 1), 0x400 + 32 + 1);
+        assert_eq!(tile.tile_number(), 0x121);
+        assert!(tile.flip_y());
+        assert_eq!(tile.palette(), 0b101);
+
+        assert!(matches!(
+            OverworldLayer2::from_bytes(&numbers[1..], &properties),
+            Err(OverworldParseError::Layer2Size(size)) if size == LAYER2_TILE_COUNT - 1
+        ));
+    }
+
+    #[test]
+    fn test_level_tiles() {
+        let mut layer1 = OverworldLayer1 { tiles: vec![0; LAYER1_TILE_COUNT] };
+        layer1.set_tile(1, 0, 0, 0x56);
+        layer1.set_tile(0, 16, 0, 0x80);
+        layer1.set_tile(0, 0, 1, 0x58);
+
+        let level_tiles = layer1.level_tiles().collect::<Vec<_>>();
+        assert_eq!(level_tiles, [
+            LevelTile { position: 0x10, translevel: 1 },
+            LevelTile { position: 0x100, translevel: 2 },
+            LevelTile { position: 0x400, translevel: 3 },
+        ]);
+        assert_eq!(layer1.translevel_at(0x100), Some(2));
+        assert_eq!(layer1.translevel_at(0x101), None);
+
+        assert_eq!(translevel_to_level(0x24), 0x024);
+        assert_eq!(translevel_to_level(0x25), 0x101);
+        assert_eq!(level_to_translevel(0x13B), Some(0x5F));
+        assert_eq!(level_to_translevel(0x100), None);
+    }
+
+    #[test]
+    fn test_layer1_tile_index() {
+        assert_eq!(OverworldLayer1::tile_index(0, 15, 15), 0xFF);
+        assert_eq!(OverworldLayer1::tile_index(0, 16, 0), 0x100);
+        assert_eq!(OverworldLayer1::tile_index(0, 0, 16), 0x200);
+        assert_eq!(OverworldLayer1::tile_index(1, 31, 31), 0x7FF);
+    }
+}
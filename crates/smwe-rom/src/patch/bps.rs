@@ -0,0 +1,284 @@
+use thiserror::Error;
+
+use crate::patch::PatchError;
+
+// -------------------------------------------------------------------------------------------------
+
+#[derive(Debug, Error)]
+pub enum BpsError {
+    #[error("Missing \"BPS1\" header")]
+    Header,
+    #[error("Cannot read a number")]
+    Number,
+    #[error("Cannot read {0} bytes of metadata")]
+    Metadata(usize),
+    #[error("Patch too short to contain checksums")]
+    Footer,
+    #[error("Patch checksum mismatch: expected {0:08X}, got {1:08X}")]
+    PatchChecksum(u32, u32),
+    #[error("Source size mismatch: expected {0:#X}, got {1:#X}")]
+    SourceSize(usize, usize),
+    #[error("Source checksum mismatch: expected {0:08X}, got {1:08X}")]
+    SourceChecksum(u32, u32),
+    #[error("Target checksum mismatch: expected {0:08X}, got {1:08X}")]
+    TargetChecksum(u32, u32),
+    #[error("Action at {0:#X} - Reading out of bounds")]
+    OutOfBounds(usize),
+    #[error("Actions output {1:#X} bytes instead of {0:#X}")]
+    TargetSize(usize, usize),
+    #[error("Target size {0:#X} is larger than any ROM")]
+    TargetTooLarge(usize),
+}
+
+// -------------------------------------------------------------------------------------------------
+
+const HEADER: &[u8] = b"BPS1";
+
+/// Sizes of source, target and patch CRC32 checksums.
+const FOOTER_SIZE: usize = 12;
+
+const ACTION_SOURCE_READ: u64 = 0;
+const ACTION_TARGET_READ: u64 = 1;
+const ACTION_SOURCE_COPY: u64 = 2;
+const ACTION_TARGET_COPY: u64 = 3;
+
+/// Largest target accepted from a patch, so that a corrupted size cannot make us allocate arbitrary amounts of memory.
+const MAX_TARGET_SIZE: usize = 0x1000000;
+
+/// Minimum length of unchanged data for which reading it from the source is smaller than storing it in the patch.
+const MIN_SOURCE_READ_LENGTH: usize = 4;
+
+/// Minimum length of a run of the same byte for which copying it from the target is smaller than storing it in the
+/// patch.
+const MIN_RUN_LENGTH: usize = 8;
+
+// -------------------------------------------------------------------------------------------------
+
+/// Creates a patch that turns `source` into `target`. Data is diffed linearly: unchanged bytes are read from the same
+/// offset in the source, runs of the same byte are copied from the target, and everything else is stored in the patch.
+pub fn create(source: &[u8], target: &[u8]) -> Vec<u8> {
+    let mut patch = HEADER.to_vec();
+    write_number(&mut patch, source.len() as u64);
+    write_number(&mut patch, target.len() as u64);
+    write_number(&mut patch, 0);
+
+    let mut literal_start = 0;
+    let mut target_relative_offset = 0;
+    let mut pos = 0;
+    while pos < target.len() {
+        let source_match = target[pos..].iter().zip(source.get(pos..).unwrap_or_default()).take_while(|(t, s)| t == s);
+        let source_match = source_match.count();
+        if source_match >= MIN_SOURCE_READ_LENGTH {
+            write_target_read(&mut patch, &target[literal_start..pos]);
+            write_action(&mut patch, ACTION_SOURCE_READ, source_match);
+            pos += source_match;
+            literal_start = pos;
+            continue;
+        }
+
+        let run_length = target[pos..].iter().take_while(|&&byte| byte == target[pos]).count();
+        if run_length >= MIN_RUN_LENGTH {
+            // The first byte of the run is stored, the rest is copied from the byte right before the output.
+            write_target_read(&mut patch, &target[literal_start..=pos]);
+            write_action(&mut patch, ACTION_TARGET_COPY, run_length - 1);
+            write_offset(&mut patch, pos as i64 - target_relative_offset as i64);
+            target_relative_offset = pos + run_length - 1;
+            pos += run_length;
+            literal_start = pos;
+            continue;
+        }
+
+        pos += 1;
+    }
+    write_target_read(&mut patch, &target[literal_start..]);
+
+    patch.extend(crc32fast::hash(source).to_le_bytes());
+    patch.extend(crc32fast::hash(target).to_le_bytes());
+    patch.extend(crc32fast::hash(&patch).to_le_bytes());
+    patch
+}
+
+fn write_target_read(patch: &mut Vec<u8>, bytes: &[u8]) {
+    if !bytes.is_empty() {
+        write_action(patch, ACTION_TARGET_READ, bytes.len());
+        patch.extend(bytes);
+    }
+}
+
+fn write_action(patch: &mut Vec<u8>, action: u64, length: usize) {
+    write_number(patch, ((length as u64 - 1) << 2) | action);
+}
+
+fn write_offset(patch: &mut Vec<u8>, offset: i64) {
+    write_number(patch, (offset.unsigned_abs() << 1) | (offset < 0) as u64);
+}
+
+fn write_number(patch: &mut Vec<u8>, mut number: u64) {
+    loop {
+        let byte = (number & 0x7F) as u8;
+        number >>= 7;
+        if number == 0 {
+            patch.push(0x80 | byte);
+            break;
+        }
+        patch.push(byte);
+        number -= 1;
+    }
+}
+
+/// Applies the patch to `source`, verifying the checksums of the source, the patch and the output.
+pub fn apply(source: &[u8], patch: &[u8]) -> Result<Vec<u8>, PatchError> {
+    if patch.len() < HEADER.len() + FOOTER_SIZE {
+        return Err(BpsError::Footer.into());
+    }
+    let (body, footer) = patch.split_at(patch.len() - FOOTER_SIZE);
+    let checksum = |index: usize| u32::from_le_bytes(footer[index * 4..][..4].try_into().unwrap());
+    let (source_checksum, target_checksum, patch_checksum) = (checksum(0), checksum(1), checksum(2));
+
+    let actual_patch_checksum = crc32fast::hash(&patch[..patch.len() - 4]);
+    if actual_patch_checksum != patch_checksum {
+        return Err(BpsError::PatchChecksum(patch_checksum, actual_patch_checksum).into());
+    }
+
+    let mut in_it = body.strip_prefix(HEADER).ok_or(BpsError::Header)?;
+    let source_size = read_number(&mut in_it)? as usize;
+    let target_size = read_number(&mut in_it)? as usize;
+    let metadata_size = read_number(&mut in_it)? as usize;
+    in_it = in_it.get(metadata_size..).ok_or(BpsError::Metadata(metadata_size))?;
+
+    if source.len() != source_size {
+        return Err(BpsError::SourceSize(source_size, source.len()).into());
+    }
+    let actual_source_checksum = crc32fast::hash(source);
+    if actual_source_checksum != source_checksum {
+        return Err(BpsError::SourceChecksum(source_checksum, actual_source_checksum).into());
+    }
+
+    if target_size > MAX_TARGET_SIZE {
+        return Err(BpsError::TargetTooLarge(target_size).into());
+    }
+
+    let mut target = Vec::with_capacity(target_size);
+    let (mut source_relative_offset, mut target_relative_offset) = (0i64, 0i64);
+    while !in_it.is_empty() {
+        let output_offset = target.len();
+        let out_of_bounds = || BpsError::OutOfBounds(output_offset);
+        let data = read_number(&mut in_it)?;
+        let length = (data >> 2) as usize + 1;
+        if length > target_size - output_offset {
+            return Err(BpsError::TargetSize(target_size, output_offset.saturating_add(length)).into());
+        }
+        match data & 0b11 {
+            ACTION_SOURCE_READ => {
+                target.extend(source.get(output_offset..output_offset + length).ok_or_else(out_of_bounds)?);
+            }
+            ACTION_TARGET_READ => {
+                target.extend(in_it.get(..length).ok_or_else(out_of_bounds)?);
+                in_it = &in_it[length..];
+            }
+            ACTION_SOURCE_COPY => {
+                source_relative_offset += read_offset(&mut in_it)?;
+                let begin = usize::try_from(source_relative_offset).map_err(|_| out_of_bounds())?;
+                target.extend(source.get(begin..begin + length).ok_or_else(out_of_bounds)?);
+                source_relative_offset += length as i64;
+            }
+            _ => {
+                target_relative_offset += read_offset(&mut in_it)?;
+                let begin = usize::try_from(target_relative_offset).map_err(|_| out_of_bounds())?;
+                if begin >= output_offset {
+                    return Err(out_of_bounds().into());
+                }
+                // The copied range may overlap with the output, so it has to be copied byte by byte.
+                for i in begin..begin + length {
+                    target.push(target[i]);
+                }
+                target_relative_offset += length as i64;
+            }
+        }
+    }
+
+    if target.len() != target_size {
+        return Err(BpsError::TargetSize(target_size, target.len()).into());
+    }
+    let actual_target_checksum = crc32fast::hash(&target);
+    if actual_target_checksum != target_checksum {
+        return Err(BpsError::TargetChecksum(target_checksum, actual_target_checksum).into());
+    }
+
+    Ok(target)
+}
+
+fn read_offset(in_it: &mut &[u8]) -> Result<i64, BpsError> {
+    let data = read_number(in_it)?;
+    let offset = (data >> 1) as i64;
+    Ok(if data & 1 != 0 { -offset } else { offset })
+}
+
+fn read_number(in_it: &mut &[u8]) -> Result<u64, BpsError> {
+    let mut number = 0u64;
+    let mut shift = 1u64;
+    loop {
+        let (&byte, rest) = in_it.split_first().ok_or(BpsError::Number)?;
+        *in_it = rest;
+        number =
+            ((byte & 0x7F) as u64).checked_mul(shift).and_then(|n| n.checked_add(number)).ok_or(BpsError::Number)?;
+        if byte & 0x80 != 0 {
+            return Ok(number);
+        }
+        shift = shift.checked_shl(7).ok_or(BpsError::Number)?;
+        number = number.checked_add(shift).ok_or(BpsError::Number)?;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let source: Vec<u8> = (0..0x20000).map(|i| (i * 7) as u8).collect();
+        let mut target = source.clone();
+        target[0x10..0x13].copy_from_slice(&[1, 2, 3]);
+        target[0x100..0x18000].fill(0xAB);
+        target.extend([0; 0x100]);
+
+        let patch = create(&source, &target);
+        assert!(patch.len() < 0x40, "patch is {} bytes long", patch.len());
+        assert_eq!(apply(&source, &patch).unwrap(), target);
+
+        let patch = create(&target, &source);
+        assert_eq!(apply(&target, &patch).unwrap(), source);
+    }
+
+    #[test]
+    fn test_wrong_source() {
+        let source = vec![0; 0x100];
+        let patch = create(&source, &[1; 0x100]);
+        assert!(matches!(apply(&[2; 0x100], &patch), Err(PatchError::Bps(BpsError::SourceChecksum(..)))));
+    }
+
+    #[test]
+    fn test_wrong_target_size() {
+        let with_checksums = |mut patch: Vec<u8>| {
+            patch.extend(crc32fast::hash(&[]).to_le_bytes());
+            patch.extend([0; 4]);
+            patch.extend(crc32fast::hash(&patch).to_le_bytes());
+            patch
+        };
+
+        let mut patch = HEADER.to_vec();
+        write_number(&mut patch, 0);
+        write_number(&mut patch, u64::MAX >> 8);
+        write_number(&mut patch, 0);
+        assert!(matches!(apply(&[], &with_checksums(patch)), Err(PatchError::Bps(BpsError::TargetTooLarge(..)))));
+
+        let mut patch = HEADER.to_vec();
+        write_number(&mut patch, 0);
+        write_number(&mut patch, 1);
+        write_number(&mut patch, 0);
+        write_target_read(&mut patch, &[1]);
+        write_action(&mut patch, ACTION_TARGET_COPY, 0x100);
+        write_offset(&mut patch, 0);
+        assert!(matches!(apply(&[], &with_checksums(patch)), Err(PatchError::Bps(BpsError::TargetSize(..)))));
+    }
+}
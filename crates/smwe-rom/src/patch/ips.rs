@@ -0,0 +1,197 @@
+use thiserror::Error;
+
+use crate::patch::PatchError;
+
+// -------------------------------------------------------------------------------------------------
+
+#[derive(Debug, Error)]
+pub enum IpsError {
+    #[error("Missing \"PATCH\" header")]
+    Header,
+    #[error("Missing \"EOF\" footer")]
+    Footer,
+    #[error("Record at {0:#08X} - Cannot read {1} bytes")]
+    Record(usize, usize),
+    #[error("Truncation - Cannot read size")]
+    Truncation,
+    #[error("Patched file too large: {0:#X} bytes, IPS addresses up to 16 MB")]
+    FileTooLarge(usize),
+}
+
+// -------------------------------------------------------------------------------------------------
+
+const HEADER: &[u8] = b"PATCH";
+const FOOTER: &[u8] = b"EOF";
+
+/// Record offset that would be read as the footer.
+const FOOTER_OFFSET: usize = 0x454F46;
+
+const MAX_FILE_SIZE: usize = 0x1000000;
+const MAX_RECORD_SIZE: usize = 0xFFFF;
+
+/// Size of a record's offset and size fields.
+const RECORD_HEADER_SIZE: usize = 5;
+
+/// Minimum length of a run of the same byte for which an RLE record is smaller than copying the bytes, including the
+/// header of the record that has to be started after it.
+const MIN_RLE_LENGTH: usize = 14;
+
+// -------------------------------------------------------------------------------------------------
+
+/// Creates a patch that turns `base` into `modified`. If `modified` is shorter, the truncation extension is used.
+pub fn create(base: &[u8], modified: &[u8]) -> Result<Vec<u8>, PatchError> {
+    if modified.len() > MAX_FILE_SIZE {
+        return Err(IpsError::FileTooLarge(modified.len()).into());
+    }
+
+    let differs = |i: usize| base.get(i) != Some(&modified[i]);
+    let mut patch = HEADER.to_vec();
+    let mut pos = 0;
+    while pos < modified.len() {
+        if !differs(pos) {
+            pos += 1;
+            continue;
+        }
+
+        // Unchanged gaps shorter than a record header are cheaper to include than to skip.
+        let mut end = pos + 1;
+        let mut gap = 0;
+        while end + gap < modified.len() && gap < RECORD_HEADER_SIZE {
+            if differs(end + gap) {
+                end += gap + 1;
+                gap = 0;
+            } else {
+                gap += 1;
+            }
+        }
+
+        write_records(&mut patch, modified, pos, end);
+        pos = end;
+    }
+
+    patch.extend(FOOTER);
+    if modified.len() < base.len() {
+        patch.extend(&(modified.len() as u32).to_be_bytes()[1..]);
+    }
+    Ok(patch)
+}
+
+/// Writes `data[begin..end]` as a series of normal and RLE records. Records never start at the offset which spells
+/// "EOF", they start a byte earlier instead.
+fn write_records(patch: &mut Vec<u8>, data: &[u8], begin: usize, end: usize) {
+    let mut literal_start = begin;
+    let mut pos = begin;
+    while pos < end {
+        let run_length = data[pos..end].iter().take_while(|&&byte| byte == data[pos]).count().min(MAX_RECORD_SIZE);
+        if run_length >= MIN_RLE_LENGTH && pos != FOOTER_OFFSET {
+            write_literal_records(patch, data, literal_start, pos);
+            patch.extend(&(pos as u32).to_be_bytes()[1..]);
+            patch.extend([0, 0]);
+            patch.extend((run_length as u16).to_be_bytes());
+            patch.push(data[pos]);
+            pos += run_length;
+            literal_start = pos;
+        } else {
+            pos += 1;
+        }
+    }
+    write_literal_records(patch, data, literal_start, end);
+}
+
+fn write_literal_records(patch: &mut Vec<u8>, data: &[u8], mut begin: usize, end: usize) {
+    while begin < end {
+        if begin == FOOTER_OFFSET {
+            begin -= 1;
+        }
+        let size = (end - begin).min(MAX_RECORD_SIZE);
+        patch.extend(&(begin as u32).to_be_bytes()[1..]);
+        patch.extend((size as u16).to_be_bytes());
+        patch.extend(&data[begin..begin + size]);
+        begin += size;
+    }
+}
+
+/// Applies the patch to a copy of `base`.
+pub fn apply(base: &[u8], patch: &[u8]) -> Result<Vec<u8>, PatchError> {
+    let mut in_it = patch.strip_prefix(HEADER).ok_or(IpsError::Header)?;
+    let mut output = base.to_vec();
+
+    loop {
+        if in_it.len() < 3 {
+            return Err(IpsError::Footer.into());
+        }
+        if in_it.starts_with(FOOTER) {
+            in_it = &in_it[FOOTER.len()..];
+            break;
+        }
+
+        let offset = u32::from_be_bytes([0, in_it[0], in_it[1], in_it[2]]) as usize;
+        in_it = &in_it[3..];
+        let size = u16::from_be_bytes(take(&mut in_it, offset, 2)?.try_into().unwrap()) as usize;
+        if size == 0 {
+            let run_length = u16::from_be_bytes(take(&mut in_it, offset, 2)?.try_into().unwrap()) as usize;
+            let byte = take(&mut in_it, offset, 1)?[0];
+            if output.len() < offset + run_length {
+                output.resize(offset + run_length, 0);
+            }
+            output[offset..offset + run_length].fill(byte);
+        } else {
+            let bytes = take(&mut in_it, offset, size)?;
+            if output.len() < offset + size {
+                output.resize(offset + size, 0);
+            }
+            output[offset..offset + size].copy_from_slice(bytes);
+        }
+    }
+
+    match in_it.len() {
+        0 => {}
+        3 => output.truncate(u32::from_be_bytes([0, in_it[0], in_it[1], in_it[2]]) as usize),
+        _ => return Err(IpsError::Truncation.into()),
+    }
+
+    Ok(output)
+}
+
+/// Takes `size` bytes of the record at `offset` from the input.
+fn take<'p>(in_it: &mut &'p [u8], offset: usize, size: usize) -> Result<&'p [u8], IpsError> {
+    if in_it.len() < size {
+        return Err(IpsError::Record(offset, size));
+    }
+    let (taken, rest) = in_it.split_at(size);
+    *in_it = rest;
+    Ok(taken)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let base: Vec<u8> = (0..0x20000).map(|i| (i * 7) as u8).collect();
+        let mut modified = base.clone();
+        modified[0x10..0x13].copy_from_slice(&[1, 2, 3]);
+        modified[0x100..0x18000].fill(0xAB);
+        modified.extend([0; 0x100]);
+
+        let patch = create(&base, &modified).unwrap();
+        assert!(patch.len() < 0x40, "patch is {} bytes long", patch.len());
+        assert_eq!(apply(&base, &patch).unwrap(), modified);
+
+        let patch = create(&modified, &base).unwrap();
+        assert_eq!(apply(&modified, &patch).unwrap(), base);
+    }
+
+    #[test]
+    fn test_record_at_footer_offset() {
+        let base = vec![0; FOOTER_OFFSET + 0x10];
+        let mut modified = base.clone();
+        modified[FOOTER_OFFSET] = 1;
+        modified[FOOTER_OFFSET + 1] = 2;
+
+        let patch = create(&base, &modified).unwrap();
+        assert_eq!(&patch[HEADER.len()..][..3], &[0x45, 0x4F, 0x45]);
+        assert_eq!(apply(&base, &patch).unwrap(), modified);
+    }
+}
@@ -0,0 +1,70 @@
+pub mod bps;
+pub mod ips;
+
+use duplicate::duplicate_item;
+use paste::paste;
+use thiserror::Error;
+
+pub use self::{bps::BpsError, ips::IpsError};
+
+// -------------------------------------------------------------------------------------------------
+
+#[derive(Debug, Error)]
+pub enum PatchError {
+    #[error("BPS patch:\n- {0}")]
+    Bps(BpsError),
+    #[error("IPS patch:\n- {0}")]
+    Ips(IpsError),
+}
+
+/// Format of patches describing the changes made to a clean ROM.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum PatchFormat {
+    Bps,
+    Ips,
+}
+
+// -------------------------------------------------------------------------------------------------
+
+#[duplicate_item(format; [Bps]; [Ips];)]
+impl From<paste! { [<format Error>] }> for PatchError {
+    fn from(e: paste! { [<format Error>] }) -> Self {
+        PatchError::format(e)
+    }
+}
+
+impl PatchFormat {
+    /// Recognizes the format by the patch's header.
+    pub fn detect(patch: &[u8]) -> Option<Self> {
+        if patch.starts_with(b"BPS1") {
+            Some(PatchFormat::Bps)
+        } else if patch.starts_with(b"PATCH") {
+            Some(PatchFormat::Ips)
+        } else {
+            None
+        }
+    }
+
+    pub fn file_extension(self) -> &'static str {
+        match self {
+            PatchFormat::Bps => "bps",
+            PatchFormat::Ips => "ips",
+        }
+    }
+
+    /// Creates a patch that turns `base` into `modified`.
+    pub fn create(self, base: &[u8], modified: &[u8]) -> Result<Vec<u8>, PatchError> {
+        match self {
+            PatchFormat::Bps => Ok(bps::create(base, modified)),
+            PatchFormat::Ips => ips::create(base, modified),
+        }
+    }
+
+    /// Applies the patch to a copy of `base`.
+    pub fn apply(self, base: &[u8], patch: &[u8]) -> Result<Vec<u8>, PatchError> {
+        match self {
+            PatchFormat::Bps => bps::apply(base, patch),
+            PatchFormat::Ips => ips::apply(base, patch),
+        }
+    }
+}
@@ -5,7 +5,11 @@ use thiserror::Error;
 use crate::{
     compression::DecompressionError,
     disassembler::binary_block::DataBlock,
-    snes_utils::{addr::AddrPc, rom_slice::*},
+    internal_header::{RomInternalHeader, HEADER_LOROM},
+    snes_utils::{
+        addr::{AddrPc, AddrSnes},
+        rom_slice::*,
+    },
 };
 
 // -------------------------------------------------------------------------------------------------
@@ -16,7 +20,7 @@ type ParseErr<'a> = nom::Err<nom::error::Error<&'a [u8]>>;
 pub enum RomError {
     #[error("Empty ROM file")]
     Empty,
-    #[error("Invalid ROM size (not a multiple of 512 bytes): {0} ({0:#x})")]
+    #[error("Cannot determine whether the ROM has a copier header, invalid size: {0} ({0:#x})")]
     Size(usize),
     #[error("Could not PC slice ROM: {0}")]
     SlicePc(PcSlice),
@@ -28,12 +32,23 @@ pub enum RomError {
     Parse,
     #[error("Data block not found: {0:?}")]
     DataBlockNotFound(DataBlock),
+    #[error("Not enough free space for {0} bytes")]
+    NoFreeSpace(usize),
+    #[error("Cannot free ROM area overlapping with code or other data: {0}")]
+    FreeOverlapping(PcSlice),
+    #[error("Invalid size of expanded ROM: {0} ({0:#x})")]
+    ExpansionSize(usize),
+    #[error("Only LoROM ROMs can be expanded")]
+    ExpansionMapMode,
 }
 
 // -------------------------------------------------------------------------------------------------
 
 pub const SMC_HEADER_SIZE: usize = 0x200;
 
+/// Sizes to which a LoROM can be expanded: 1 MB, 2 MB and 4 MB.
+pub const LOROM_EXPANSION_SIZES: [usize; 3] = [0x100000, 0x200000, 0x400000];
+
 // -------------------------------------------------------------------------------------------------
 
 pub trait RomView<'r> {
@@ -77,6 +92,10 @@ pub trait IsDecompressed {
 #[derive(Clone)]
 pub struct Rom(pub Arc<[u8]>);
 
+/// Header added by old copier devices at the beginning of some ROM files, which is not a part of the ROM itself.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct CopierHeader(pub Vec<u8>);
+
 pub struct RomWithErrorMapper<'r, EM, ET>
 where
     EM: Fn(RomError) -> ET,
@@ -119,26 +138,146 @@ pub fn noop_error_mapper<ET>(e: ET) -> ET {
     e
 }
 
-impl Rom {
-    pub fn new(mut data: Vec<u8>) -> Result<Self, RomError> {
-        if !data.is_empty() {
-            match data.len() % 0x400 {
-                SMC_HEADER_SIZE => {
-                    data.drain(..SMC_HEADER_SIZE);
-                    Ok(Self(Arc::from(data)))
-                }
-                0 => Ok(Self(Arc::from(data))),
-                _ => Err(RomError::Size(data.len())),
-            }
+/// Expands a LoROM ROM to one of the [`LOROM_EXPANSION_SIZES`], padding it with zeros, and updates the ROM size and
+/// checksum in its internal header.
+pub fn expand_lorom(rom: &mut Vec<u8>, new_size: usize) -> Result<(), RomError> {
+    check_lorom_expansion(rom.len(), new_size)?;
+    rom.resize(new_size, 0);
+    let header = &mut rom[HEADER_LOROM.begin.as_index()..][..HEADER_LOROM.size];
+    RomInternalHeader::write_rom_size_into(header, new_size);
+    RomInternalHeader::write_checksum(rom, HEADER_LOROM)
+}
+
+/// Checks that a LoROM of `rom_size` bytes can be expanded to `new_size` bytes, which must be one of the
+/// [`LOROM_EXPANSION_SIZES`] larger than the ROM.
+pub fn check_lorom_expansion(rom_size: usize, new_size: usize) -> Result<(), RomError> {
+    if !LOROM_EXPANSION_SIZES.contains(&new_size) || new_size <= rom_size {
+        return Err(RomError::ExpansionSize(new_size));
+    }
+    Ok(())
+}
+
+/// Sum of all bytes in a ROM as computed by the SNES header convention. ROMs whose size is not a power of two are
+/// treated as if their last part was mirrored up to the next power of two.
+pub fn checksum(rom: &[u8]) -> u16 {
+    mirrored_sum(rom, rom.len().next_power_of_two())
+}
+
+/// Sums up `data` mirrored to fill `size` bytes. Data of non-power-of-two size is split into the largest power of two
+/// part and the remainder, which is mirrored to fill the size of the first part.
+fn mirrored_sum(data: &[u8], size: usize) -> u16 {
+    if data.is_empty() {
+        return 0;
+    }
+    let sum = if data.len().is_power_of_two() {
+        data.iter().fold(0u16, |sum, &byte| sum.wrapping_add(byte as u16))
+    } else {
+        let base = 1 << (usize::BITS - 1 - data.len().leading_zeros());
+        mirrored_sum(&data[..base], base).wrapping_add(mirrored_sum(&data[base..], base))
+    };
+    let repetitions = size / data.len().next_power_of_two();
+    sum.wrapping_mul(repetitions as u16)
+}
+
+impl CopierHeader {
+    /// Creates a header holding the ROM size in 8 KB units, with all the other bytes zeroed.
+    pub fn new(rom_size: usize) -> Self {
+        let mut bytes = vec![0; SMC_HEADER_SIZE];
+        bytes[..2].copy_from_slice(&((rom_size / 0x2000) as u16).to_le_bytes());
+        Self(bytes)
+    }
+
+    /// Separates the copier header from the contents of a ROM file, if the file has one.
+    ///
+    /// The header is detected by validating the internal ROM header at both possible offsets. If that is inconclusive,
+    /// the file size decides: ROMs consist of whole 32 KB banks, so a file with 512 extra bytes has a copier header.
+    pub fn split_from(mut file: Vec<u8>) -> Result<(Option<Self>, Vec<u8>), RomError> {
+        if file.is_empty() {
+            return Err(RomError::Empty);
+        }
+
+        let valid_without_header = RomInternalHeader::exists_in(&file);
+        let valid_with_header = file.get(SMC_HEADER_SIZE..).is_some_and(RomInternalHeader::exists_in);
+        let has_header = match (valid_without_header, valid_with_header) {
+            (true, false) => false,
+            (false, true) => true,
+            _ => match file.len() % 0x8000 {
+                0 => false,
+                SMC_HEADER_SIZE => true,
+                _ => return Err(RomError::Size(file.len())),
+            },
+        };
+
+        if has_header {
+            let rom = file.split_off(SMC_HEADER_SIZE);
+            Ok((Some(Self(file)), rom))
         } else {
-            Err(RomError::Empty)
+            Ok((None, file))
         }
     }
 
+    /// Puts the contents of a ROM file back together, with the copier header if there is one.
+    pub fn join(header: Option<&Self>, rom: &[u8]) -> Vec<u8> {
+        let header = header.map(|header| header.0.as_slice()).unwrap_or_default();
+        [header, rom].concat()
+    }
+}
+
+impl Rom {
+    /// Creates a ROM from its contents without a copier header, which can be separated from a file's contents with
+    /// [`CopierHeader::split_from`].
+    pub fn new(data: Vec<u8>) -> Result<Self, RomError> {
+        if data.is_empty() {
+            return Err(RomError::Empty);
+        }
+        Ok(Self(Arc::from(data)))
+    }
+
     pub fn view(&self) -> RomWithErrorMapper<'_, impl Fn(RomError) -> RomError, RomError> {
         self.with_error_mapper(noop_error_mapper)
     }
 
+    /// Overwrites ROM contents starting at PC address `addr` with `bytes`.
+    ///
+    /// If the underlying buffer is shared with other `Rom` instances, it gets copied first so that
+    /// the other instances remain unchanged.
+    pub fn write_pc(&mut self, addr: AddrPc, bytes: &[u8]) -> Result<(), RomError> {
+        let slice = PcSlice::new(addr, bytes.len());
+        let begin = addr.as_index();
+        if begin + bytes.len() > self.0.len() {
+            return Err(RomError::SlicePc(slice));
+        }
+        self.bytes_mut()[begin..begin + bytes.len()].copy_from_slice(bytes);
+        Ok(())
+    }
+
+    /// Overwrites ROM contents starting at LoROM address `addr` with `bytes`.
+    pub fn write_lorom(&mut self, addr: AddrSnes, bytes: &[u8]) -> Result<(), RomError> {
+        let addr_pc =
+            AddrPc::try_from_lorom(addr).map_err(|_| RomError::SliceSnes(SnesSlice::new(addr, bytes.len())))?;
+        self.write_pc(addr_pc, bytes)
+    }
+
+    /// Expands the ROM and updates its internal header, see [`expand_lorom`].
+    pub fn expand(&mut self, new_size: usize) -> Result<(), RomError> {
+        let mut bytes = self.0.to_vec();
+        expand_lorom(&mut bytes, new_size)?;
+        self.0 = Arc::from(bytes);
+        Ok(())
+    }
+
+    /// Sum of all bytes in the ROM, see [`checksum`].
+    pub fn checksum(&self) -> u16 {
+        checksum(&self.0)
+    }
+
+    pub(crate) fn bytes_mut(&mut self) -> &mut [u8] {
+        if Arc::get_mut(&mut self.0).is_none() {
+            self.0 = Arc::from(self.0.to_vec());
+        }
+        Arc::get_mut(&mut self.0).unwrap()
+    }
+
     pub fn with_error_mapper<'r, EM, ET>(&'r self, error_mapper: EM) -> RomWithErrorMapper<'r, EM, ET>
     where
         EM: Fn(RomError) -> ET,
@@ -0,0 +1,150 @@
+use crate::{
+    disassembler::{
+        binary_block::{DataBlock, DataKind},
+        RomDisassembly,
+    },
+    snes_utils::{addr::AddrSnes, rom_slice::SnesSlice},
+    text::{check_capacity, decode, encode, TextError, TextParseError},
+};
+
+// -------------------------------------------------------------------------------------------------
+
+/// Fragments of level names, the last character of each one having its highest bit set.
+pub const LEVEL_NAME_STRINGS: SnesSlice = SnesSlice::new(AddrSnes(0x049AC5), 0x1CC);
+/// Offsets into [`LEVEL_NAME_STRINGS`] of fragments that can be used as the first, second and third part of a name.
+pub const LEVEL_NAME_FRAGMENT_OFFSETS: [SnesSlice; 3] = [
+    SnesSlice::new(AddrSnes(0x049C91), 31 * 2),
+    SnesSlice::new(AddrSnes(0x049CCF), 15 * 2),
+    SnesSlice::new(AddrSnes(0x049CED), 13 * 2),
+];
+pub const LEVEL_NAME_COUNT: usize = 0x5D;
+/// Level names of translevels, made of indices of fragments.
+pub const LEVEL_NAMES: SnesSlice = SnesSlice::new(AddrSnes(0x04A0FC), LEVEL_NAME_COUNT * 2);
+
+const FRAGMENT_END: u8 = 0x80;
+
+// -------------------------------------------------------------------------------------------------
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct LevelNames {
+    /// Fragments that can be used as the first, second and third part of a name.
+    pub fragments: [Vec<String>; 3],
+    /// Names of translevels.
+    pub names:     Vec<LevelName>,
+}
+
+/// `---FFFFF SSSSTTTT`
+///
+/// | Value   | Comment                       |
+/// |---------|-------------------------------|
+/// | `FFFFF` | Index of the first fragment   |
+/// | `SSSS`  | Index of the second fragment  |
+/// | `TTTT`  | Index of the third fragment   |
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct LevelName(pub u16);
+
+// -------------------------------------------------------------------------------------------------
+
+impl LevelNames {
+    pub(crate) fn parse(disasm: &mut RomDisassembly) -> Result<Self, TextParseError> {
+        let mut read = |slice: SnesSlice| {
+            let block = DataBlock { slice, kind: DataKind::Text };
+            disasm.rom_slice_at_block(block, TextParseError::LevelNamesRead)?.as_bytes().map(<[u8]>::to_vec)
+        };
+        let strings = read(LEVEL_NAME_STRINGS)?;
+        let names = read(LEVEL_NAMES)?.chunks(2).map(|w| LevelName(u16::from_le_bytes([w[0], w[1]]))).collect();
+
+        let mut fragments: [Vec<String>; 3] = Default::default();
+        for (fragments, offsets) in fragments.iter_mut().zip(LEVEL_NAME_FRAGMENT_OFFSETS) {
+            for offset in read(offsets)?.chunks(2).map(|w| u16::from_le_bytes([w[0], w[1]]) as usize) {
+                let bytes = strings.get(offset..).unwrap_or_default();
+                let len = bytes
+                    .iter()
+                    .position(|&b| b & FRAGMENT_END != 0)
+                    .ok_or(TextParseError::LevelNameFragment(offset))?
+                    + 1;
+                let bytes = bytes[..len].iter().map(|&b| b & !FRAGMENT_END).collect::<Vec<_>>();
+                fragments.push(decode(&bytes));
+            }
+        }
+
+        Ok(Self { fragments, names })
+    }
+
+    /// Returns the full name of the translevel, or `None` if the translevel doesn't have one. Fragments with indices
+    /// outside the tables are skipped.
+    pub fn name(&self, translevel: usize) -> Option<String> {
+        let name = self.names.get(translevel)?;
+        let parts = name.fragment_indices().into_iter().zip(&self.fragments);
+        Some(parts.filter_map(|(index, fragments)| fragments.get(index).map(String::as_str)).collect())
+    }
+
+    /// Encodes the fragments and names into tables, along with their addresses. Fragments are stored one after
+    /// another in the order of their parts, so the table of strings may change even if only one of them does.
+    pub fn encode_tables(&self) -> Result<Vec<(AddrSnes, Vec<u8>)>, TextError> {
+        let mut strings = Vec::with_capacity(LEVEL_NAME_STRINGS.size);
+        let mut tables = Vec::with_capacity(5);
+        for (fragments, offsets_table) in self.fragments.iter().zip(LEVEL_NAME_FRAGMENT_OFFSETS) {
+            check_capacity(fragments.len() * 2, offsets_table.size)?;
+            let mut offsets = Vec::with_capacity(offsets_table.size);
+            for fragment in fragments {
+                offsets.extend((strings.len() as u16).to_le_bytes());
+                let mut bytes = encode(fragment)?;
+                let last = bytes.last_mut().ok_or(TextError::EmptyFragment)?;
+                *last |= FRAGMENT_END;
+                strings.extend(bytes);
+            }
+            offsets.resize(offsets_table.size, 0);
+            tables.push((offsets_table.begin, offsets));
+        }
+        check_capacity(strings.len(), LEVEL_NAME_STRINGS.size)?;
+        tables.push((LEVEL_NAME_STRINGS.begin, strings));
+
+        check_capacity(self.names.len() * 2, LEVEL_NAMES.size)?;
+        tables.push((LEVEL_NAMES.begin, self.names.iter().flat_map(|name| name.0.to_le_bytes()).collect()));
+        Ok(tables)
+    }
+}
+
+impl LevelName {
+    pub fn new(first: u8, second: u8, third: u8) -> Self {
+        Self(((first as u16 & 0b11111) << 8) | ((second as u16 & 0b1111) << 4) | (third as u16 & 0b1111))
+    }
+
+    /// Indices of the first, second and third fragment.
+    pub fn fragment_indices(self) -> [usize; 3] {
+        // ---FFFFF SSSSTTTT
+        [((self.0 >> 8) & 0b11111) as usize, ((self.0 >> 4) & 0b1111) as usize, (self.0 & 0b1111) as usize]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode_tables() {
+        let level_names = LevelNames {
+            fragments: [vec!["YOSHI'S ".into(), "DONUT ".into()], vec!["ISLAND ".into()], vec!["".into(), "2".into()]],
+            names:     vec![LevelName::new(1, 0, 0), LevelName::new(0, 0, 1)],
+        };
+        assert!(matches!(level_names.encode_tables(), Err(TextError::EmptyFragment)));
+
+        let level_names = LevelNames {
+            fragments: [vec!["YOSHI ".into(), "DONUT ".into()], vec!["ISLAND ".into()], vec!["1".into(), "2".into()]],
+            ..level_names
+        };
+        assert_eq!(level_names.name(0).as_deref(), Some("DONUT ISLAND 1"));
+        assert_eq!(level_names.name(1).as_deref(), Some("YOSHI ISLAND 2"));
+        assert_eq!(level_names.name(2), None);
+
+        let tables = level_names.encode_tables().unwrap();
+        let (strings_addr, strings) = &tables[3];
+        assert_eq!(*strings_addr, LEVEL_NAME_STRINGS.begin);
+        assert_eq!(strings.len(), 6 + 6 + 7 + 1 + 1);
+        assert_eq!(strings[5], 0x1F | FRAGMENT_END);
+        assert_eq!(strings[19], 0x23 | FRAGMENT_END);
+        assert_eq!(&tables[0].1[..4], &[0, 0, 6, 0]);
+        assert_eq!(&tables[4].1, &[0x00, 0x01, 0x01, 0x00]);
+    }
+}
@@ -0,0 +1,264 @@
+//! Text encoded with SMW's character table, which is also the order of letters in the Layer3 font, so that the same
+//! bytes serve as characters in message boxes and tile numbers in stripe images.
+//!
+//! Bytes that don't correspond to any character are written as `{XX}`, where `XX` is the byte in hexadecimal, so that
+//! decoding and encoding is lossless.
+
+use thiserror::Error;
+
+pub use self::{level_names::LevelNames, stripe::StripeImage};
+use crate::{
+    disassembler::{
+        binary_block::{DataBlock, DataKind},
+        RomDisassembly,
+    },
+    snes_utils::{addr::AddrSnes, rom::RomError, rom_slice::SnesSlice},
+};
+
+pub mod level_names;
+pub mod stripe;
+
+// -------------------------------------------------------------------------------------------------
+
+#[derive(Debug, Error)]
+pub enum TextParseError {
+    #[error("Reading message boxes:\n- {0}")]
+    MessagesRead(RomError),
+    #[error("Reading level names:\n- {0}")]
+    LevelNamesRead(RomError),
+    #[error("Level name fragment at offset {0:#X} has no terminator")]
+    LevelNameFragment(usize),
+    #[error("Reading stripe image at {0}:\n- {1}")]
+    StripeRead(AddrSnes, RomError),
+    #[error("Stripe image at {0} is not terminated")]
+    StripeTerminator(AddrSnes),
+    #[error("Stripe image at {0} has a stripe of {1} bytes, which is not a whole number of tiles")]
+    StripeSize(AddrSnes, usize),
+}
+
+#[derive(Debug, Error)]
+pub enum TextError {
+    #[error("Character {0:?} is not in the character table")]
+    UnknownCharacter(char),
+    #[error("Invalid escape sequence: {0:?}")]
+    Escape(String),
+    #[error("Level name fragments cannot be empty")]
+    EmptyFragment,
+    #[error("Text takes {needed} bytes, but only {capacity} are available in place")]
+    Overflow { needed: usize, capacity: usize },
+    #[error("Writing text:\n- {0}")]
+    Write(RomError),
+}
+
+// -------------------------------------------------------------------------------------------------
+
+/// Region containing all message boxes, one after another.
+pub const MESSAGE_BOXES: SnesSlice = SnesSlice::new(AddrSnes(0x05A5D9), 0xB26);
+pub const MESSAGE_TERMINATOR: u8 = 0xFE;
+
+/// Castle cutscene texts, one after another, each made of stripe images for its lines.
+pub const CUTSCENE_STRIPES: AddrSnes = AddrSnes(0x0CBE85);
+pub const CUTSCENE_STRIPE_COUNT: usize = 54;
+/// Names of enemies shown during the credits, followed by the special names of some of them.
+pub const CREDITS_STRIPES: AddrSnes = AddrSnes(0x0DF300);
+pub const CREDITS_STRIPE_COUNT: usize = 26;
+
+// -------------------------------------------------------------------------------------------------
+
+#[derive(Debug, Clone)]
+pub struct Text {
+    /// The first message box is the one shown in the intro.
+    pub message_boxes: Vec<TextBlock>,
+    pub level_names:   LevelNames,
+    pub cutscenes:     Vec<StripeImage>,
+    pub credits:       Vec<StripeImage>,
+}
+
+/// Text stored at a fixed location in the ROM, which cannot take more space than it originally did.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct TextBlock {
+    pub text:     String,
+    pub addr:     AddrSnes,
+    /// Size of the original text in bytes, including its terminator.
+    pub capacity: usize,
+}
+
+// -------------------------------------------------------------------------------------------------
+
+impl Text {
+    pub fn parse(disasm: &mut RomDisassembly) -> Result<Self, TextParseError> {
+        let message_boxes = TextBlock::parse_messages(disasm)?;
+        let level_names = LevelNames::parse(disasm)?;
+        let cutscenes = StripeImage::parse_sequence(disasm, CUTSCENE_STRIPES, CUTSCENE_STRIPE_COUNT)?;
+        let credits = StripeImage::parse_sequence(disasm, CREDITS_STRIPES, CREDITS_STRIPE_COUNT)?;
+        Ok(Self { message_boxes, level_names, cutscenes, credits })
+    }
+
+    /// Writes all the text back in place. Nothing is written if any of it doesn't fit.
+    pub fn write_to_rom(&self, disasm: &mut RomDisassembly) -> Result<(), TextError> {
+        let mut writes = Vec::new();
+        for message in self.message_boxes.iter() {
+            writes.push((message.addr, message.encode()?));
+        }
+        writes.extend(self.level_names.encode_tables()?);
+        for image in self.cutscenes.iter().chain(&self.credits) {
+            writes.push((image.addr, image.encode()?));
+        }
+        for (addr, bytes) in writes {
+            disasm.rom.write_lorom(addr, &bytes).map_err(TextError::Write)?;
+        }
+        Ok(())
+    }
+}
+
+impl TextBlock {
+    fn parse_messages(disasm: &mut RomDisassembly) -> Result<Vec<Self>, TextParseError> {
+        let bytes = disasm.rom.view().slice_lorom(MESSAGE_BOXES).and_then(|view| view.as_bytes());
+        let bytes = bytes.map_err(TextParseError::MessagesRead)?.to_vec();
+
+        let mut messages = Vec::new();
+        let mut offset = 0;
+        while offset < bytes.len() {
+            let addr = MESSAGE_BOXES.begin + offset;
+            let Some(len) = bytes[offset..].iter().position(|&b| b == MESSAGE_TERMINATOR) else {
+                log::warn!("Ignoring {} bytes without a terminator after the last message box", bytes.len() - offset);
+                break;
+            };
+            let capacity = len + 1;
+            disasm
+                .mark_data_block(DataBlock { slice: SnesSlice::new(addr, capacity), kind: DataKind::Text })
+                .map_err(TextParseError::MessagesRead)?;
+            messages.push(Self { text: decode(&bytes[offset..offset + len]), addr, capacity });
+            offset += capacity;
+        }
+        Ok(messages)
+    }
+
+    /// Encodes the text followed by its terminator, failing if it's larger than the original text. Shorter text is
+    /// not padded.
+    pub fn encode(&self) -> Result<Vec<u8>, TextError> {
+        let mut bytes = encode(&self.text)?;
+        bytes.push(MESSAGE_TERMINATOR);
+        check_capacity(bytes.len(), self.capacity)?;
+        Ok(bytes)
+    }
+
+    /// Returns `false` if the text no longer fits in place.
+    pub fn fits(&self) -> bool {
+        encoded_len(&self.text).is_ok_and(|len| len < self.capacity)
+    }
+}
+
+// -------------------------------------------------------------------------------------------------
+
+pub fn decode_char(byte: u8) -> Option<char> {
+    match byte {
+        0x00..=0x19 => Some((b'A' + byte) as char),
+        0x1A => Some('!'),
+        0x1B => Some('.'),
+        0x1C => Some('-'),
+        0x1D => Some(','),
+        0x1E => Some('?'),
+        0x1F => Some(' '),
+        0x22..=0x2B => Some((b'0' + byte - 0x22) as char),
+        0x40..=0x59 => Some((b'a' + byte - 0x40) as char),
+        0x5A => Some('#'),
+        0x5B => Some('('),
+        0x5C => Some(')'),
+        0x5D => Some('\''),
+        _ => None,
+    }
+}
+
+pub fn encode_char(c: char) -> Option<u8> {
+    match c {
+        'A'..='Z' => Some(c as u8 - b'A'),
+        '!' => Some(0x1A),
+        '.' => Some(0x1B),
+        '-' => Some(0x1C),
+        ',' => Some(0x1D),
+        '?' => Some(0x1E),
+        ' ' => Some(0x1F),
+        '0'..='9' => Some(c as u8 - b'0' + 0x22),
+        'a'..='z' => Some(c as u8 - b'a' + 0x40),
+        '#' => Some(0x5A),
+        '(' => Some(0x5B),
+        ')' => Some(0x5C),
+        '\'' => Some(0x5D),
+        _ => None,
+    }
+}
+
+pub fn decode(bytes: &[u8]) -> String {
+    let mut text = String::with_capacity(bytes.len());
+    for &byte in bytes {
+        match decode_char(byte) {
+            Some(c) => text.push(c),
+            None => text.push_str(&format!("{{{byte:02X}}}")),
+        }
+    }
+    text
+}
+
+pub fn encode(text: &str) -> Result<Vec<u8>, TextError> {
+    let mut bytes = Vec::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c == '{' {
+            let escape: String = chars.by_ref().take(3).collect();
+            let byte = escape
+                .strip_suffix('}')
+                .filter(|hex| hex.len() == 2)
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+                .ok_or_else(|| TextError::Escape(format!("{{{escape}")))?;
+            bytes.push(byte);
+        } else {
+            bytes.push(encode_char(c).ok_or(TextError::UnknownCharacter(c))?);
+        }
+    }
+    Ok(bytes)
+}
+
+/// Number of bytes the text takes when encoded, without a terminator.
+pub fn encoded_len(text: &str) -> Result<usize, TextError> {
+    encode(text).map(|bytes| bytes.len())
+}
+
+fn check_capacity(needed: usize, capacity: usize) -> Result<(), TextError> {
+    if needed > capacity {
+        Err(TextError::Overflow { needed, capacity })
+    } else {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_codec_round_trip() {
+        let bytes = [0x07, 0x44, 0x4B, 0x4B, 0x4E, 0x1D, 0x1F, 0x16, 0x0E, 0x11, 0x0B, 0x03, 0x1A, 0x85, 0x5F];
+        let text = decode(&bytes);
+        assert_eq!(text, "Hello, WORLD!{85}{5F}");
+        assert_eq!(encode(&text).unwrap(), bytes);
+
+        let bytes = [0x5A, 0x23, 0x1F, 0x08, 0x06, 0x06, 0x18, 0x5D, 0x12, 0x1F, 0x5B, 0x22, 0x2B, 0x5C];
+        let text = decode(&bytes);
+        assert_eq!(text, "#1 IGGY'S (09)");
+        assert_eq!(encode(&text).unwrap(), bytes);
+
+        assert!(matches!(encode("ü"), Err(TextError::UnknownCharacter('ü'))));
+        assert!(matches!(encode("{8"), Err(TextError::Escape(_))));
+        assert!(matches!(encode("{XY}"), Err(TextError::Escape(_))));
+    }
+
+    #[test]
+    fn test_overflow() {
+        let mut message = TextBlock { text: "Hi".into(), addr: MESSAGE_BOXES.begin, capacity: 4 };
+        assert_eq!(message.encode().unwrap(), [0x07, 0x48, MESSAGE_TERMINATOR]);
+        message.text = "Hey!".into();
+        assert!(!message.fits());
+        assert!(matches!(message.encode(), Err(TextError::Overflow { needed: 5, capacity: 4 })));
+    }
+}
@@ -0,0 +1,193 @@
+use crate::{
+    disassembler::{binary_block::DataKind, RomDisassembly},
+    objects::map16::Tile8x8,
+    snes_utils::{addr::AddrSnes, rom_slice::SnesSlice},
+    text::{check_capacity, decode, encode, TextError, TextParseError},
+};
+
+// -------------------------------------------------------------------------------------------------
+
+pub const STRIPE_HEADER_SIZE: usize = 4;
+pub const STRIPE_IMAGE_TERMINATOR: u8 = 0xFF;
+
+// -------------------------------------------------------------------------------------------------
+
+/// A series of stripes uploaded to VRAM at once, terminated by `0xFF`.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct StripeImage {
+    pub stripes:  Vec<Stripe>,
+    pub addr:     AddrSnes,
+    /// Size of the original image in bytes, including its terminator.
+    pub capacity: usize,
+}
+
+/// `AAAAAAAA AAAAAAAA DRLLLLLL LLLLLLLL`
+///
+/// | Value               | Comment                                                       |
+/// |---------------------|---------------------------------------------------------------|
+/// | `AAAAAAAA AAAAAAAA` | VRAM address                                                  |
+/// | `D`                 | Direction: 0 - horizontal, 1 - vertical                       |
+/// | `R`                 | Repeat the single following tile instead of listing all tiles |
+/// | `LLLLLL LLLLLLLL`   | Size of data minus one, in bytes                              |
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Stripe {
+    pub vram_addr: u16,
+    pub vertical:  bool,
+    pub data:      StripeData,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum StripeData {
+    Tiles(Vec<Tile8x8>),
+    Repeat(Tile8x8, usize),
+}
+
+// -------------------------------------------------------------------------------------------------
+
+impl StripeImage {
+    /// Parses `count` images stored one after another.
+    pub(crate) fn parse_sequence(
+        disasm: &mut RomDisassembly, mut addr: AddrSnes, count: usize,
+    ) -> Result<Vec<Self>, TextParseError> {
+        let mut images = Vec::with_capacity(count);
+        for _ in 0..count {
+            let image = disasm.parse_and_mark_data(
+                addr,
+                DataKind::Text,
+                |e| TextParseError::StripeRead(addr, e),
+                |rom_view| {
+                    let bytes = rom_view.as_bytes()?;
+                    let image = Self::read_from(bytes, addr)?;
+                    let size = image.capacity;
+                    Ok((image, size))
+                },
+            )?;
+            addr += image.capacity;
+            images.push(image);
+        }
+        Ok(images)
+    }
+
+    /// Fails if the input ends before the terminator, or if a stripe's size is not a whole number of tiles, which
+    /// couldn't be encoded back.
+    pub fn read_from(input: &[u8], addr: AddrSnes) -> Result<Self, TextParseError> {
+        let mut stripes = Vec::new();
+        let mut in_it = input;
+        loop {
+            if *in_it.first().ok_or(TextParseError::StripeTerminator(addr))? == STRIPE_IMAGE_TERMINATOR {
+                let capacity = input.len() - in_it.len() + 1;
+                return Ok(Self { stripes, addr, capacity });
+            }
+            let header = take(&mut in_it, STRIPE_HEADER_SIZE, addr)?;
+            let vram_addr = u16::from_be_bytes([header[0], header[1]]);
+            let vertical = header[2] & 0x80 != 0;
+            let repeat = header[2] & 0x40 != 0;
+            let size = (u16::from_be_bytes([header[2] & 0x3F, header[3]]) as usize) + 1;
+            if size % 2 != 0 {
+                return Err(TextParseError::StripeSize(addr, size));
+            }
+
+            let data = if repeat {
+                let tile = take(&mut in_it, 2, addr)?;
+                StripeData::Repeat(Tile8x8(u16::from_le_bytes([tile[0], tile[1]])), size / 2)
+            } else {
+                let tiles = take(&mut in_it, size, addr)?;
+                StripeData::Tiles(tiles.chunks(2).map(|t| Tile8x8(u16::from_le_bytes([t[0], t[1]]))).collect())
+            };
+            stripes.push(Stripe { vram_addr, vertical, data });
+        }
+    }
+
+    /// Encodes the stripes followed by the terminator, failing if the image is larger than the original one.
+    pub fn encode(&self) -> Result<Vec<u8>, TextError> {
+        let mut bytes = Vec::with_capacity(self.capacity);
+        for stripe in self.stripes.iter() {
+            let (repeat, size) = match &stripe.data {
+                StripeData::Tiles(tiles) => (0, tiles.len() * 2),
+                StripeData::Repeat(_, count) => (0x40, count * 2),
+            };
+            let [size_hi, size_lo] = (size.max(1) as u16 - 1).to_be_bytes();
+            bytes.extend(stripe.vram_addr.to_be_bytes());
+            bytes.extend([((stripe.vertical as u8) << 7) | repeat | (size_hi & 0x3F), size_lo]);
+            match &stripe.data {
+                StripeData::Tiles(tiles) => bytes.extend(tiles.iter().flat_map(|tile| tile.0.to_le_bytes())),
+                StripeData::Repeat(tile, _) => bytes.extend(tile.0.to_le_bytes()),
+            }
+        }
+        bytes.push(STRIPE_IMAGE_TERMINATOR);
+        check_capacity(bytes.len(), self.capacity)?;
+        Ok(bytes)
+    }
+
+    /// Text of all stripes, one per line.
+    pub fn text(&self) -> String {
+        self.stripes.iter().map(Stripe::text).collect::<Vec<_>>().join("\n")
+    }
+
+    pub fn slice(&self) -> SnesSlice {
+        SnesSlice::new(self.addr, self.capacity)
+    }
+}
+
+impl Stripe {
+    pub fn text(&self) -> String {
+        match &self.data {
+            StripeData::Tiles(tiles) => decode(&tiles.iter().map(|tile| tile.0 as u8).collect::<Vec<_>>()),
+            StripeData::Repeat(tile, count) => decode(&[tile.0 as u8]).repeat(*count),
+        }
+    }
+
+    /// Replaces tile numbers with the encoded text. Properties of existing tiles are kept, new tiles get properties of
+    /// the last one.
+    pub fn set_text(&mut self, text: &str) -> Result<(), TextError> {
+        let bytes = encode(text)?;
+        let mut tiles = match &self.data {
+            StripeData::Tiles(tiles) => tiles.clone(),
+            StripeData::Repeat(tile, count) => vec![*tile; *count],
+        };
+        let last_props = tiles.last().map_or(0, |tile| tile.0 & 0xFF00);
+        tiles.resize(bytes.len(), Tile8x8(last_props));
+        for (tile, byte) in tiles.iter_mut().zip(bytes) {
+            tile.0 = (tile.0 & 0xFF00) | byte as u16;
+        }
+        self.data = StripeData::Tiles(tiles);
+        Ok(())
+    }
+}
+
+/// Splits off the first `len` bytes of the input.
+fn take<'a>(input: &mut &'a [u8], len: usize, addr: AddrSnes) -> Result<&'a [u8], TextParseError> {
+    let bytes = input.get(..len).ok_or(TextParseError::StripeTerminator(addr))?;
+    *input = &input[len..];
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stripe_image_round_trip() {
+        let hi = [0x51, 0x23, 0x00, 0x05, 0x07, 0x39, 0x48, 0x39, 0x1A, 0x39];
+        let spaces = [0x51, 0x43, 0x40, 0x09, 0x1F, 0x38];
+        let bytes = [&hi[..], &spaces, &[STRIPE_IMAGE_TERMINATOR]].concat();
+        let mut image = StripeImage::read_from(&bytes, AddrSnes(0x0CBE85)).unwrap();
+        assert_eq!(image.capacity, bytes.len());
+        assert_eq!(image.text(), "Hi!\n     ");
+        assert_eq!(image.encode().unwrap(), bytes);
+
+        image.stripes[0].set_text("Ho").unwrap();
+        let encoded = image.encode().unwrap();
+        assert_eq!(&encoded[..8], &[0x51, 0x23, 0x00, 0x03, 0x07, 0x39, 0x4E, 0x39]);
+
+        image.stripes[0].set_text("Hello").unwrap();
+        assert!(matches!(image.encode(), Err(TextError::Overflow { needed: 21, capacity: 17 })));
+        assert!(matches!(
+            StripeImage::read_from(&bytes[..bytes.len() - 1], AddrSnes(0x0CBE85)),
+            Err(TextParseError::StripeTerminator(_))
+        ));
+
+        let odd = [0x51, 0x23, 0x00, 0x02, 0x07, 0x39, 0x48, STRIPE_IMAGE_TERMINATOR];
+        assert!(matches!(StripeImage::read_from(&odd, AddrSnes(0x0CBE85)), Err(TextParseError::StripeSize(_, 3))));
+    }
+}
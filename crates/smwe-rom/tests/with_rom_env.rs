@@ -1,11 +1,240 @@
-use std::env;
+use std::{env, ffi::OsString};
 
-use smwe_rom::SmwRom;
+use smwe_render::color::Abgr1555;
+use smwe_rom::{
+    disassembler::free_space::{EXPANDED_AREA_START, FREE_SPACE_BANKS},
+    level::{
+        sprite_layer::SpriteInstance,
+        BackgroundData,
+        Layer2Data,
+        LevelDataType,
+        LevelSaveError,
+        LAYER1_POINTERS,
+        LAYER2_POINTERS,
+    },
+    overworld::{level_to_translevel, translevel_to_level, SPRITE_COUNT},
+    snes_utils::{
+        addr::{AddrPc, AddrSnes},
+        rom::Rom,
+    },
+    SmwRom,
+};
+
+fn rom_path() -> OsString {
+    let rom_path = env::var_os("ROM_PATH").expect("ROM_PATH not set");
+    assert!(std::fs::metadata(&rom_path).expect("ROM_PATH invalid").is_file());
+    rom_path
+}
 
 #[test]
 #[ignore]
 fn test_with_rom_env() {
-    let rom_path = env::var_os("ROM_PATH").expect("ROM_PATH not set");
-    assert!(std::fs::metadata(&rom_path).expect("ROM_PATH invalid").is_file());
-    SmwRom::from_file(rom_path).expect("Rom parse error encountered");
+    SmwRom::from_file(rom_path()).expect("Rom parse error encountered");
+}
+
+#[test]
+#[ignore]
+fn test_parsing_overworld() {
+    let smw_rom = SmwRom::from_file(rom_path()).expect("Rom parse error encountered");
+    let overworld = smw_rom.overworld.expect("Overworld not parsed");
+    assert_eq!(overworld.sprites.len(), SPRITE_COUNT);
+    let translevels = overworld.layer1.level_tiles().map(|tile| tile.translevel).collect::<Vec<_>>();
+    assert!(!translevels.is_empty(), "No level tiles found on the overworld");
+    assert!(
+        translevels.iter().all(|&translevel| level_to_translevel(translevel_to_level(translevel)) == Some(translevel)),
+        "Level tiles lead to levels that cannot be entered from the overworld"
+    );
+}
+
+#[test]
+#[ignore]
+fn test_writing_unchanged_overworld_events_keeps_rom_intact() {
+    let mut smw_rom = SmwRom::from_file(rom_path()).expect("Rom parse error encountered");
+    let overworld = smw_rom.overworld.take().expect("Overworld not parsed");
+    let original_bytes = smw_rom.disassembly.rom_bytes().to_vec();
+    overworld.events.write_to_rom(&mut smw_rom.disassembly).expect("Overworld events save error encountered");
+    assert!(original_bytes == smw_rom.disassembly.rom_bytes(), "Writing unchanged overworld events modified the ROM");
+}
+
+#[test]
+#[ignore]
+fn test_decoding_level_names() {
+    let smw_rom = SmwRom::from_file(rom_path()).expect("Rom parse error encountered");
+    let level_names = &smw_rom.text.expect("Text not parsed").level_names;
+    for translevel in 0..level_names.names.len() {
+        let name = level_names.name(translevel).unwrap_or_default();
+        assert!(!name.contains('{'), "Name of translevel {translevel:02X} has unknown characters: {name:?}");
+    }
+    let yoshis_island_1 = level_to_translevel(0x105).unwrap() as usize;
+    assert_eq!(level_names.name(yoshis_island_1).as_deref().map(str::trim_end), Some("YOSHI'S ISLAND 1"));
+}
+
+#[test]
+#[ignore]
+fn test_saving_unchanged_levels_keeps_rom_intact() {
+    let mut smw_rom = SmwRom::from_file(rom_path()).expect("Rom parse error encountered");
+    let original_bytes = smw_rom.disassembly.rom_bytes().to_vec();
+    smw_rom.save_levels().expect("Level save error encountered");
+    assert!(original_bytes == smw_rom.disassembly.rom_bytes(), "Saving unchanged levels modified the ROM");
+}
+
+#[test]
+#[ignore]
+fn test_adding_sprite_to_vanilla_level() {
+    let mut smw_rom = SmwRom::from_file(rom_path()).expect("Rom parse error encountered");
+    assert!(smw_rom.lunar_magic.is_none(), "Test requires a ROM not saved with Lunar Magic");
+    smw_rom.expand(0x100000).expect("Expansion error encountered");
+    let original_bytes = smw_rom.disassembly.rom_bytes().to_vec();
+
+    let level_num = 0x105;
+    let sprite = SpriteInstance::new(0x0D, 0, (4, 0x10), 0).expect("Sprite error encountered");
+    let sprites = &mut smw_rom.levels[level_num].sprite_layer.sprites;
+    sprites.insert(0, sprite);
+    assert!(
+        matches!(smw_rom.save_levels(), Err(LevelSaveError::FixedBank(0x105, LevelDataType::Sprites, 0x07))),
+        "Moved sprite data out of its fixed bank"
+    );
+    assert!(original_bytes == smw_rom.disassembly.rom_bytes(), "Failing to save sprite data modified the ROM");
+
+    // The same number of sprites still fits in place.
+    let sprites = &mut smw_rom.levels[level_num].sprite_layer.sprites;
+    sprites.pop();
+    let expected_sprites = sprites.clone();
+    smw_rom.save_levels().expect("Level save error encountered");
+    let saved_path = env::temp_dir().join("smwe_test_adding_sprite.smc");
+    smw_rom.save_to_file(&saved_path).expect("Save error encountered");
+
+    let saved_rom = SmwRom::from_file(&saved_path).expect("Saved ROM parse error encountered");
+    std::fs::remove_file(&saved_path).ok();
+    assert_eq!(saved_rom.levels[level_num].sprite_layer.sprites, expected_sprites, "Sprites differ after saving");
+}
+
+#[test]
+#[ignore]
+fn test_object_layers_round_trip() {
+    let smw_rom = SmwRom::from_file(rom_path()).expect("Rom parse error encountered");
+    let rom_bytes = smw_rom.disassembly.rom_bytes();
+    let level_addr = |pointers: AddrSnes, level_num: usize| {
+        let pointers_addr = AddrPc::try_from_lorom(pointers).unwrap().as_index();
+        let ptr = &rom_bytes[pointers_addr + 3 * level_num..];
+        AddrSnes(u32::from_le_bytes([ptr[0], ptr[1], ptr[2], 0]))
+    };
+    for (level_num, level) in smw_rom.levels.iter().enumerate() {
+        let serialized = level.serialize().expect("Level encode error encountered");
+
+        let layer1_addr = AddrPc::try_from_lorom(level_addr(LAYER1_POINTERS.begin, level_num)).unwrap().as_index();
+        assert_eq!(
+            &rom_bytes[layer1_addr..layer1_addr + serialized.layer1.len()],
+            serialized.layer1.as_slice(),
+            "Layer1 of level {level_num:X} differs after encoding"
+        );
+
+        if let Layer2Data::Objects(..) = level.layer2 {
+            let layer2_addr = AddrPc::try_from_lorom(level_addr(LAYER2_POINTERS.begin, level_num)).unwrap().as_index();
+            assert_eq!(
+                &rom_bytes[layer2_addr..layer2_addr + serialized.layer2.len()],
+                serialized.layer2.as_slice(),
+                "Layer2 of level {level_num:X} differs after encoding"
+            );
+        }
+    }
+}
+
+#[test]
+#[ignore]
+fn test_backgrounds_round_trip() {
+    let smw_rom = SmwRom::from_file(rom_path()).expect("Rom parse error encountered");
+    for (level_num, level) in smw_rom.levels.iter().enumerate() {
+        if let Layer2Data::Background(background) = &level.layer2 {
+            let (decoded, _) = BackgroundData::read_from(&background.to_bytes(), background.compression)
+                .expect("Background decode error");
+            assert!(decoded == *background, "Layer2 background of level {level_num:X} differs after encoding");
+        }
+    }
+}
+
+#[test]
+#[ignore]
+fn test_allocating_and_freeing_space() {
+    let mut smw_rom = SmwRom::from_file(rom_path()).expect("Rom parse error encountered");
+    assert!(
+        smw_rom.disassembly.allocate(0x20, FREE_SPACE_BANKS).is_err(),
+        "Allocated space in a ROM that hasn't been expanded"
+    );
+
+    smw_rom.expand(0x100000).expect("Expansion error encountered");
+    let disasm = &mut smw_rom.disassembly;
+    let original_bytes = disasm.rom_bytes().to_vec();
+    let original_chunk_count = disasm.chunks.len();
+
+    let first = disasm.allocate(0x20, FREE_SPACE_BANKS).expect("Allocation error encountered");
+    let second = disasm.allocate(0x20, FREE_SPACE_BANKS).expect("Allocation error encountered");
+    assert!(AddrPc::try_from_lorom(first).unwrap() >= EXPANDED_AREA_START, "Allocated space in the original ROM");
+    assert_ne!(first, second, "Allocated the same space twice");
+    assert!(disasm.free(first).expect("Free error encountered"));
+    assert_eq!(disasm.allocate(0x20, FREE_SPACE_BANKS).expect("Allocation error encountered"), first);
+    assert!(!disasm.free(first + 1).expect("Free error encountered"), "Freed unprotected data");
+    assert!(disasm.free(first).expect("Free error encountered"));
+    assert!(disasm.free(second).expect("Free error encountered"));
+
+    assert!(original_bytes == disasm.rom_bytes(), "Freeing space did not restore the ROM");
+    assert_eq!(original_chunk_count, disasm.chunks.len(), "Freeing space did not merge chunks");
+}
+
+#[test]
+#[ignore]
+fn test_writing_unchanged_samples_keeps_rom_intact() {
+    let mut smw_rom = SmwRom::from_file(rom_path()).expect("Rom parse error encountered");
+    let audio = smw_rom.audio.take().expect("Music and samples not parsed");
+    assert!(!audio.samples.samples.is_empty(), "No samples found in the sample directory");
+    assert!(audio.music_banks.iter().all(|bank| !bank.songs.is_empty()), "No songs found in a music bank");
+
+    let original_bytes = smw_rom.disassembly.rom_bytes().to_vec();
+    audio.write_to_rom(&mut smw_rom.disassembly).expect("Sample write error encountered");
+    assert!(original_bytes == smw_rom.disassembly.rom_bytes(), "Writing unchanged samples modified the ROM");
+}
+
+#[test]
+#[ignore]
+fn test_gfx_files_round_trip() {
+    let smw_rom = SmwRom::from_file(rom_path()).expect("Rom parse error encountered");
+    for (file_num, file) in smw_rom.gfx.files.iter().enumerate() {
+        let (decompressed, _) = file.compression.decompress(&file.to_bytes()).expect("GFX decompression error");
+        assert!(decompressed == file.to_uncompressed_bytes(), "GFX file {file_num:02X} differs after encoding");
+    }
+}
+
+#[test]
+#[ignore]
+fn test_writing_unchanged_gfx_keeps_rom_intact() {
+    let mut smw_rom = SmwRom::from_file(rom_path()).expect("Rom parse error encountered");
+    let original_bytes = smw_rom.disassembly.rom_bytes().to_vec();
+    smw_rom
+        .gfx
+        .write_to_rom(&mut smw_rom.disassembly, smw_rom.lunar_magic.as_mut())
+        .expect("GFX save error encountered");
+    assert!(original_bytes == smw_rom.disassembly.rom_bytes(), "Writing unchanged GFX modified the ROM");
+}
+
+#[test]
+#[ignore]
+fn test_level_palette_round_trip() {
+    let mut smw_rom = SmwRom::from_file(rom_path()).expect("Rom parse error encountered");
+    let header = smw_rom.levels[0x105].primary_header;
+    let palettes = &mut smw_rom.gfx.color_palettes;
+    let mut palette = palettes.get_level_palette(&header).expect("Level palette error encountered");
+    palette.background[0] = Abgr1555(0x1234);
+    palette.sprite[11] = Abgr1555(0x4321);
+    palettes.set_level_palette(&header, &palette).expect("Level palette error encountered");
+    smw_rom
+        .gfx
+        .write_to_rom(&mut smw_rom.disassembly, smw_rom.lunar_magic.as_mut())
+        .expect("GFX save error encountered");
+
+    let rom = Rom::new(smw_rom.disassembly.rom_bytes().to_vec()).expect("Rom error encountered");
+    let reparsed = SmwRom::from_rom(rom).expect("Rom parse error encountered");
+    let reparsed_palette =
+        reparsed.gfx.color_palettes.get_level_palette(&header).expect("Level palette error encountered");
+    assert_eq!(reparsed_palette.background[0].0, 0x1234);
+    assert_eq!(reparsed_palette.sprite[11].0, 0x4321);
 }
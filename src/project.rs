@@ -2,22 +2,88 @@ use std::{cell::RefCell, path::Path, rc::Rc, sync::Arc};
 
 use egui::Id;
 use smwe_emu::rom::Rom;
+use smwe_rom::{
+    internal_header::{GameVersion, RomInternalHeader, HEADER_LOROM},
+    patch::PatchFormat,
+    snes_utils::rom::{check_lorom_expansion, checksum, CopierHeader, RomError},
+};
 
 #[derive(Debug)]
 pub struct Project {
     pub title: String,
     pub rom:   Arc<Rom>,
+
+    /// Contents of the base ROM file without the copier header, against which patches are made.
+    pub base_rom:      Arc<[u8]>,
+    /// Copier header of the base ROM file, written back when saving the ROM.
+    pub copier_header: Option<CopierHeader>,
 }
 
 pub type ProjectRef = Rc<RefCell<Project>>;
 
 impl Project {
     pub fn new(rom_path: impl AsRef<Path>) -> anyhow::Result<Self> {
-        // TODO: check smc/sfc
-        let mut rom = Rom::new(std::fs::read(&rom_path)?[0x200..].to_vec());
-        rom.load_symbols(include_str!("../symbols/SMW_U.sym"));
+        let (copier_header, bytes) = CopierHeader::split_from(std::fs::read(&rom_path)?)?;
+        let base_rom: Arc<[u8]> = Arc::from(bytes.as_slice());
+        let internal_header = RomInternalHeader::parse(&smwe_rom::snes_utils::rom::Rom(Arc::clone(&base_rom)))?;
+        let game_version = internal_header.game_version()?;
+        log::info!("Detected game version: {game_version:?}");
+
+        let mut rom = Rom::new(bytes);
+        rom.load_symbols(Self::symbols(game_version));
+
+        Ok(Self { title: String::from("Test Project"), rom: Arc::new(rom), base_rom, copier_header })
+    }
+
+    /// Saves the ROM with an updated checksum, in the same form as the base ROM file unless `copier_header` was
+    /// changed.
+    pub fn save_rom(&mut self, rom_path: impl AsRef<Path>) -> anyhow::Result<()> {
+        let rom = Arc::make_mut(&mut self.rom);
+        Self::update_checksum(rom)?;
+        std::fs::write(rom_path, CopierHeader::join(self.copier_header.as_ref(), rom.as_slice()))?;
+        Ok(())
+    }
+
+    /// Expands the ROM to one of the [`LOROM_EXPANSION_SIZES`], updating its internal header, so that the emulator
+    /// sees the new banks.
+    ///
+    /// [`LOROM_EXPANSION_SIZES`]: smwe_rom::snes_utils::rom::LOROM_EXPANSION_SIZES
+    pub fn expand_rom(&mut self, new_size: usize) -> Result<(), RomError> {
+        let rom = Arc::make_mut(&mut self.rom);
+        check_lorom_expansion(rom.as_slice().len(), new_size)?;
+        rom.resize(new_size);
+        RomInternalHeader::write_rom_size_into(Self::internal_header(rom)?, new_size);
+        Self::update_checksum(rom)
+    }
+
+    /// Saves the changes made to the base ROM as a patch, with an updated checksum.
+    pub fn export_patch(&mut self, format: PatchFormat, patch_path: impl AsRef<Path>) -> anyhow::Result<()> {
+        let rom = Arc::make_mut(&mut self.rom);
+        Self::update_checksum(rom)?;
+        std::fs::write(patch_path, format.create(&self.base_rom, rom.as_slice())?)?;
+        Ok(())
+    }
+
+    fn update_checksum(rom: &mut Rom) -> Result<(), RomError> {
+        // The checksum covers its own bytes, so they're set to a valid pair before summing up.
+        RomInternalHeader::write_checksum_into(Self::internal_header(rom)?, 0);
+        let checksum = checksum(rom.as_slice());
+        RomInternalHeader::write_checksum_into(Self::internal_header(rom)?, checksum);
+        Ok(())
+    }
+
+    fn internal_header(rom: &mut Rom) -> Result<&mut [u8], RomError> {
+        rom.internal_header_mut().ok_or(RomError::SlicePc(HEADER_LOROM))
+    }
 
-        Ok(Self { title: String::from("Test Project"), rom: Arc::new(rom) })
+    fn symbols(game_version: GameVersion) -> &'static str {
+        match game_version {
+            GameVersion::Japan => include_str!("../symbols/SMW_J.sym"),
+            GameVersion::NorthAmerica => include_str!("../symbols/SMW_U.sym"),
+            GameVersion::Europe1_0 => include_str!("../symbols/SMW_E0.sym"),
+            GameVersion::Europe1_1 => include_str!("../symbols/SMW_E1.sym"),
+            GameVersion::AllStars => include_str!("../symbols/SMW_SS.sym"),
+        }
     }
 
     pub fn rom_id() -> Id {